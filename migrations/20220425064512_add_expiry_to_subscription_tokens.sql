@@ -0,0 +1,7 @@
+-- Add migration script here
+ALTER TABLE subscription_tokens
+    ADD COLUMN created_at timestamptz NOT NULL DEFAULT now(),
+    ADD COLUMN expires_at timestamptz NOT NULL DEFAULT now() + interval '24 hours',
+    ADD COLUMN used_at timestamptz NULL;
+-- New tokens must state their expiry explicitly
+ALTER TABLE subscription_tokens ALTER COLUMN expires_at DROP DEFAULT;
@@ -0,0 +1,5 @@
+-- Add migration script here
+ALTER TABLE newsletter_issues
+    ADD COLUMN status TEXT NOT NULL DEFAULT 'published',
+    ADD COLUMN scheduled_for timestamptz NULL;
+ALTER TABLE newsletter_issues ALTER COLUMN status DROP DEFAULT;
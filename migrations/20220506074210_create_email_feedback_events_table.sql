@@ -0,0 +1,10 @@
+-- Add migration script here
+CREATE TABLE email_feedback_events(
+    id uuid PRIMARY KEY,
+    email TEXT NOT NULL,
+    record_type TEXT NOT NULL,
+    event_type TEXT NOT NULL,
+    description TEXT NULL,
+    received_at timestamptz NOT NULL
+);
+CREATE INDEX email_feedback_events_email_idx ON email_feedback_events (email);
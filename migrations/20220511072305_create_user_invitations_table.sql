@@ -0,0 +1,11 @@
+-- Add migration script here
+CREATE TABLE user_invitations(
+    invitation_token TEXT NOT NULL,
+    email TEXT NOT NULL,
+    role TEXT NOT NULL,
+    invited_by uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    accepted_at timestamptz NULL,
+    PRIMARY KEY (invitation_token)
+);
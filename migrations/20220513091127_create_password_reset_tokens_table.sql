@@ -0,0 +1,9 @@
+-- Add migration script here
+CREATE TABLE password_reset_tokens(
+    password_reset_token TEXT NOT NULL,
+    user_id uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    expires_at timestamptz NOT NULL,
+    used_at timestamptz NULL,
+    PRIMARY KEY (password_reset_token)
+);
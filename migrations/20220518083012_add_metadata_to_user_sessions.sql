@@ -0,0 +1,4 @@
+-- Add migration script here
+ALTER TABLE user_sessions ADD COLUMN user_agent TEXT NULL;
+ALTER TABLE user_sessions ADD COLUMN ip_address TEXT NULL;
+ALTER TABLE user_sessions ADD COLUMN last_seen_at timestamptz NOT NULL DEFAULT now();
@@ -0,0 +1,35 @@
+-- Add migration script here
+CREATE TABLE lists(
+    list_id uuid NOT NULL,
+    slug TEXT NOT NULL UNIQUE,
+    name TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id)
+);
+CREATE TABLE list_memberships(
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    status TEXT NOT NULL,
+    subscribed_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (list_id, subscriber_id)
+);
+CREATE INDEX list_memberships_subscriber_id_idx ON list_memberships (subscriber_id);
+CREATE TABLE newsletter_issue_lists(
+    newsletter_issue_id uuid NOT NULL REFERENCES newsletter_issues (newsletter_issue_id),
+    list_id uuid NOT NULL REFERENCES lists (list_id),
+    PRIMARY KEY (newsletter_issue_id, list_id)
+);
+
+-- Everything sent so far went to a single list: it becomes the default one.
+INSERT INTO lists (list_id, slug, name)
+VALUES ('5c7a0f53-0f2e-4d39-9d3b-6a0e3c1f8b21', 'newsletter', 'Newsletter');
+INSERT INTO list_memberships (list_id, subscriber_id, status, subscribed_at)
+SELECT
+    '5c7a0f53-0f2e-4d39-9d3b-6a0e3c1f8b21',
+    id,
+    CASE WHEN status IN ('pending_confirmation', 'confirmed') THEN status ELSE 'unsubscribed' END,
+    subscribed_at
+FROM subscriptions;
+INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+SELECT newsletter_issue_id, '5c7a0f53-0f2e-4d39-9d3b-6a0e3c1f8b21'
+FROM newsletter_issues;
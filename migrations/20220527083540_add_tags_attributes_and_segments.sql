@@ -0,0 +1,38 @@
+-- Add migration script here
+ALTER TABLE subscriptions
+    ADD COLUMN tags TEXT[] NOT NULL DEFAULT '{}',
+    ADD COLUMN attributes JSONB NOT NULL DEFAULT '{}';
+
+CREATE TABLE segments(
+    segment_id uuid NOT NULL,
+    name TEXT NOT NULL UNIQUE,
+    -- As typed by the admin, `conditions` holds its parsed form.
+    expression TEXT NOT NULL,
+    conditions JSONB NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (segment_id)
+);
+
+ALTER TABLE newsletter_issues
+    ADD COLUMN segment_id uuid NULL REFERENCES segments (segment_id);
+
+-- Every condition of the segment has to hold; see `SegmentCondition` for their shape.
+CREATE FUNCTION subscriber_matches_segment(tags TEXT[], attributes JSONB, conditions JSONB)
+RETURNS BOOLEAN
+LANGUAGE SQL IMMUTABLE
+AS $$
+    SELECT NOT EXISTS (
+        SELECT 1
+        FROM jsonb_array_elements(conditions) AS c
+        WHERE NOT COALESCE(
+            CASE c->>'kind'
+                WHEN 'has_tag' THEN (c->>'value') = ANY(tags)
+                WHEN 'lacks_tag' THEN NOT ((c->>'value') = ANY(tags))
+                WHEN 'attribute_equals' THEN attributes->>(c->>'key') = c->>'value'
+                WHEN 'attribute_not_equals' THEN attributes->>(c->>'key') IS DISTINCT FROM c->>'value'
+                WHEN 'has_attribute' THEN attributes ? (c->>'key')
+            END,
+            false
+        )
+    )
+$$;
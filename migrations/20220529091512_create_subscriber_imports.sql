@@ -0,0 +1,20 @@
+-- Add migration script here
+-- How consent was given by subscribers who were imported as confirmed.
+ALTER TABLE subscriptions ADD COLUMN consent_source TEXT NULL;
+
+-- Uploads are kept between the dry run and the actual import.
+CREATE TABLE subscriber_imports(
+    import_id uuid NOT NULL,
+    uploaded_by uuid NOT NULL REFERENCES users (user_id) ON DELETE CASCADE,
+    csv TEXT NOT NULL,
+    created_at timestamptz NOT NULL DEFAULT now(),
+    imported_at timestamptz NULL,
+    PRIMARY KEY (import_id)
+);
+
+CREATE TABLE confirmation_email_queue(
+    subscriber_id uuid NOT NULL REFERENCES subscriptions (id) ON DELETE CASCADE,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (subscriber_id)
+);
@@ -0,0 +1,10 @@
+-- Add migration script here
+-- Reset emails are sent in the background, so that answering the request takes as long
+-- for unknown addresses as for known ones.
+CREATE TABLE password_reset_email_queue(
+    password_reset_token TEXT NOT NULL
+        REFERENCES password_reset_tokens (password_reset_token) ON DELETE CASCADE,
+    n_retries SMALLINT NOT NULL DEFAULT 0,
+    execute_after timestamptz NOT NULL DEFAULT now(),
+    PRIMARY KEY (password_reset_token)
+);
@@ -0,0 +1,15 @@
+-- Add migration script here
+-- A confirmation link only confirms the list it was sent for.
+ALTER TABLE subscription_tokens ADD COLUMN list_id uuid NULL REFERENCES lists (list_id);
+-- Links sent so far go to the list the subscriber joined last, or the default one.
+UPDATE subscription_tokens t
+SET list_id = COALESCE(
+    (
+        SELECT m.list_id FROM list_memberships m
+        WHERE m.subscriber_id = t.subscriber_id AND m.status = 'pending_confirmation'
+        ORDER BY m.subscribed_at DESC
+        LIMIT 1
+    ),
+    '5c7a0f53-0f2e-4d39-9d3b-6a0e3c1f8b21'
+);
+ALTER TABLE subscription_tokens ALTER COLUMN list_id SET NOT NULL;
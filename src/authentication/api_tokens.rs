@@ -0,0 +1,120 @@
+use super::Role;
+use anyhow::Context;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::thread_rng;
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+// Makes leaked tokens easy to recognise, e.g. by secret scanners.
+const TOKEN_PREFIX: &str = "nl_";
+const TOKEN_LENGTH: usize = 40;
+
+// Enough of the token to tell tokens apart on the admin page, without revealing it.
+fn display_prefix(token: &str) -> String {
+    format!("{}…", &token[..TOKEN_PREFIX.len() + 6])
+}
+
+// Tokens are random and long, so unlike passwords a fast hash is sufficient.
+fn hash_api_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.trim().as_bytes()))
+}
+
+// The token is only ever shown once: the database keeps its hash.
+#[tracing::instrument(name = "Create an API token", skip(executor, name))]
+pub async fn create_api_token(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    name: &str,
+) -> Result<Secret<String>, anyhow::Error> {
+    let token = format!(
+        "{}{}",
+        TOKEN_PREFIX,
+        Alphanumeric.sample_string(&mut thread_rng(), TOKEN_LENGTH)
+    );
+    sqlx::query!(
+        r#"
+        INSERT INTO api_tokens (api_token_id, user_id, name, token_prefix, token_hash)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        Uuid::new_v4(),
+        user_id,
+        name,
+        display_prefix(&token),
+        hash_api_token(&token),
+    )
+    .execute(executor)
+    .await
+    .context("Failed to store the API token.")?;
+    Ok(Secret::new(token))
+}
+
+#[tracing::instrument(name = "Revoke an API token", skip(executor))]
+pub async fn revoke_api_token(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    api_token_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE api_tokens
+        SET revoked_at = now()
+        WHERE user_id = $1 AND api_token_id = $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        api_token_id,
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_revoked > 0)
+}
+
+// Returns the owner of a valid token along with their current role, so that role
+// changes apply to API calls straight away.
+#[tracing::instrument(name = "Authenticate an API token", skip(token, pool))]
+pub async fn authenticate_api_token(
+    token: &Secret<String>,
+    pool: &PgPool,
+) -> Result<Option<(Uuid, Role)>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH used_token AS (
+            UPDATE api_tokens
+            SET last_used_at = now()
+            WHERE token_hash = $1 AND revoked_at IS NULL
+            RETURNING user_id
+        )
+        SELECT u.user_id AS "user_id!", u.role AS "role!"
+        FROM users u
+        JOIN used_token t ON t.user_id = u.user_id
+        "#,
+        hash_api_token(token.expose_secret()),
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to look up the API token.")?;
+    row.map(|r| {
+        let role = Role::parse(&r.role).map_err(anyhow::Error::msg)?;
+        Ok((r.user_id, role))
+    })
+    .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{display_prefix, hash_api_token};
+
+    #[test]
+    fn the_display_prefix_does_not_reveal_the_token() {
+        let token = "nl_abcdef0123456789abcdef0123456789abcdef01";
+        assert_eq!(display_prefix(token), "nl_abcdef…");
+    }
+
+    #[test]
+    fn surrounding_whitespace_does_not_change_the_hash() {
+        assert_eq!(hash_api_token(" nl_token\n"), hash_api_token("nl_token"));
+        assert_ne!(hash_api_token("nl_token"), hash_api_token("nl_other"));
+    }
+}
@@ -1,11 +1,17 @@
+use super::{authenticate_api_token, Role};
+use crate::routes::ApiError;
 use crate::session_state::TypedSession;
 use crate::util::{e500, see_other};
 use actix_web::body::MessageBody;
 use actix_web::dev::{ServiceRequest, ServiceResponse};
 use actix_web::error::InternalError;
-use actix_web::FromRequest;
+use actix_web::http::header::AUTHORIZATION;
 use actix_web::HttpMessage;
+use actix_web::{web, FromRequest};
 use actix_web_lab::middleware::Next;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
 use std::ops::Deref;
 use uuid::Uuid;
 
@@ -34,15 +40,95 @@ pub async fn reject_anonymous_users(
         TypedSession::from_request(http_request, payload).await
     }?;
 
-    match session.get_user_id().map_err(e500)? {
-        Some(user_id) => {
+    let (user_id, session_id) = match (
+        session.get_user_id().map_err(e500)?,
+        session.get_session_id().map_err(e500)?,
+    ) {
+        (Some(user_id), Some(session_id)) => (user_id, session_id),
+        _ => {
+            let response = see_other("/login");
+            let e = anyhow::anyhow!("The user has not logged in");
+            return Err(InternalError::from_response(e, response).into());
+        }
+    };
+
+    // The role is looked up on every request so that role changes, revoked sessions and
+    // removed accounts take effect without waiting for the session to expire. The same
+    // query keeps track of when the session was last used.
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not configured"))?;
+    match get_role(user_id, session_id, pool).await.map_err(e500)? {
+        Some(role) => {
             req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
             next.call(req).await
         }
         None => {
+            session.purge();
             let response = see_other("/login");
-            let e = anyhow::anyhow!("The user has not logged in");
+            let e = anyhow::anyhow!("The session has been revoked");
             Err(InternalError::from_response(e, response).into())
         }
     }
 }
+
+// Guards the JSON API: requests authenticate with `Authorization: Bearer <API token>`
+// instead of a session cookie.
+pub async fn reject_invalid_api_tokens(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, actix_web::Error> {
+    let token = req
+        .headers()
+        .get(AUTHORIZATION)
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Bearer "))
+        .map(|t| Secret::new(t.to_string()))
+        .ok_or(ApiError::Unauthorized)?;
+    let pool = req
+        .app_data::<web::Data<PgPool>>()
+        .ok_or_else(|| e500("The database pool is not configured"))?;
+    match authenticate_api_token(&token, pool)
+        .await
+        .map_err(ApiError::Unexpected)?
+    {
+        Some((user_id, role)) => {
+            req.extensions_mut().insert(UserId(user_id));
+            req.extensions_mut().insert(role);
+            next.call(req).await
+        }
+        None => Err(ApiError::Unauthorized.into()),
+    }
+}
+
+#[tracing::instrument(name = "Get user role", skip(pool))]
+async fn get_role(
+    user_id: Uuid,
+    session_id: Uuid,
+    pool: &PgPool,
+) -> Result<Option<Role>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        WITH active_session AS (
+            UPDATE user_sessions
+            SET last_seen_at = now()
+            WHERE
+                user_id = $1 AND
+                session_id = $2 AND
+                revoked_at IS NULL
+            RETURNING user_id
+        )
+        SELECT u.role AS "role!"
+        FROM users u
+        JOIN active_session s ON s.user_id = u.user_id
+        "#,
+        user_id,
+        session_id,
+    )
+    .fetch_optional(pool)
+    .await
+    .context("Failed to retrieve the user role.")?;
+    row.map(|r| Role::parse(&r.role).map_err(anyhow::Error::msg))
+        .transpose()
+}
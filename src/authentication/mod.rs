@@ -1,5 +1,22 @@
+mod api_tokens;
 mod middleware;
 mod password;
+mod role;
+mod sessions;
+mod throttle;
+mod two_factor;
 
-pub use middleware::{reject_anonymous_users, UserId};
-pub use password::{update_password_hash, validate_credentials, AuthError, Credentials};
+pub use api_tokens::{authenticate_api_token, create_api_token, revoke_api_token};
+pub use middleware::{reject_anonymous_users, reject_invalid_api_tokens, UserId};
+pub use password::{
+    create_user, update_password_hash, validate_credentials, validate_new_password, AuthError,
+    Credentials, PasswordHashing,
+};
+pub use role::{Permission, Role};
+pub use sessions::{revoke_all_sessions, revoke_other_sessions, revoke_session, start_session};
+pub use throttle::LoginThrottle;
+pub use two_factor::{
+    count_unused_recovery_codes, disable_two_factor, enable_two_factor, generate_recovery_codes,
+    generate_totp_secret, get_totp_secret, provisioning_uri, qr_code_svg, verify_second_factor,
+    verify_totp_code,
+};
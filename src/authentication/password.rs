@@ -1,9 +1,12 @@
+use super::Role;
+use crate::configuration::PasswordHashingSettings;
 use crate::util::spawn_blocking_with_tracing;
 use anyhow::Context;
 use argon2::password_hash::SaltString;
 use argon2::{Algorithm, Argon2, Params, PasswordHash, PasswordHasher, PasswordVerifier, Version};
 use secrecy::{ExposeSecret, Secret};
-use sqlx::PgPool;
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use uuid::Uuid;
 
 #[derive(thiserror::Error, Debug)]
 pub enum AuthError {
@@ -19,22 +22,77 @@ pub struct Credentials {
     pub password: Secret<String>,
 }
 
-#[tracing::instrument(name = "Validate credentials", skip(credentials, pool))]
+// Hashes passwords with the configured Argon2id parameters.
+#[derive(Clone)]
+pub struct PasswordHashing {
+    params: Params,
+    dummy_hash: Secret<String>,
+}
+
+impl PasswordHashing {
+    pub fn new(settings: &PasswordHashingSettings) -> Result<Self, anyhow::Error> {
+        let params = Params::new(
+            settings.memory_size_kib,
+            settings.iterations,
+            settings.parallelism,
+            None,
+        )
+        .map_err(|e| anyhow::anyhow!("Invalid password hashing parameters: {}", e))?;
+        let mut hashing = Self {
+            params,
+            dummy_hash: Secret::new(String::new()),
+        };
+        // Unknown usernames are verified against this hash, so that they cost as much as a
+        // wrong password for a known one.
+        hashing.dummy_hash = hashing
+            .compute_hash(Secret::new(Uuid::new_v4().to_string()))
+            .context("Failed to compute the dummy password hash.")?;
+        Ok(hashing)
+    }
+
+    #[tracing::instrument(name = "Compute password hash", skip(self, password))]
+    fn compute_hash(&self, password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
+        let salt = SaltString::generate(&mut rand::thread_rng());
+        let password_hash = Argon2::new(Algorithm::Argon2id, Version::V0x13, self.params.clone())
+            .hash_password(password.expose_secret().as_bytes(), &salt)?
+            .to_string();
+        Ok(Secret::new(password_hash))
+    }
+
+    async fn compute_hash_blocking(
+        &self,
+        password: Secret<String>,
+    ) -> Result<Secret<String>, anyhow::Error> {
+        let hashing = self.clone();
+        spawn_blocking_with_tracing(move || hashing.compute_hash(password))
+            .await?
+            .context("Failed to hash password.")
+    }
+
+    fn is_outdated(&self, password_hash: &PasswordHash) -> bool {
+        let params = match Params::try_from(password_hash) {
+            Ok(params) => params,
+            Err(_) => return true,
+        };
+        password_hash.algorithm != Algorithm::Argon2id.ident()
+            || password_hash.version != Some(Version::V0x13.into())
+            || params.m_cost() != self.params.m_cost()
+            || params.t_cost() != self.params.t_cost()
+            || params.p_cost() != self.params.p_cost()
+    }
+}
+
+#[tracing::instrument(name = "Validate credentials", skip(credentials, pool, hashing))]
 pub async fn validate_credentials(
     credentials: Credentials,
     pool: &PgPool,
+    hashing: &PasswordHashing,
 ) -> Result<uuid::Uuid, AuthError> {
-    // Note: We're still verifying hash for non existent user by using a default password hash
+    // Note: We're still verifying hash for non existent user by using a dummy password hash
     // to prevent timing side channel attack. The downside of the current implementation is,
-    // we report invalid password in case of unknown username. Also the default hash is hard-corded
-    // with hash algo and parameters. TODO
+    // we report invalid password in case of unknown username.
     let mut user_id = None;
-    let mut expected_password_hash = Secret::new(
-        "$argon2id$v=19$m=15000,t=2,p=1$\
-        gZiV/M1gPc22ElAH/Jh1Hw$\
-        CWOrkoo7oJBQ/iyh7uJ0LO2aLEfrHwTWllSAxT0zRno"
-            .to_string(),
-    );
+    let mut expected_password_hash = hashing.dummy_hash.clone();
     if let Some((stored_user_id, stored_password_hash)) =
         get_stored_credentials(&credentials.username, pool)
             .await
@@ -43,6 +101,8 @@ pub async fn validate_credentials(
         user_id = Some(stored_user_id);
         expected_password_hash = stored_password_hash;
     }
+    let password = credentials.password.clone();
+    let stored_password_hash = expected_password_hash.clone();
     spawn_blocking_with_tracing(move || {
         verify_password_hash(expected_password_hash, credentials.password)
     })
@@ -50,7 +110,51 @@ pub async fn validate_credentials(
     .context("Failed to spawn blocking task.")?
     .await?;
 
-    user_id.ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))
+    let user_id = user_id
+        .ok_or_else(|| AuthError::InvalidCredentials(anyhow::anyhow!("Unknown username.")))?;
+    let is_outdated = PasswordHash::new(stored_password_hash.expose_secret())
+        .map(|password_hash| hashing.is_outdated(&password_hash))
+        .unwrap_or(true);
+    if is_outdated {
+        // The login itself has already succeeded, so a failed upgrade is retried next time.
+        if let Err(e) =
+            upgrade_password_hash(pool, user_id, stored_password_hash, password, hashing).await
+        {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to upgrade an outdated password hash.",
+            );
+        }
+    }
+    Ok(user_id)
+}
+
+#[tracing::instrument(
+    name = "Upgrade password hash",
+    skip(pool, outdated_password_hash, password, hashing)
+)]
+async fn upgrade_password_hash(
+    pool: &PgPool,
+    user_id: Uuid,
+    outdated_password_hash: Secret<String>,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
+) -> Result<(), anyhow::Error> {
+    let password_hash = hashing.compute_hash_blocking(password).await?;
+    // Only replace the hash that was verified, in case the password changed in the meantime.
+    sqlx::query!(
+        "UPDATE users
+        SET password_hash = $1
+        WHERE user_id = $2 AND password_hash = $3",
+        password_hash.expose_secret(),
+        user_id,
+        outdated_password_hash.expose_secret(),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to store the upgraded password hash.")?;
+    Ok(())
 }
 
 #[tracing::instrument(
@@ -96,37 +200,66 @@ async fn get_stored_credentials(
     Ok(row)
 }
 
-#[tracing::instrument(name = "Compute password hash", skip(password))]
-fn compute_hash(password: Secret<String>) -> Result<Secret<String>, anyhow::Error> {
-    let salt = SaltString::generate(&mut rand::thread_rng());
-    let password_hash = Argon2::new(
-        Algorithm::Argon2id,
-        Version::V0x13,
-        Params::new(15000, 2, 1, None).unwrap(),
-    )
-    .hash_password(password.expose_secret().as_bytes(), &salt)?
-    .to_string();
-    Ok(Secret::new(password_hash))
+// Checks a password chosen through one of the forms that set a new password.
+pub fn validate_new_password(
+    new_password: &Secret<String>,
+    confirm_new_password: &Secret<String>,
+) -> Result<(), &'static str> {
+    if new_password.expose_secret() != confirm_new_password.expose_secret() {
+        return Err("New password does not match with confirmation password.");
+    }
+    if !matches!(new_password.expose_secret().len(), 12..=127) {
+        return Err(
+            "New password must at least 12 characters long but shorter than 128 characters.",
+        );
+    }
+    Ok(())
 }
 
-#[tracing::instrument(name = "Update password hash", skip(credentials, pool))]
+#[tracing::instrument(name = "Update password hash", skip(executor, password, hashing))]
 pub async fn update_password_hash(
-    credentials: Credentials,
-    pool: &PgPool,
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    password: Secret<String>,
+    hashing: &PasswordHashing,
 ) -> Result<(), anyhow::Error> {
-    let password_hash = spawn_blocking_with_tracing(move || compute_hash(credentials.password))
-        .await?
-        .context("Failed to hash password.")?;
+    let password_hash = hashing.compute_hash_blocking(password).await?;
     sqlx::query!(
         "UPDATE users
         SET password_hash = $1
-        WHERE username = $2",
+        WHERE user_id = $2",
         password_hash.expose_secret(),
-        credentials.username,
+        user_id,
     )
-    .execute(pool)
+    .execute(executor)
     .await
     .context("Failed to update password")?;
 
     Ok(())
 }
+
+#[tracing::instrument(name = "Create user", skip(transaction, credentials, hashing), fields(username=%credentials.username))]
+pub async fn create_user(
+    transaction: &mut Transaction<'_, Postgres>,
+    credentials: Credentials,
+    email: &str,
+    role: Role,
+    hashing: &PasswordHashing,
+) -> Result<Uuid, anyhow::Error> {
+    let password_hash = hashing.compute_hash_blocking(credentials.password).await?;
+    let user_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO users (user_id, username, password_hash, email, role)
+        VALUES ($1, $2, $3, $4, $5)",
+        user_id,
+        credentials.username,
+        password_hash.expose_secret(),
+        email,
+        role.as_str(),
+    )
+    .execute(transaction)
+    .await
+    .context("Failed to store the new user.")?;
+
+    Ok(user_id)
+}
@@ -0,0 +1,109 @@
+use crate::util::e403;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Role {
+    Owner,
+    Publisher,
+    Editor,
+    Viewer,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Permission {
+    ManageUsers,
+    Publish,
+    EditDrafts,
+    ManageSubscribers,
+}
+
+impl Role {
+    pub const ALL: [Role; 4] = [Role::Owner, Role::Publisher, Role::Editor, Role::Viewer];
+
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "owner" => Ok(Self::Owner),
+            "publisher" => Ok(Self::Publisher),
+            "editor" => Ok(Self::Editor),
+            "viewer" => Ok(Self::Viewer),
+            other => Err(format!("{} is not a valid role.", other)),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Owner => "owner",
+            Self::Publisher => "publisher",
+            Self::Editor => "editor",
+            Self::Viewer => "viewer",
+        }
+    }
+
+    pub fn can(&self, permission: Permission) -> bool {
+        match permission {
+            Permission::ManageUsers => matches!(self, Self::Owner),
+            Permission::Publish => matches!(self, Self::Owner | Self::Publisher),
+            Permission::EditDrafts => !matches!(self, Self::Viewer),
+            Permission::ManageSubscribers => matches!(self, Self::Owner | Self::Publisher),
+        }
+    }
+
+    pub fn require(&self, permission: Permission) -> Result<(), actix_web::Error> {
+        if self.can(permission) {
+            return Ok(());
+        }
+        let action = match permission {
+            Permission::ManageUsers => "manage users",
+            Permission::Publish => "send newsletter issues",
+            Permission::EditDrafts => "edit drafts",
+            Permission::ManageSubscribers => "manage subscribers",
+        };
+        Err(e403(format!(
+            "The {} role is not allowed to {}.",
+            self.as_str(),
+            action
+        )))
+    }
+}
+
+impl std::fmt::Display for Role {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Permission, Role};
+
+    #[test]
+    fn roles_round_trip_through_their_names() {
+        for role in Role::ALL {
+            assert_eq!(Role::parse(role.as_str()), Ok(role));
+        }
+        assert!(Role::parse("admin").is_err());
+    }
+
+    #[test]
+    fn only_owners_can_manage_users() {
+        assert!(Role::Owner.can(Permission::ManageUsers));
+        assert!(!Role::Publisher.can(Permission::ManageUsers));
+        assert!(!Role::Editor.can(Permission::ManageUsers));
+        assert!(!Role::Viewer.can(Permission::ManageUsers));
+    }
+
+    #[test]
+    fn editors_can_draft_but_not_publish() {
+        assert!(Role::Editor.can(Permission::EditDrafts));
+        assert!(!Role::Editor.can(Permission::Publish));
+        assert!(Role::Publisher.can(Permission::Publish));
+        assert!(!Role::Viewer.can(Permission::EditDrafts));
+    }
+
+    #[test]
+    fn only_publishers_and_owners_can_manage_subscribers() {
+        assert!(Role::Owner.can(Permission::ManageSubscribers));
+        assert!(Role::Publisher.can(Permission::ManageSubscribers));
+        assert!(!Role::Editor.can(Permission::ManageSubscribers));
+        assert!(!Role::Viewer.can(Permission::ManageSubscribers));
+    }
+}
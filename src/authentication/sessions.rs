@@ -0,0 +1,105 @@
+use crate::session_state::TypedSession;
+use crate::util::client_ip;
+use actix_web::http::header::USER_AGENT;
+use actix_web::HttpRequest;
+use anyhow::Context;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+// Every login gets a row in `user_sessions`, so that sessions stored in Redis can be
+// revoked by user without having to scan the session store.
+#[tracing::instrument(name = "Start a user session", skip(session, request, pool))]
+pub async fn start_session(
+    session: &TypedSession,
+    user_id: Uuid,
+    request: &HttpRequest,
+    pool: &PgPool,
+) -> Result<(), anyhow::Error> {
+    let session_id = Uuid::new_v4();
+    // Only shown on the sessions page, to help users recognise their devices.
+    let user_agent = request
+        .headers()
+        .get(USER_AGENT)
+        .and_then(|h| h.to_str().ok())
+        .map(String::from);
+    let ip_address = client_ip(request);
+    sqlx::query!(
+        r#"
+        INSERT INTO user_sessions (session_id, user_id, user_agent, ip_address)
+        VALUES ($1, $2, $3, $4)
+        "#,
+        session_id,
+        user_id,
+        user_agent,
+        ip_address,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to record the user session.")?;
+
+    session.renew();
+    session.insert_user_id(user_id)?;
+    session.insert_session_id(session_id)?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Revoke all sessions of a user", skip(executor))]
+pub async fn revoke_all_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND revoked_at IS NULL
+        "#,
+        user_id,
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_revoked)
+}
+
+#[tracing::instrument(name = "Revoke a user session", skip(executor))]
+pub async fn revoke_session(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    session_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id = $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        session_id,
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_revoked > 0)
+}
+
+#[tracing::instrument(name = "Revoke the other sessions of a user", skip(executor))]
+pub async fn revoke_other_sessions(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+    current_session_id: Uuid,
+) -> Result<u64, sqlx::Error> {
+    let n_revoked = sqlx::query!(
+        r#"
+        UPDATE user_sessions
+        SET revoked_at = now()
+        WHERE user_id = $1 AND session_id <> $2 AND revoked_at IS NULL
+        "#,
+        user_id,
+        current_session_id,
+    )
+    .execute(executor)
+    .await?
+    .rows_affected();
+    Ok(n_revoked)
+}
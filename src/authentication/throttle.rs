@@ -0,0 +1,131 @@
+use crate::configuration::LoginThrottleSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use redis::AsyncCommands;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+// Counts failed logins per username and per client address in Redis, and blocks further
+// attempts for a while once too many of them failed. This keeps brute-force attempts from
+// queuing up Argon2 hashes on the blocking pool.
+#[derive(Clone)]
+pub struct LoginThrottle {
+    redis: ConnectionManager,
+    settings: LoginThrottleSettings,
+}
+
+enum Subject<'a> {
+    Username(&'a str),
+    Ip(&'a str),
+}
+
+impl Subject<'_> {
+    fn failures_key(&self) -> String {
+        match self {
+            Self::Username(username) => {
+                format!(
+                    "login_throttle:failures:username:{}",
+                    username.to_lowercase()
+                )
+            }
+            Self::Ip(ip) => format!("login_throttle:failures:ip:{}", ip),
+        }
+    }
+
+    fn blocked_key(&self) -> String {
+        match self {
+            Self::Username(username) => {
+                format!(
+                    "login_throttle:blocked:username:{}",
+                    username.to_lowercase()
+                )
+            }
+            Self::Ip(ip) => format!("login_throttle:blocked:ip:{}", ip),
+        }
+    }
+}
+
+fn subjects<'a>(username: &'a str, ip: Option<&'a str>) -> Vec<Subject<'a>> {
+    let mut subjects = vec![Subject::Username(username)];
+    subjects.extend(ip.map(Subject::Ip));
+    subjects
+}
+
+impl LoginThrottle {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: LoginThrottleSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI.")?;
+        let redis = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self { redis, settings })
+    }
+
+    // Returns how long the caller has to wait before a login attempt is accepted again.
+    #[tracing::instrument(name = "Check login throttle", skip(self))]
+    pub async fn retry_after(
+        &self,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let mut retry_after = None;
+        for subject in subjects(username, ip) {
+            let ttl_ms: i64 = redis
+                .pttl(subject.blocked_key())
+                .await
+                .context("Failed to read a login block from Redis.")?;
+            if ttl_ms > 0 {
+                retry_after = retry_after.max(Some(Duration::from_millis(ttl_ms as u64)));
+            }
+        }
+        Ok(retry_after)
+    }
+
+    #[tracing::instrument(name = "Record failed login", skip(self))]
+    pub async fn record_failure(
+        &self,
+        username: &str,
+        ip: Option<&str>,
+    ) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        for subject in subjects(username, ip) {
+            let failures_key = subject.failures_key();
+            let n_failures: u32 = redis
+                .incr(&failures_key, 1)
+                .await
+                .context("Failed to count a failed login in Redis.")?;
+            let _: bool = redis
+                .expire(&failures_key, self.settings.window_seconds as usize)
+                .await
+                .context("Failed to set the expiry of a login failure counter.")?;
+            let delay = match subject {
+                Subject::Username(_) => self.settings.username_delay(n_failures),
+                Subject::Ip(_) => self.settings.ip_delay(n_failures),
+            };
+            if let Some(delay) = delay {
+                let _: () = redis
+                    .set_ex(subject.blocked_key(), 1, delay.as_secs().max(1) as usize)
+                    .await
+                    .context("Failed to store a login block in Redis.")?;
+            }
+        }
+        Ok(())
+    }
+
+    // Only the username is forgiven: a client address that guesses one password right
+    // may still be trying others.
+    #[tracing::instrument(name = "Record successful login", skip(self))]
+    pub async fn record_success(&self, username: &str) -> Result<(), anyhow::Error> {
+        let mut redis = self.redis.clone();
+        let subject = Subject::Username(username);
+        let _: u32 = redis
+            .del(&[subject.failures_key(), subject.blocked_key()])
+            .await
+            .context("Failed to reset the login failure counter.")?;
+        Ok(())
+    }
+}
@@ -0,0 +1,319 @@
+use anyhow::Context;
+use qrcodegen::{QrCode, QrCodeEcc};
+use rand::distributions::{Alphanumeric, DistString};
+use rand::{thread_rng, RngCore};
+use secrecy::{ExposeSecret, Secret};
+use sha2::{Digest, Sha256};
+use sqlx::{PgExecutor, PgPool, Postgres, Transaction};
+use std::fmt::Write;
+use totp_rs::{Algorithm, TOTP};
+use uuid::Uuid;
+
+const ISSUER: &str = "Newsletter";
+const STEP_SECONDS: u64 = 30;
+const RECOVERY_CODE_COUNT: usize = 10;
+
+// A base32 encoded TOTP secret, as stored in `users.totp_secret`.
+pub fn generate_totp_secret() -> Secret<String> {
+    let mut bytes = [0u8; 20];
+    thread_rng().fill_bytes(&mut bytes);
+    Secret::new(base32_encode(&bytes))
+}
+
+fn base32_encode(bytes: &[u8]) -> String {
+    totp_rs::Secret::Raw(bytes.to_vec())
+        .to_encoded()
+        .to_string()
+}
+
+fn totp(secret: &Secret<String>, username: &str) -> Result<TOTP, anyhow::Error> {
+    let secret = totp_rs::Secret::Encoded(secret.expose_secret().clone())
+        .to_bytes()
+        .map_err(|e| anyhow::anyhow!("Invalid TOTP secret: {:?}", e))?;
+    // Colons separate the issuer from the account name in provisioning URIs.
+    let account_name = username.replace(':', "");
+    TOTP::new(
+        Algorithm::SHA1,
+        6,
+        0,
+        STEP_SECONDS,
+        secret,
+        Some(ISSUER.into()),
+        account_name,
+    )
+    .context("Failed to build the TOTP generator.")
+}
+
+// The `otpauth://` URI that authenticator apps scan to enroll the secret.
+pub fn provisioning_uri(secret: &Secret<String>, username: &str) -> Result<String, anyhow::Error> {
+    Ok(totp(secret, username)?.get_url())
+}
+
+pub fn qr_code_svg(text: &str) -> Result<String, anyhow::Error> {
+    let qr = QrCode::encode_text(text, QrCodeEcc::Medium)
+        .map_err(|_| anyhow::anyhow!("The provisioning URI is too long for a QR code."))?;
+    let border = 4;
+    let dimension = qr.size() + border * 2;
+    let mut path = String::new();
+    for y in 0..qr.size() {
+        for x in 0..qr.size() {
+            if qr.get_module(x, y) {
+                write!(path, "M{},{}h1v1h-1z ", x + border, y + border)?;
+            }
+        }
+    }
+    Ok(format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" viewBox="0 0 {0} {0}" width="200" height="200" stroke="none"><rect width="100%" height="100%" fill="white"/><path d="{1}" fill="black"/></svg>"#,
+        dimension,
+        path.trim_end()
+    ))
+}
+
+// Returns the time step the code belongs to, allowing one step of clock drift either way.
+pub fn verify_totp_code(
+    secret: &Secret<String>,
+    code: &str,
+    now: u64,
+) -> Result<Option<i64>, anyhow::Error> {
+    let totp = totp(secret, "")?;
+    let current_step = now / STEP_SECONDS;
+    for step in [current_step - 1, current_step, current_step + 1] {
+        if totp.check(code, step * STEP_SECONDS) {
+            return Ok(Some(step as i64));
+        }
+    }
+    Ok(None)
+}
+
+pub fn generate_recovery_codes() -> Vec<String> {
+    (0..RECOVERY_CODE_COUNT)
+        .map(|_| {
+            let code = Alphanumeric
+                .sample_string(&mut thread_rng(), 10)
+                .to_lowercase();
+            format!("{}-{}", &code[..5], &code[5..])
+        })
+        .collect()
+}
+
+// Recovery codes carry enough entropy for a fast hash to be sufficient.
+fn hash_recovery_code(code: &str) -> String {
+    let normalized = code.trim().to_lowercase();
+    hex::encode(Sha256::digest(normalized.as_bytes()))
+}
+
+#[tracing::instrument(name = "Enable two-factor authentication", skip_all, fields(user_id=%user_id))]
+pub async fn enable_two_factor(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    secret: &Secret<String>,
+    verified_step: i64,
+    recovery_codes: &[String],
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = $2, totp_last_used_step = $3
+        WHERE user_id = $1
+        "#,
+        user_id,
+        secret.expose_secret(),
+        verified_step,
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to store the TOTP secret.")?;
+    sqlx::query!(
+        r#"DELETE FROM user_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut *transaction)
+    .await
+    .context("Failed to delete previous recovery codes.")?;
+    for code in recovery_codes {
+        sqlx::query!(
+            r#"INSERT INTO user_recovery_codes (user_id, code_hash) VALUES ($1, $2)"#,
+            user_id,
+            hash_recovery_code(code),
+        )
+        .execute(&mut *transaction)
+        .await
+        .context("Failed to store a recovery code.")?;
+    }
+    Ok(())
+}
+
+#[tracing::instrument(name = "Disable two-factor authentication", skip(pool))]
+pub async fn disable_two_factor(pool: &PgPool, user_id: Uuid) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    sqlx::query!(
+        r#"
+        UPDATE users
+        SET totp_secret = NULL, totp_last_used_step = NULL
+        WHERE user_id = $1
+        "#,
+        user_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to remove the TOTP secret.")?;
+    sqlx::query!(
+        r#"DELETE FROM user_recovery_codes WHERE user_id = $1"#,
+        user_id
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to delete recovery codes.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit disabling two-factor authentication.")?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Get TOTP secret", skip(executor))]
+pub async fn get_totp_secret(
+    executor: impl PgExecutor<'_>,
+    user_id: Uuid,
+) -> Result<Option<Secret<String>>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"SELECT totp_secret FROM users WHERE user_id = $1"#,
+        user_id
+    )
+    .fetch_one(executor)
+    .await
+    .context("Failed to retrieve the TOTP secret.")?;
+    Ok(row.totp_secret.map(Secret::new))
+}
+
+// Checks the second factor of a login, which is either a TOTP code or an unused recovery code.
+// A TOTP code is only accepted once, so an observed code cannot be replayed.
+#[tracing::instrument(name = "Verify second factor", skip(pool, secret, code))]
+pub async fn verify_second_factor(
+    pool: &PgPool,
+    user_id: Uuid,
+    secret: &Secret<String>,
+    code: &str,
+    now: u64,
+) -> Result<bool, anyhow::Error> {
+    let code = code.trim();
+    if code.len() == 6 && code.chars().all(|c| c.is_ascii_digit()) {
+        let step = match verify_totp_code(secret, code, now)? {
+            Some(step) => step,
+            None => return Ok(false),
+        };
+        let n_updated = sqlx::query!(
+            r#"
+            UPDATE users
+            SET totp_last_used_step = $2
+            WHERE
+                user_id = $1 AND
+                (totp_last_used_step IS NULL OR totp_last_used_step < $2)
+            "#,
+            user_id,
+            step,
+        )
+        .execute(pool)
+        .await
+        .context("Failed to record the TOTP step.")?
+        .rows_affected();
+        return Ok(n_updated == 1);
+    }
+
+    let n_used = sqlx::query!(
+        r#"
+        UPDATE user_recovery_codes
+        SET used_at = now()
+        WHERE
+            user_id = $1 AND
+            code_hash = $2 AND
+            used_at IS NULL
+        "#,
+        user_id,
+        hash_recovery_code(code),
+    )
+    .execute(pool)
+    .await
+    .context("Failed to use a recovery code.")?
+    .rows_affected();
+    Ok(n_used == 1)
+}
+
+#[tracing::instrument(name = "Count unused recovery codes", skip(pool))]
+pub async fn count_unused_recovery_codes(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<i64, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "n!"
+        FROM user_recovery_codes
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+    )
+    .fetch_one(pool)
+    .await
+    .context("Failed to count recovery codes.")?;
+    Ok(row.n)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn code_at(secret: &Secret<String>, time: u64) -> String {
+        totp(secret, "").unwrap().generate(time)
+    }
+
+    #[test]
+    fn codes_from_adjacent_steps_are_accepted() {
+        let secret = generate_totp_secret();
+        let now = 1_650_000_000;
+        for time in [now - 30, now, now + 30] {
+            let code = code_at(&secret, time);
+            assert_eq!(
+                verify_totp_code(&secret, &code, now).unwrap(),
+                Some((time / STEP_SECONDS) as i64)
+            );
+        }
+    }
+
+    #[test]
+    fn codes_from_distant_steps_are_rejected() {
+        let secret = generate_totp_secret();
+        let now = 1_650_000_000;
+        let code = code_at(&secret, now - 90);
+        // Guard against an accidental collision with a code in the accepted window.
+        if (now - 30..=now + 30)
+            .step_by(30)
+            .all(|t| code_at(&secret, t) != code)
+        {
+            assert_eq!(verify_totp_code(&secret, &code, now).unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn provisioning_uri_names_the_issuer_and_account() {
+        let secret = generate_totp_secret();
+        let uri = provisioning_uri(&secret, "ad:min").unwrap();
+        assert!(uri.starts_with("otpauth://totp/Newsletter:admin?"));
+        assert!(uri.contains(&format!("secret={}", secret.expose_secret())));
+        assert!(qr_code_svg(&uri).unwrap().starts_with("<svg"));
+    }
+
+    #[test]
+    fn recovery_codes_are_distinct_and_normalized_before_hashing() {
+        let codes = generate_recovery_codes();
+        assert_eq!(codes.len(), RECOVERY_CODE_COUNT);
+        let unique: std::collections::HashSet<_> = codes.iter().collect();
+        assert_eq!(unique.len(), RECOVERY_CODE_COUNT);
+        assert_eq!(
+            hash_recovery_code(&format!(" {} ", codes[0].to_uppercase())),
+            hash_recovery_code(&codes[0])
+        );
+    }
+}
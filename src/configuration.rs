@@ -1,17 +1,22 @@
 use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::email_client::{EmailClient, FileSinkTransport, PostmarkTransport, SmtpTransport};
 use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
 use serde::Deserialize;
 use serde_aux::field_attributes::deserialize_number_from_string;
 use sqlx::postgres::{PgConnectOptions, PgSslMode};
 use sqlx::ConnectOptions;
+use std::net::IpAddr;
 
 #[derive(Deserialize, Debug, Clone)]
 pub struct Settings {
     pub application: ApplicationSettings,
     pub database: DatabaseSettings,
     pub email_client: EmailClientSettings,
+    pub delivery_worker: DeliveryWorkerSettings,
+    pub login_throttle: LoginThrottleSettings,
+    pub confirmation_resend: ConfirmationResendSettings,
+    pub password_hashing: PasswordHashingSettings,
     pub redis_uri: Secret<String>,
 }
 
@@ -22,6 +27,12 @@ pub struct ApplicationSettings {
     pub host: String,
     pub base_url: String,
     pub hmac_secret: Secret<String>,
+    pub postmark_webhook_secret: Secret<String>,
+    // Addresses of the reverse proxies in front of the application. Requests they pass on
+    // are attributed to the address they add to `X-Forwarded-For`, so that per-address
+    // limits apply to the actual client. Empty when clients connect directly.
+    #[serde(default)]
+    pub trusted_proxies: Vec<IpAddr>,
 }
 
 impl ApplicationSettings {
@@ -66,10 +77,38 @@ impl DatabaseSettings {
 
 #[derive(serde::Deserialize, Debug, Clone)]
 pub struct EmailClientSettings {
+    #[serde(default)]
+    pub transport: EmailTransportKind,
     pub base_url: String,
     pub sender_email: String,
     pub authorization_token: Secret<String>,
     pub timeout_milliseconds: u64,
+    pub smtp: Option<SmtpSettings>,
+    pub file_sink_directory: Option<String>,
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum EmailTransportKind {
+    #[default]
+    Postmark,
+    Smtp,
+    FileSink,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct SmtpSettings {
+    pub host: String,
+    #[serde(deserialize_with = "deserialize_number_from_string")]
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<Secret<String>>,
+    #[serde(default = "default_require_tls")]
+    pub require_tls: bool,
+}
+
+fn default_require_tls() -> bool {
+    true
 }
 
 impl EmailClientSettings {
@@ -85,11 +124,109 @@ impl EmailClientSettings {
     pub fn client(self) -> EmailClient {
         let sender_email = self.sender().expect("Invalid sender email address.");
         let timeout = self.timeout();
-        let base_url = self.base_url().expect("Invalid base URL");
-        EmailClient::new(base_url, sender_email, self.authorization_token, timeout)
+        match self.transport {
+            EmailTransportKind::Postmark => {
+                let base_url = self.base_url().expect("Invalid base URL");
+                let transport = PostmarkTransport::new(base_url, self.authorization_token, timeout);
+                EmailClient::new(sender_email, transport)
+            }
+            EmailTransportKind::Smtp => {
+                let smtp = self.smtp.expect("Missing SMTP settings.");
+                let credentials = smtp.username.zip(smtp.password);
+                let transport = SmtpTransport::new(
+                    &smtp.host,
+                    smtp.port,
+                    credentials,
+                    smtp.require_tls,
+                    timeout,
+                )
+                .expect("Invalid SMTP settings.");
+                EmailClient::new(sender_email, transport)
+            }
+            EmailTransportKind::FileSink => {
+                let directory = self
+                    .file_sink_directory
+                    .expect("Missing file sink directory.");
+                let transport = FileSinkTransport::new(directory)
+                    .expect("Failed to create the file sink directory.");
+                EmailClient::new(sender_email, transport)
+            }
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct DeliveryWorkerSettings {
+    pub max_retries: i16,
+    pub initial_backoff_milliseconds: u64,
+    pub max_backoff_milliseconds: u64,
+    // When set, the worker sends the queued deliveries in batches of this size.
+    #[serde(default)]
+    pub batch_size: Option<u16>,
+}
+
+impl DeliveryWorkerSettings {
+    pub fn backoff(&self, n_retries: i16) -> std::time::Duration {
+        let factor = 2u64.saturating_pow(n_retries.max(0) as u32);
+        let backoff = self.initial_backoff_milliseconds.saturating_mul(factor);
+        std::time::Duration::from_millis(backoff.min(self.max_backoff_milliseconds))
+    }
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub struct LoginThrottleSettings {
+    // Failed attempts are forgotten once this long has passed without a new one.
+    pub window_seconds: u64,
+    // Failures per username that are tolerated before each attempt has to wait.
+    pub free_attempts: u32,
+    pub initial_delay_seconds: u64,
+    pub max_delay_seconds: u64,
+    pub max_failures_per_username: u32,
+    pub max_failures_per_ip: u32,
+    pub lockout_seconds: u64,
+}
+
+impl LoginThrottleSettings {
+    // How long a username has to wait before its next attempt after `n_failures` failed ones.
+    pub fn username_delay(&self, n_failures: u32) -> Option<std::time::Duration> {
+        if n_failures >= self.max_failures_per_username {
+            return Some(std::time::Duration::from_secs(self.lockout_seconds));
+        }
+        if n_failures <= self.free_attempts {
+            return None;
+        }
+        let factor = 2u64.saturating_pow(n_failures - self.free_attempts - 1);
+        let delay = self.initial_delay_seconds.saturating_mul(factor);
+        Some(std::time::Duration::from_secs(
+            delay.min(self.max_delay_seconds),
+        ))
+    }
+
+    // Addresses are only locked out, so that a shared address is not slowed down by a few typos.
+    pub fn ip_delay(&self, n_failures: u32) -> Option<std::time::Duration> {
+        (n_failures >= self.max_failures_per_ip)
+            .then(|| std::time::Duration::from_secs(self.lockout_seconds))
     }
 }
 
+#[derive(Deserialize, Debug, Clone)]
+pub struct ConfirmationResendSettings {
+    // How long an address has to wait before another confirmation or password reset email is
+    // sent to it.
+    pub address_cooldown_seconds: u64,
+    // How long a client address has to wait between two resend or password reset requests.
+    pub ip_cooldown_seconds: u64,
+}
+
+// Argon2id parameters for new password hashes. Stored hashes with other parameters keep
+// working and are re-hashed with these on the next successful login.
+#[derive(Deserialize, Debug, Clone)]
+pub struct PasswordHashingSettings {
+    pub memory_size_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
 pub fn get_configuration() -> Result<Settings, config::ConfigError> {
     let base_path = std::env::current_dir().expect("Failed to determine the current directory");
     let configuration_directory = base_path.join("configuration");
@@ -137,3 +274,50 @@ impl TryFrom<String> for Environment {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::LoginThrottleSettings;
+    use std::time::Duration;
+
+    fn settings() -> LoginThrottleSettings {
+        LoginThrottleSettings {
+            window_seconds: 900,
+            free_attempts: 3,
+            initial_delay_seconds: 1,
+            max_delay_seconds: 4,
+            max_failures_per_username: 10,
+            max_failures_per_ip: 50,
+            lockout_seconds: 900,
+        }
+    }
+
+    #[test]
+    fn username_delays_grow_after_the_free_attempts_and_end_in_a_lockout() {
+        let settings = settings();
+        let delays: Vec<_> = (1..=10).map(|n| settings.username_delay(n)).collect();
+        let secs = |s| Some(Duration::from_secs(s));
+        assert_eq!(
+            delays,
+            vec![
+                None,
+                None,
+                None,
+                secs(1),
+                secs(2),
+                secs(4),
+                secs(4),
+                secs(4),
+                secs(4),
+                secs(900)
+            ]
+        );
+    }
+
+    #[test]
+    fn addresses_are_only_locked_out() {
+        let settings = settings();
+        assert_eq!(settings.ip_delay(49), None);
+        assert_eq!(settings.ip_delay(50), Some(Duration::from_secs(900)));
+    }
+}
@@ -0,0 +1,238 @@
+use crate::configuration::{DeliveryWorkerSettings, Settings};
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{ExecutionError, ExecutionOutcome};
+use crate::routes::{confirmation_token, send_confirmation_email};
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+use tracing::{field::display, Span};
+use uuid::Uuid;
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct Task {
+    subscriber_id: Uuid,
+    email: String,
+    name: String,
+    // The list the subscriber joined last and has not confirmed yet, if any.
+    list_id: Option<Uuid>,
+    n_retries: i16,
+}
+
+// Confirmation emails for subscribers added in bulk, or asked for again through the resend
+// form, are sent here rather than while handling the request, one at a time and with the
+// retry budget of issue deliveries.
+#[tracing::instrument(
+    skip_all,
+    fields(subscriber_id=tracing::field::Empty),
+    err(Debug)
+)]
+pub async fn try_send_confirmation_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &Url,
+    settings: &DeliveryWorkerSettings,
+) -> Result<ExecutionOutcome, ExecutionError> {
+    let (mut transaction, task) = match dequeue_task(pool)
+        .await
+        .context("Failed to dequeue a confirmation email.")
+        .map_err(ExecutionError::Transient)?
+    {
+        Some(dequeued) => dequeued,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+    Span::current().record("subscriber_id", &display(task.subscriber_id));
+
+    // Subscribers who confirmed or left in the meantime are not asked again.
+    if let Some(list_id) = task.list_id {
+        let subscriber = SubscriberEmail::parse(&task.email).and_then(|email| {
+            Ok(NewSubscriber::new(
+                email,
+                SubscriberName::parse(&task.name)?,
+            ))
+        });
+        let subscriber = match subscriber {
+            Ok(subscriber) => subscriber,
+            Err(e) => {
+                delete_task(&mut transaction, task.subscriber_id)
+                    .await
+                    .context("Failed to delete the confirmation email.")
+                    .map_err(ExecutionError::Transient)?;
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to delete the confirmation email.")
+                    .map_err(ExecutionError::Transient)?;
+                return Err(ExecutionError::Fatal(
+                    anyhow::anyhow!(e).context("Invalid contact details."),
+                ));
+            }
+        };
+        let subscription_token = confirmation_token(&mut transaction, task.subscriber_id, list_id)
+            .await
+            .context("Failed to get a subscription token from the database.")
+            .map_err(ExecutionError::Transient)?;
+        if let Err(e) =
+            send_confirmation_email(email_client, subscriber, base_url, &subscription_token).await
+        {
+            let gave_up = task.n_retries >= settings.max_retries;
+            if gave_up {
+                delete_task(&mut transaction, task.subscriber_id).await
+            } else {
+                schedule_retry(&mut transaction, &task, settings.backoff(task.n_retries)).await
+            }
+            .context("Failed to record the failed confirmation email.")
+            .map_err(ExecutionError::Transient)?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to record the failed confirmation email.")
+                .map_err(ExecutionError::Transient)?;
+            if gave_up {
+                return Err(ExecutionError::Fatal(
+                    e.context("Failed to send a confirmation email, giving up."),
+                ));
+            }
+            return Err(ExecutionError::Transient(
+                e.context("Failed to send a confirmation email."),
+            ));
+        }
+    }
+    delete_task(&mut transaction, task.subscriber_id)
+        .await
+        .context("Failed to complete the confirmation email.")
+        .map_err(ExecutionError::Transient)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to complete the confirmation email.")
+        .map_err(ExecutionError::Transient)?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Task)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT
+            q.subscriber_id,
+            s.email,
+            s.name,
+            (
+                SELECT m.list_id FROM list_memberships m
+                WHERE m.subscriber_id = s.id AND m.status = 'pending_confirmation'
+                ORDER BY m.subscribed_at DESC
+                LIMIT 1
+            ) AS "list_id?",
+            q.n_retries
+        FROM confirmation_email_queue q
+        JOIN subscriptions s ON s.id = q.subscriber_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn delete_task(
+    transaction: &mut PgTransaction,
+    subscriber_id: Uuid,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM confirmation_email_queue WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE confirmation_email_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $2
+        WHERE subscriber_id = $1
+        "#,
+        task.subscriber_id,
+        execute_after,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn enqueue_confirmation_emails(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_email_queue (subscriber_id)
+        SELECT subscriber_id FROM unnest($1::uuid[]) AS subscriber_id
+        ON CONFLICT DO NOTHING
+        "#,
+        subscriber_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: Url,
+    settings: DeliveryWorkerSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_confirmation_email(&pool, &email_client, &base_url, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(ExecutionError::Transient(_)) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(ExecutionError::Fatal(_)) => {}
+        }
+    }
+}
+
+pub async fn run_confirmation_worker_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let base_url = configuration
+        .application
+        .base_url()
+        .expect("Failed to get application base URL.");
+    worker_loop(
+        connection_pool,
+        email_client,
+        base_url,
+        configuration.delivery_worker,
+    )
+    .await
+}
@@ -0,0 +1,63 @@
+use chrono::Duration;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::thread_rng;
+
+#[derive(Debug)]
+pub struct InvitationToken(String);
+
+impl InvitationToken {
+    const TOKEN_LEN: usize = 32;
+    const VALIDITY_HOURS: i64 = 72;
+
+    pub fn new() -> Self {
+        let token = Alphanumeric.sample_string(&mut thread_rng(), Self::TOKEN_LEN);
+        Self(token)
+    }
+
+    pub fn parse(s: impl AsRef<str>) -> Result<Self, String> {
+        let s = s.as_ref();
+        let valid_token_len = s.len() == Self::TOKEN_LEN;
+        let valid_chars = s.chars().all(|c| c.is_ascii_alphanumeric());
+        if valid_token_len && valid_chars {
+            Ok(Self(s.to_string()))
+        } else {
+            Err(format!("{} is not a valid invitation token.", s))
+        }
+    }
+
+    /// How long an invitation link stays valid after it has been sent.
+    pub fn validity() -> Duration {
+        Duration::hours(Self::VALIDITY_HOURS)
+    }
+}
+
+impl Default for InvitationToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsRef<str> for InvitationToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::InvitationToken;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn generated_token_is_valid() {
+        let token = InvitationToken::new();
+        assert_ok!(InvitationToken::parse(token.as_ref()));
+        assert_ne!(token.as_ref(), InvitationToken::new().as_ref());
+    }
+
+    #[test]
+    fn tokens_of_the_wrong_shape_are_rejected() {
+        assert_err!(InvitationToken::parse("too-short"));
+        assert_err!(InvitationToken::parse("é".repeat(16)));
+    }
+}
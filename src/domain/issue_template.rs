@@ -0,0 +1,147 @@
+// Issue contents can reference per-subscriber variables as `{{ variable }}`.
+static VARIABLES: [&str; 4] = ["name", "email", "unsubscribe_url", "preferences_url"];
+
+pub struct TemplateVariables<'a> {
+    pub name: &'a str,
+    pub email: &'a str,
+    pub unsubscribe_url: &'a str,
+    pub preferences_url: &'a str,
+}
+
+impl<'a> TemplateVariables<'a> {
+    fn get(&self, variable: &str) -> Option<&'a str> {
+        match variable {
+            "name" => Some(self.name),
+            "email" => Some(self.email),
+            "unsubscribe_url" => Some(self.unsubscribe_url),
+            "preferences_url" => Some(self.preferences_url),
+            _ => None,
+        }
+    }
+
+    // Substitutes the known variables; anything else is left untouched.
+    pub fn render(&self, content: &str, escape_html: bool) -> String {
+        let mut rendered = String::with_capacity(content.len());
+        let mut rest = content;
+        while let Some(start) = rest.find("{{") {
+            let after = &rest[start + 2..];
+            let placeholder = after
+                .find("}}")
+                .and_then(|end| self.get(after[..end].trim()).map(|value| (end, value)));
+            match placeholder {
+                Some((end, value)) => {
+                    rendered.push_str(&rest[..start]);
+                    if escape_html {
+                        rendered.push_str(&html_escape(value));
+                    } else {
+                        rendered.push_str(value);
+                    }
+                    rest = &after[end + 2..];
+                }
+                None => {
+                    rendered.push_str(&rest[..start + 2]);
+                    rest = after;
+                }
+            }
+        }
+        rendered.push_str(rest);
+        rendered
+    }
+}
+
+pub fn validate_template(content: &str) -> Result<(), String> {
+    let mut rest = content;
+    while let Some(start) = rest.find("{{") {
+        let after = &rest[start + 2..];
+        let end = after
+            .find("}}")
+            .ok_or_else(|| "A `{{` placeholder is not closed with `}}`.".to_string())?;
+        let variable = after[..end].trim();
+        if !VARIABLES.contains(&variable) {
+            return Err(format!(
+                "`{{{{ {} }}}}` is not a known variable. Use one of: {}.",
+                variable,
+                VARIABLES.join(", ")
+            ));
+        }
+        rest = &after[end + 2..];
+    }
+    Ok(())
+}
+
+fn html_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#x27;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{validate_template, TemplateVariables};
+    use claim::{assert_err, assert_ok};
+
+    fn variables() -> TemplateVariables<'static> {
+        TemplateVariables {
+            name: "Ursula Le Guin",
+            email: "ursula@example.com",
+            unsubscribe_url: "https://example.com/unsubscribe?token=a&b",
+            preferences_url: "https://example.com/preferences?token=a&b",
+        }
+    }
+
+    #[test]
+    fn known_variables_are_valid() {
+        assert_ok!(validate_template(
+            "Hi {{ name }} ({{email}}), leave at {{  unsubscribe_url }} or {{preferences_url}}"
+        ));
+    }
+
+    #[test]
+    fn content_without_variables_is_valid() {
+        assert_ok!(validate_template("Just text with a lone } brace"));
+    }
+
+    #[test]
+    fn unknown_variables_are_rejected() {
+        let error = assert_err!(validate_template("Hi {{ first_name }}"));
+        assert!(error.contains("`{{ first_name }}` is not a known variable"));
+    }
+
+    #[test]
+    fn unclosed_placeholders_are_rejected() {
+        assert_err!(validate_template("Hi {{ name"));
+    }
+
+    #[test]
+    fn variables_are_substituted() {
+        let rendered = variables().render("Hi {{ name }}, this is sent to {{email}}.", false);
+        assert_eq!(
+            rendered,
+            "Hi Ursula Le Guin, this is sent to ursula@example.com."
+        );
+    }
+
+    #[test]
+    fn values_are_escaped_in_html() {
+        let rendered = variables().render(r#"<a href="{{ unsubscribe_url }}">"#, true);
+        assert_eq!(
+            rendered,
+            r#"<a href="https://example.com/unsubscribe?token=a&amp;b">"#
+        );
+    }
+
+    #[test]
+    fn unknown_placeholders_are_left_untouched() {
+        let rendered = variables().render("{{ unknown }} {{ name }} {{", false);
+        assert_eq!(rendered, "{{ unknown }} Ursula Le Guin {{");
+    }
+}
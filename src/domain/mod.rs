@@ -1,8 +1,17 @@
+pub mod invitation_token;
+mod issue_template;
 mod new_subscriber;
+pub mod password_reset_token;
+mod segment_expression;
+mod subscriber_attributes;
 mod subscriber_email;
 mod subscriber_name;
 pub mod subscription_token;
+pub mod unsubscribe_token;
 
+pub use issue_template::{validate_template, TemplateVariables};
 pub use new_subscriber::NewSubscriber;
+pub use segment_expression::SegmentExpression;
+pub use subscriber_attributes::{SubscriberAttributes, SubscriberTags};
 pub use subscriber_email::SubscriberEmail;
 pub use subscriber_name::SubscriberName;
@@ -0,0 +1,63 @@
+use chrono::Duration;
+use rand::distributions::{Alphanumeric, DistString};
+use rand::thread_rng;
+
+#[derive(Debug)]
+pub struct PasswordResetToken(String);
+
+impl PasswordResetToken {
+    const TOKEN_LEN: usize = 32;
+    const VALIDITY_HOURS: i64 = 1;
+
+    pub fn new() -> Self {
+        let token = Alphanumeric.sample_string(&mut thread_rng(), Self::TOKEN_LEN);
+        Self(token)
+    }
+
+    pub fn parse(s: impl AsRef<str>) -> Result<Self, String> {
+        let s = s.as_ref();
+        let valid_token_len = s.len() == Self::TOKEN_LEN;
+        let valid_chars = s.chars().all(|c| c.is_ascii_alphanumeric());
+        if valid_token_len && valid_chars {
+            Ok(Self(s.to_string()))
+        } else {
+            Err(format!("{} is not a valid password reset token.", s))
+        }
+    }
+
+    /// How long a password reset link stays valid after it has been sent.
+    pub fn validity() -> Duration {
+        Duration::hours(Self::VALIDITY_HOURS)
+    }
+}
+
+impl Default for PasswordResetToken {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl AsRef<str> for PasswordResetToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::PasswordResetToken;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn generated_token_is_valid() {
+        let token = PasswordResetToken::new();
+        assert_ok!(PasswordResetToken::parse(token.as_ref()));
+        assert_ne!(token.as_ref(), PasswordResetToken::new().as_ref());
+    }
+
+    #[test]
+    fn tokens_of_the_wrong_shape_are_rejected() {
+        assert_err!(PasswordResetToken::parse("too-short"));
+        assert_err!(PasswordResetToken::parse("é".repeat(16)));
+    }
+}
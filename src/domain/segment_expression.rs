@@ -0,0 +1,177 @@
+use super::subscriber_attributes::is_valid_key;
+
+// The shape of each condition is understood by the `subscriber_matches_segment`
+// SQL function.
+#[derive(Debug, PartialEq, serde::Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum SegmentCondition {
+    HasTag { value: String },
+    LacksTag { value: String },
+    AttributeEquals { key: String, value: String },
+    AttributeNotEquals { key: String, value: String },
+    HasAttribute { key: String },
+}
+
+// Conditions joined with `and`, e.g. `tag = vip and country = "DE" and plan != free`.
+// `<attribute> exists` matches subscribers that have the attribute at all.
+#[derive(Debug)]
+pub struct SegmentExpression(Vec<SegmentCondition>);
+
+#[derive(Debug, PartialEq)]
+enum Token {
+    Word(String),
+    Quoted(String),
+    Equals,
+    NotEquals,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => {}
+            '=' => tokens.push(Token::Equals),
+            '!' if chars.peek() == Some(&'=') => {
+                chars.next();
+                tokens.push(Token::NotEquals);
+            }
+            '"' => {
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => value.push(c),
+                        None => return Err("A quoted value is not closed with `\"`.".into()),
+                    }
+                }
+                tokens.push(Token::Quoted(value));
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() || c == '=' || c == '!' || c == '"' {
+                        break;
+                    }
+                    word.push(c);
+                    chars.next();
+                }
+                tokens.push(Token::Word(word));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+impl SegmentExpression {
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut tokens = tokenize(s)?.into_iter().peekable();
+        if tokens.peek().is_none() {
+            return Err("The segment expression is empty.".into());
+        }
+        let mut conditions = Vec::new();
+        loop {
+            let key = match tokens.next() {
+                Some(Token::Word(key)) if is_valid_key(&key) => key,
+                _ => return Err("Expected `tag` or an attribute name.".into()),
+            };
+            let condition = match (tokens.next(), key == "tag") {
+                (Some(Token::Equals), true) => SegmentCondition::HasTag {
+                    value: parse_value(tokens.next(), &key)?.to_lowercase(),
+                },
+                (Some(Token::NotEquals), true) => SegmentCondition::LacksTag {
+                    value: parse_value(tokens.next(), &key)?.to_lowercase(),
+                },
+                (Some(Token::Equals), false) => SegmentCondition::AttributeEquals {
+                    value: parse_value(tokens.next(), &key)?,
+                    key,
+                },
+                (Some(Token::NotEquals), false) => SegmentCondition::AttributeNotEquals {
+                    value: parse_value(tokens.next(), &key)?,
+                    key,
+                },
+                (Some(Token::Word(w)), false) if w.eq_ignore_ascii_case("exists") => {
+                    SegmentCondition::HasAttribute { key }
+                }
+                _ => return Err(format!("Expected `=`, `!=` or `exists` after `{}`.", key)),
+            };
+            conditions.push(condition);
+            match tokens.next() {
+                None => break,
+                Some(Token::Word(w)) if w.eq_ignore_ascii_case("and") => {}
+                _ => return Err("Conditions must be joined with `and`.".into()),
+            }
+        }
+        Ok(Self(conditions))
+    }
+
+    // The conditions as stored in the `segments.conditions` column.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).expect("Segment conditions always serialize")
+    }
+}
+
+fn parse_value(token: Option<Token>, key: &str) -> Result<String, String> {
+    match token {
+        Some(Token::Word(value)) if !value.eq_ignore_ascii_case("and") => Ok(value),
+        Some(Token::Quoted(value)) => Ok(value),
+        _ => Err(format!("Expected a value for `{}`.", key)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SegmentCondition, SegmentExpression};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn conditions_are_joined_with_and() {
+        let expression = assert_ok!(SegmentExpression::parse(
+            r#"tag = VIP AND country="United Kingdom" and plan != free and signup_source exists"#
+        ));
+        assert_eq!(
+            expression.0,
+            vec![
+                SegmentCondition::HasTag {
+                    value: "vip".into()
+                },
+                SegmentCondition::AttributeEquals {
+                    key: "country".into(),
+                    value: "United Kingdom".into()
+                },
+                SegmentCondition::AttributeNotEquals {
+                    key: "plan".into(),
+                    value: "free".into()
+                },
+                SegmentCondition::HasAttribute {
+                    key: "signup_source".into()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn conditions_are_stored_as_tagged_json() {
+        let expression = assert_ok!(SegmentExpression::parse("tag != beta"));
+        assert_eq!(
+            expression.to_json(),
+            r#"[{"kind":"lacks_tag","value":"beta"}]"#
+        );
+    }
+
+    #[test]
+    fn malformed_expressions_are_rejected() {
+        for input in [
+            "",
+            "tag",
+            "tag exists",
+            "country =",
+            "country = DE or plan = pro",
+            "country = DE and",
+            r#"country = "DE"#,
+            "Country = DE",
+        ] {
+            assert_err!(SegmentExpression::parse(input), "{} was accepted", input);
+        }
+    }
+}
@@ -0,0 +1,113 @@
+use std::collections::BTreeMap;
+
+// Tags and attribute keys show up in segment expressions, so they are kept to a
+// small alphabet.
+pub(crate) fn is_valid_key(key: &str) -> bool {
+    !key.is_empty()
+        && key.len() <= 50
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_' || c == '-')
+}
+
+#[derive(Debug)]
+pub struct SubscriberTags(Vec<String>);
+
+impl SubscriberTags {
+    // Parses a comma-separated list of tags; case is ignored and duplicates are dropped.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut tags = Vec::new();
+        for tag in s.split(',').map(|t| t.trim().to_lowercase()) {
+            if tag.is_empty() || tags.contains(&tag) {
+                continue;
+            }
+            if !is_valid_key(&tag) {
+                return Err(format!(
+                    "`{}` is not a valid tag: use lowercase letters, digits, `_` and `-`.",
+                    tag
+                ));
+            }
+            tags.push(tag);
+        }
+        tags.sort();
+        Ok(Self(tags))
+    }
+}
+
+impl AsRef<[String]> for SubscriberTags {
+    fn as_ref(&self) -> &[String] {
+        &self.0
+    }
+}
+
+#[derive(Debug)]
+pub struct SubscriberAttributes(BTreeMap<String, String>);
+
+impl SubscriberAttributes {
+    // Parses one `key=value` pair per line; blank lines are ignored.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let mut attributes = BTreeMap::new();
+        for line in s.lines().map(str::trim).filter(|l| !l.is_empty()) {
+            let (key, value) = line
+                .split_once('=')
+                .ok_or_else(|| format!("`{}` is not a `key=value` pair.", line))?;
+            let (key, value) = (key.trim(), value.trim());
+            if !is_valid_key(key) {
+                return Err(format!(
+                    "`{}` is not a valid attribute name: use lowercase letters, digits, `_` and `-`.",
+                    key
+                ));
+            }
+            if value.is_empty() {
+                return Err(format!("The attribute `{}` has no value.", key));
+            }
+            if attributes
+                .insert(key.to_string(), value.to_string())
+                .is_some()
+            {
+                return Err(format!("The attribute `{}` is set twice.", key));
+            }
+        }
+        Ok(Self(attributes))
+    }
+
+    // The JSON object stored in the `attributes` column.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).expect("A map of strings always serializes")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SubscriberAttributes, SubscriberTags};
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn tags_are_normalised() {
+        let tags = assert_ok!(SubscriberTags::parse(" VIP, beta ,,vip"));
+        assert_eq!(tags.as_ref(), ["beta", "vip"]);
+    }
+
+    #[test]
+    fn tags_with_spaces_are_rejected() {
+        assert_err!(SubscriberTags::parse("early adopter"));
+    }
+
+    #[test]
+    fn attributes_are_read_one_per_line() {
+        let attributes = assert_ok!(SubscriberAttributes::parse(
+            "country = DE\n\nsignup_source=landing page"
+        ));
+        assert_eq!(
+            attributes.to_json(),
+            r#"{"country":"DE","signup_source":"landing page"}"#
+        );
+    }
+
+    #[test]
+    fn invalid_attributes_are_rejected() {
+        for input in ["country", "Country=DE", "country=", "plan=a\nplan=b"] {
+            assert_err!(SubscriberAttributes::parse(input));
+        }
+    }
+}
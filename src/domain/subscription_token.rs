@@ -1,3 +1,4 @@
+use chrono::Duration;
 use rand::distributions::{Alphanumeric, DistString};
 use rand::thread_rng;
 
@@ -6,6 +7,8 @@ pub struct SubscriptionToken(String);
 
 impl SubscriptionToken {
     const TOKEN_LEN: usize = 25;
+    const VALIDITY_HOURS: i64 = 24;
+
     pub fn new() -> Self {
         let token = Alphanumeric.sample_string(&mut thread_rng(), Self::TOKEN_LEN);
         Self(token)
@@ -21,6 +24,11 @@ impl SubscriptionToken {
             Err(format!("{} is not a valid subscription token.", s))
         }
     }
+
+    /// How long a confirmation link stays valid after it has been issued.
+    pub fn validity() -> Duration {
+        Duration::hours(Self::VALIDITY_HOURS)
+    }
 }
 
 impl AsRef<str> for SubscriptionToken {
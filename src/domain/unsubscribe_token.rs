@@ -0,0 +1,101 @@
+use hmac::{Hmac, Mac};
+use secrecy::{ExposeSecret, Secret};
+use sha2::Sha256;
+use uuid::Uuid;
+
+/// A stateless token that lets a subscriber manage their subscription or leave the newsletter.
+/// It is made of the subscriber id and an HMAC tag computed over it,
+/// so nothing has to be stored to verify it later.
+#[derive(Debug)]
+pub struct UnsubscribeToken {
+    subscriber_id: Uuid,
+    tag: Vec<u8>,
+}
+
+impl UnsubscribeToken {
+    pub fn new(subscriber_id: Uuid, secret: &Secret<String>) -> Self {
+        let tag = Self::mac(subscriber_id, secret)
+            .finalize()
+            .into_bytes()
+            .to_vec();
+        Self { subscriber_id, tag }
+    }
+
+    pub fn parse(s: impl AsRef<str>) -> Result<Self, String> {
+        let s = s.as_ref();
+        let error = || format!("{} is not a valid unsubscribe token.", s);
+        let (subscriber_id, tag) = s.split_once('.').ok_or_else(error)?;
+        let subscriber_id = Uuid::parse_str(subscriber_id).map_err(|_| error())?;
+        let tag = hex::decode(tag).map_err(|_| error())?;
+        Ok(Self { subscriber_id, tag })
+    }
+
+    /// Returns the subscriber id if the token was signed with `secret`.
+    pub fn verify(&self, secret: &Secret<String>) -> Option<Uuid> {
+        Self::mac(self.subscriber_id, secret)
+            .verify_slice(&self.tag)
+            .ok()
+            .map(|_| self.subscriber_id)
+    }
+
+    fn mac(subscriber_id: Uuid, secret: &Secret<String>) -> Hmac<Sha256> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.expose_secret().as_bytes()).unwrap();
+        mac.update(b"unsubscribe:");
+        mac.update(subscriber_id.as_bytes());
+        mac
+    }
+}
+
+impl std::fmt::Display for UnsubscribeToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}.{}", self.subscriber_id, hex::encode(&self.tag))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::UnsubscribeToken;
+    use claim::{assert_err, assert_none, assert_ok};
+    use secrecy::Secret;
+    use uuid::Uuid;
+
+    fn secret() -> Secret<String> {
+        Secret::new("a-secret-used-to-sign-unsubscribe-tokens".to_string())
+    }
+
+    #[test]
+    fn generated_token_is_verified() {
+        let subscriber_id = Uuid::new_v4();
+        let token = UnsubscribeToken::new(subscriber_id, &secret()).to_string();
+
+        let parsed = assert_ok!(UnsubscribeToken::parse(token));
+        assert_eq!(parsed.verify(&secret()), Some(subscriber_id));
+    }
+
+    #[test]
+    fn token_signed_with_another_secret_is_rejected() {
+        let other_secret = Secret::new("another-secret".to_string());
+        let token = UnsubscribeToken::new(Uuid::new_v4(), &other_secret).to_string();
+
+        let parsed = assert_ok!(UnsubscribeToken::parse(token));
+        assert_none!(parsed.verify(&secret()));
+    }
+
+    #[test]
+    fn token_for_another_subscriber_is_rejected() {
+        let token = UnsubscribeToken::new(Uuid::new_v4(), &secret()).to_string();
+        let (_, tag) = token.split_once('.').unwrap();
+        let forged = format!("{}.{}", Uuid::new_v4(), tag);
+
+        let parsed = assert_ok!(UnsubscribeToken::parse(forged));
+        assert_none!(parsed.verify(&secret()));
+    }
+
+    #[test]
+    fn ill_formatted_tokens_are_rejected() {
+        for token in ["", "no-separator", "not-a-uuid.abcd", "{}.not-hex"] {
+            let token = token.replace("{}", &Uuid::new_v4().to_string());
+            assert_err!(UnsubscribeToken::parse(token));
+        }
+    }
+}
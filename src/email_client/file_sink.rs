@@ -0,0 +1,75 @@
+use super::{build_message, Email, EmailTransport};
+use crate::domain::SubscriberEmail;
+use lettre::{AsyncFileTransport, AsyncTransport, Tokio1Executor};
+use std::path::Path;
+
+// Writes every email as an .eml file in a directory instead of sending it.
+pub struct FileSinkTransport {
+    sink: AsyncFileTransport<Tokio1Executor>,
+}
+
+impl FileSinkTransport {
+    pub fn new(directory: impl AsRef<Path>) -> Result<Self, std::io::Error> {
+        std::fs::create_dir_all(directory.as_ref())?;
+        Ok(Self {
+            sink: AsyncFileTransport::new(directory),
+        })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for FileSinkTransport {
+    async fn send(&self, sender: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let message = build_message(sender, email)?;
+        self.sink.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, EmailHeader, FileSinkTransport};
+    use claim::assert_ok;
+
+    #[tokio::test]
+    async fn emails_are_written_as_eml_files_in_the_directory() {
+        // Arrange
+        let directory = std::env::temp_dir().join(uuid::Uuid::new_v4().to_string());
+        let transport = FileSinkTransport::new(&directory).unwrap();
+        let sender = SubscriberEmail::parse("sender@example.com").unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com").unwrap();
+        let email_client = EmailClient::new(sender, transport);
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click",
+        }];
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                &recipient,
+                "Newsletter title",
+                "<p>Newsletter body as HTML</p>",
+                "Newsletter body as plain text",
+                &headers,
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let files = std::fs::read_dir(&directory)
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect::<Vec<_>>();
+        assert_eq!(files.len(), 1);
+        assert_eq!(files[0].extension().unwrap(), "eml");
+        let eml = std::fs::read_to_string(&files[0]).unwrap();
+        assert!(eml.contains("To: recipient@example.com"));
+        assert!(eml.contains("Subject: Newsletter title"));
+        assert!(eml.contains("List-Unsubscribe-Post: List-Unsubscribe=One-Click"));
+        assert!(eml.contains("Newsletter body as plain text"));
+        assert!(eml.contains("<p>Newsletter body as HTML</p>"));
+        std::fs::remove_dir_all(directory).unwrap();
+    }
+}
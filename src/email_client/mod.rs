@@ -0,0 +1,114 @@
+mod file_sink;
+mod postmark;
+mod smtp;
+
+use crate::domain::SubscriberEmail;
+use lettre::message::header::{HeaderName, HeaderValue};
+use lettre::message::{Mailbox, MultiPart};
+use lettre::Message;
+
+pub use file_sink::FileSinkTransport;
+pub use postmark::PostmarkTransport;
+pub use smtp::SmtpTransport;
+
+pub struct Email<'a> {
+    pub recipient: &'a SubscriberEmail,
+    pub subject: &'a str,
+    pub html_content: &'a str,
+    pub text_content: &'a str,
+    pub headers: &'a [EmailHeader<'a>],
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct EmailHeader<'a> {
+    pub name: &'a str,
+    pub value: &'a str,
+}
+
+// The outcome of each message of a batch, in the same order as the batch.
+pub type BatchOutcome = Vec<Result<(), anyhow::Error>>;
+
+// A message the provider will never accept for this recipient, e.g. an invalid or inactive
+// address. Transports wrap such errors in it so that callers do not retry them.
+#[derive(thiserror::Error, Debug)]
+#[error("{0}")]
+pub struct PermanentFailure(pub String);
+
+#[async_trait::async_trait]
+pub trait EmailTransport: Send + Sync {
+    async fn send(&self, sender: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error>;
+
+    // Transports without a batch API send the messages one at a time.
+    async fn send_batch(
+        &self,
+        sender: &SubscriberEmail,
+        emails: &[Email<'_>],
+    ) -> Result<BatchOutcome, anyhow::Error> {
+        let mut outcome = Vec::with_capacity(emails.len());
+        for email in emails {
+            outcome.push(self.send(sender, email).await);
+        }
+        Ok(outcome)
+    }
+}
+
+pub struct EmailClient {
+    sender: SubscriberEmail,
+    transport: Box<dyn EmailTransport>,
+}
+
+impl EmailClient {
+    pub fn new(sender: SubscriberEmail, transport: impl EmailTransport + 'static) -> Self {
+        Self {
+            sender,
+            transport: Box::new(transport),
+        }
+    }
+
+    pub async fn send_email(
+        &self,
+        recipient: &SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        headers: &[EmailHeader<'_>],
+    ) -> Result<(), anyhow::Error> {
+        let email = Email {
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            headers,
+        };
+        self.transport.send(&self.sender, &email).await
+    }
+
+    // A failure of the whole batch is returned as an error, while failures of
+    // single messages are reported in the outcome.
+    pub async fn send_email_batch(
+        &self,
+        emails: &[Email<'_>],
+    ) -> Result<BatchOutcome, anyhow::Error> {
+        self.transport.send_batch(&self.sender, emails).await
+    }
+}
+
+// Builds a MIME message for the transports that speak RFC 5322 rather than a JSON API.
+fn build_message(sender: &SubscriberEmail, email: &Email<'_>) -> Result<Message, anyhow::Error> {
+    let mut message = Message::builder()
+        .from(sender.as_ref().parse::<Mailbox>()?)
+        .to(email.recipient.as_ref().parse::<Mailbox>()?)
+        .subject(email.subject)
+        .multipart(MultiPart::alternative_plain_html(
+            email.text_content.to_string(),
+            email.html_content.to_string(),
+        ))?;
+    for header in email.headers {
+        let name = HeaderName::new_from_ascii(header.name.to_string())?;
+        message
+            .headers_mut()
+            .insert_raw(HeaderValue::new(name, header.value.to_string()));
+    }
+    Ok(message)
+}
@@ -0,0 +1,513 @@
+use super::{BatchOutcome, Email, EmailHeader, EmailTransport, PermanentFailure};
+use crate::domain::SubscriberEmail;
+use reqwest::{Client, Url};
+use secrecy::{ExposeSecret, Secret};
+
+// Postmark rejects batches with more than 500 messages.
+const MAX_BATCH_SIZE: usize = 500;
+// Error codes for messages that fail the same way however often they are sent: an invalid
+// request (e.g. a malformed recipient) and a recipient marked inactive after a hard bounce
+// or a spam complaint.
+const PERMANENT_ERROR_CODES: [i64; 2] = [300, 406];
+
+pub struct PostmarkTransport {
+    http_client: Client,
+    base_url: Url,
+    authorization_token: Secret<String>,
+}
+
+impl PostmarkTransport {
+    pub fn new(
+        base_url: Url,
+        authorization_token: Secret<String>,
+        timeout: std::time::Duration,
+    ) -> Self {
+        let http_client = Client::builder().timeout(timeout).build().unwrap();
+        Self {
+            http_client,
+            base_url,
+            authorization_token,
+        }
+    }
+
+    // Sends up to `MAX_BATCH_SIZE` messages with a single request.
+    async fn send_chunk(
+        &self,
+        sender: &SubscriberEmail,
+        chunk: &[Email<'_>],
+    ) -> Result<BatchOutcome, anyhow::Error> {
+        let url = self.base_url.join("email/batch").unwrap();
+        let request_body = chunk
+            .iter()
+            .map(|email| SendEmailRequest::new(sender, email))
+            .collect::<Vec<_>>();
+        let responses: Vec<SendEmailResponse> = self
+            .http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        if responses.len() != chunk.len() {
+            anyhow::bail!(
+                "Postmark returned {} results for a batch of {} messages.",
+                responses.len(),
+                chunk.len()
+            );
+        }
+        Ok(responses
+            .into_iter()
+            .map(|response| {
+                let error = format!(
+                    "Postmark error {}: {}",
+                    response.error_code, response.message
+                );
+                match response.error_code {
+                    0 => Ok(()),
+                    code if PERMANENT_ERROR_CODES.contains(&code) => {
+                        Err(PermanentFailure(error).into())
+                    }
+                    _ => Err(anyhow::anyhow!(error)),
+                }
+            })
+            .collect())
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for PostmarkTransport {
+    async fn send(&self, sender: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let url = self.base_url.join("email").unwrap();
+        let request_body = SendEmailRequest::new(sender, email);
+        self.http_client
+            .post(url)
+            .header(
+                "X-Postmark-Server-Token",
+                self.authorization_token.expose_secret(),
+            )
+            .json(&request_body)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
+    async fn send_batch(
+        &self,
+        sender: &SubscriberEmail,
+        emails: &[Email<'_>],
+    ) -> Result<BatchOutcome, anyhow::Error> {
+        let mut outcome = Vec::with_capacity(emails.len());
+        for chunk in emails.chunks(MAX_BATCH_SIZE) {
+            match self.send_chunk(sender, chunk).await {
+                Ok(results) => outcome.extend(results),
+                Err(e) if outcome.is_empty() => return Err(e),
+                // The chunks Postmark already accepted must not be sent again: only the
+                // messages that have not gone out are reported as failed.
+                Err(e) => {
+                    let message = format!("{:#}", e);
+                    outcome.resize_with(emails.len(), || Err(anyhow::anyhow!(message.clone())));
+                    break;
+                }
+            }
+        }
+        Ok(outcome)
+    }
+}
+
+#[derive(serde::Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailRequest<'a> {
+    from: &'a str,
+    to: &'a str,
+    subject: &'a str,
+    html_body: &'a str,
+    text_body: &'a str,
+    #[serde(skip_serializing_if = "<[_]>::is_empty")]
+    headers: &'a [EmailHeader<'a>],
+}
+
+impl<'a> SendEmailRequest<'a> {
+    fn new(sender: &'a SubscriberEmail, email: &'a Email<'a>) -> Self {
+        Self {
+            from: sender.as_ref(),
+            to: email.recipient.as_ref(),
+            subject: email.subject,
+            html_body: email.html_content,
+            text_body: email.text_content,
+            headers: email.headers,
+        }
+    }
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailResponse {
+    error_code: i64,
+    message: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{Email, EmailClient, EmailHeader, PostmarkTransport};
+    use claim::{assert_err, assert_ok};
+    use fake::faker::internet::en::SafeEmail;
+    use fake::faker::lorem::en::{Paragraph, Sentence};
+    use fake::{Fake, Faker};
+    use secrecy::Secret;
+    use wiremock::matchers::{any, header, header_exists, method, path};
+    use wiremock::{Mock, MockServer, Request, ResponseTemplate};
+
+    struct SendEmailBodyMatcher;
+
+    impl wiremock::Match for SendEmailBodyMatcher {
+        fn matches(&self, request: &Request) -> bool {
+            let result: Result<serde_json::Value, _> = serde_json::from_slice(&request.body);
+            if let Ok(body) = result {
+                body.get("From").is_some()
+                    && body.get("To").is_some()
+                    && body.get("Subject").is_some()
+                    && body.get("HtmlBody").is_some()
+                    && body.get("TextBody").is_some()
+            } else {
+                false
+            }
+        }
+    }
+
+    fn subject() -> String {
+        Sentence(1..2).fake()
+    }
+
+    fn content() -> String {
+        Paragraph(1..10).fake()
+    }
+
+    fn email() -> SubscriberEmail {
+        SubscriberEmail::parse(SafeEmail().fake::<String>()).unwrap()
+    }
+
+    fn email_client(base_url: String) -> EmailClient {
+        let base_url = reqwest::Url::parse(&base_url).unwrap();
+        let transport = PostmarkTransport::new(
+            base_url,
+            Secret::new(Faker.fake()),
+            std::time::Duration::from_millis(200),
+        );
+        EmailClient::new(email(), transport)
+    }
+
+    #[tokio::test]
+    async fn send_email_sends_the_expected_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(header("Content-Type", "application/json"))
+            .and(path("/email"))
+            .and(method("POST"))
+            .and(SendEmailBodyMatcher)
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+    }
+
+    #[tokio::test]
+    async fn send_email_includes_custom_headers_in_the_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        let headers = [EmailHeader {
+            name: "List-Unsubscribe-Post",
+            value: "List-Unsubscribe=One-Click",
+        }];
+
+        // Act
+        let _ = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &headers)
+            .await;
+
+        // Assert
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Headers"],
+            serde_json::json!([{
+                "Name": "List-Unsubscribe-Post",
+                "Value": "List-Unsubscribe=One-Click"
+            }])
+        );
+    }
+
+    #[tokio::test]
+    async fn send_email_succeeds_if_the_server_returns_200() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            // Not a 200 anymore!
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    #[tokio::test]
+    async fn send_email_times_out_if_the_server_takes_too_long() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        let response = ResponseTemplate::new(200).set_delay(std::time::Duration::from_secs(180));
+        Mock::given(any())
+            .respond_with(response)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(&email(), &subject(), &content(), &content(), &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+
+    // Answers a batch request with one result per message, failing the ones sent to `bad_recipient`.
+    fn batch_responder(bad_recipient: Option<String>) -> impl Fn(&Request) -> ResponseTemplate {
+        move |request: &Request| {
+            let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+            let results = body
+                .iter()
+                .map(|message| {
+                    if Some(message["To"].as_str().unwrap()) == bad_recipient.as_deref() {
+                        serde_json::json!({"ErrorCode": 300, "Message": "Invalid 'To' address."})
+                    } else {
+                        serde_json::json!({"ErrorCode": 0, "Message": "OK"})
+                    }
+                })
+                .collect::<Vec<_>>();
+            ResponseTemplate::new(200).set_body_json(results)
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_sends_all_messages_in_one_request() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email()];
+        let (subject, content) = (subject(), content());
+        let emails = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                headers: &[],
+            })
+            .collect::<Vec<_>>();
+
+        Mock::given(header_exists("X-Postmark-Server-Token"))
+            .and(path("/email/batch"))
+            .and(method("POST"))
+            .respond_with(batch_responder(None))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        let outcome = assert_ok!(outcome);
+        assert_eq!(outcome.len(), 2);
+        assert!(outcome.iter().all(|result| result.is_ok()));
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body[0]["To"], recipients[0].as_ref());
+        assert_eq!(body[1]["To"], recipients[1].as_ref());
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_reports_failures_per_message() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipients = [email(), email(), email()];
+        let (subject, content) = (subject(), content());
+        let emails = recipients
+            .iter()
+            .map(|recipient| Email {
+                recipient,
+                subject: &subject,
+                html_content: &content,
+                text_content: &content,
+                headers: &[],
+            })
+            .collect::<Vec<_>>();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(batch_responder(Some(recipients[1].to_string())))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        let outcome = assert_ok!(outcome);
+        assert_ok!(&outcome[0]);
+        assert_err!(&outcome[1]);
+        assert_ok!(&outcome[2]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_splits_batches_larger_than_500_messages() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let emails = (0..501)
+            .map(|_| Email {
+                recipient: &recipient,
+                subject: "Subject",
+                html_content: "<p>Body</p>",
+                text_content: "Body",
+                headers: &[],
+            })
+            .collect::<Vec<_>>();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(batch_responder(None))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        assert_eq!(assert_ok!(outcome).len(), 501);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_keeps_the_outcome_of_chunks_already_sent() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let emails = (0..501)
+            .map(|_| Email {
+                recipient: &recipient,
+                subject: "Subject",
+                html_content: "<p>Body</p>",
+                text_content: "Body",
+                headers: &[],
+            })
+            .collect::<Vec<_>>();
+
+        Mock::given(path("/email/batch"))
+            .respond_with(batch_responder(None))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+        Mock::given(path("/email/batch"))
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        let outcome = assert_ok!(outcome);
+        assert_eq!(outcome.len(), 501);
+        assert!(outcome[..500].iter().all(Result::is_ok));
+        assert_err!(&outcome[500]);
+    }
+
+    #[tokio::test]
+    async fn send_email_batch_fails_if_the_server_returns_500() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+        let recipient = email();
+        let emails = [Email {
+            recipient: &recipient,
+            subject: "Subject",
+            html_content: "<p>Body</p>",
+            text_content: "Body",
+            headers: &[],
+        }];
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client.send_email_batch(&emails).await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
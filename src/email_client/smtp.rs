@@ -0,0 +1,79 @@
+use super::{build_message, Email, EmailTransport};
+use crate::domain::SubscriberEmail;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use secrecy::{ExposeSecret, Secret};
+
+pub struct SmtpTransport {
+    mailer: AsyncSmtpTransport<Tokio1Executor>,
+}
+
+impl SmtpTransport {
+    // Without `require_tls` the connection is in plain text: only meant for local relays (e.g. MailHog).
+    pub fn new(
+        host: &str,
+        port: u16,
+        credentials: Option<(String, Secret<String>)>,
+        require_tls: bool,
+        timeout: std::time::Duration,
+    ) -> Result<Self, lettre::transport::smtp::Error> {
+        let mut builder = if require_tls {
+            AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(host)?
+        } else {
+            AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(host)
+        };
+        if let Some((username, password)) = credentials {
+            builder = builder.credentials(Credentials::new(
+                username,
+                password.expose_secret().to_owned(),
+            ));
+        }
+        let mailer = builder.port(port).timeout(Some(timeout)).build();
+        Ok(Self { mailer })
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailTransport for SmtpTransport {
+    async fn send(&self, sender: &SubscriberEmail, email: &Email<'_>) -> Result<(), anyhow::Error> {
+        let message = build_message(sender, email)?;
+        self.mailer.send(message).await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriberEmail;
+    use crate::email_client::{EmailClient, SmtpTransport};
+    use claim::assert_err;
+
+    #[tokio::test]
+    async fn send_email_fails_if_the_relay_is_unreachable() {
+        // Arrange
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let transport = SmtpTransport::new(
+            "127.0.0.1",
+            port,
+            None,
+            false,
+            std::time::Duration::from_millis(200),
+        )
+        .unwrap();
+        let sender = SubscriberEmail::parse("sender@example.com").unwrap();
+        let recipient = SubscriberEmail::parse("recipient@example.com").unwrap();
+        let email_client = EmailClient::new(sender, transport);
+
+        // Act
+        let outcome = email_client
+            .send_email(&recipient, "Subject", "<p>Body</p>", "Body", &[])
+            .await;
+
+        // Assert
+        assert_err!(outcome);
+    }
+}
@@ -1,16 +1,24 @@
-use crate::configuration::Settings;
-use crate::domain::SubscriberEmail;
-use crate::email_client::EmailClient;
+use crate::configuration::{DeliveryWorkerSettings, Settings};
+use crate::domain::unsubscribe_token::UnsubscribeToken;
+use crate::domain::{SubscriberEmail, TemplateVariables};
+use crate::email_client::{Email, EmailClient, EmailHeader, PermanentFailure};
 use crate::startup::get_connection_pool;
 use crate::util::error_chain_fmt;
 use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use secrecy::Secret;
 use sqlx::{PgPool, Postgres, Transaction};
+use std::collections::hash_map::Entry;
+use std::collections::HashMap;
 use std::time::Duration;
+use tokio::time::Instant;
 use tracing::{field::display, Span};
 use uuid::Uuid;
 
 type PgTransaction = Transaction<'static, Postgres>;
 
+#[derive(Debug)]
 pub enum ExecutionOutcome {
     TaskCompleted,
     EmptyQueue,
@@ -41,6 +49,9 @@ impl std::fmt::Debug for ExecutionError {
 pub async fn try_execute_task(
     pool: &PgPool,
     email_client: &EmailClient,
+    base_url: &Url,
+    hmac_secret: &Secret<String>,
+    settings: &DeliveryWorkerSettings,
 ) -> Result<ExecutionOutcome, ExecutionError> {
     let task = dequeue_task(pool)
         .await
@@ -50,7 +61,8 @@ pub async fn try_execute_task(
         return Ok(ExecutionOutcome::EmptyQueue);
     }
     // we already handled the case when task is None by early return, so it's okay to unwrap.
-    let (transaction, issue_id, email) = task.unwrap();
+    let (mut transaction, task) = task.unwrap();
+    let (issue_id, email) = (task.newsletter_issue_id, task.subscriber_email.clone());
     Span::current()
         .record("newsletter_issue_id", &display(issue_id))
         .record("subscriber_email", &display(&email));
@@ -61,23 +73,41 @@ pub async fn try_execute_task(
                 .await
                 .context("Failed to retrieve issue from database.")
                 .map_err(ExecutionError::Transient)?;
+            let content = IssueContent::new(&issue, &task, base_url, hmac_secret);
             if let Err(e) = email_client
                 .send_email(
                     &email,
                     &issue.title,
-                    &issue.html_content,
-                    &issue.text_content,
+                    &content.html,
+                    &content.text,
+                    &content.headers(),
                 )
                 .await
             {
                 tracing::error!(
                     error.cause_chain = ?e,
                     error.message = %e,
-                    "Failed to deliver issue to a confirmed subscriber. Skipping.",
+                    n_retries = task.n_retries,
+                    "Failed to deliver issue to a confirmed subscriber.",
                 );
 
+                let gave_up = fail_task(&mut transaction, &task, &e.to_string(), settings)
+                    .await
+                    .context("Failed to record the failed delivery.")
+                    .map_err(ExecutionError::Transient)?;
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to commit the failed delivery.")
+                    .map_err(ExecutionError::Transient)?;
+
+                if gave_up {
+                    return Err(ExecutionError::Fatal(
+                        e.context("Failed to send email, giving up."),
+                    ));
+                }
                 return Err(ExecutionError::Transient(
-                    anyhow::anyhow!(e).context("Failed to send email."),
+                    e.context("Failed to send email."),
                 ));
             }
         }
@@ -88,9 +118,20 @@ pub async fn try_execute_task(
                 "Skipping a confirmed subscriber. Their stored contact details are invalid.",
             );
 
-            delete_task(transaction, issue_id, &email)
+            complete_task(
+                &mut transaction,
+                issue_id,
+                &email,
+                DeliveryOutcome::Skipped,
+                Some(&e),
+            )
+            .await
+            .context("Failed to complete task.")
+            .map_err(ExecutionError::Transient)?;
+            transaction
+                .commit()
                 .await
-                .context("Failed to delete task.")
+                .context("Failed to complete task.")
                 .map_err(ExecutionError::Transient)?;
 
             tracing::info!("Deleted the task with invalid contact details from queue.");
@@ -100,13 +141,179 @@ pub async fn try_execute_task(
             ));
         }
     }
-    delete_task(transaction, issue_id, &email)
+    complete_task(
+        &mut transaction,
+        issue_id,
+        &email,
+        DeliveryOutcome::Delivered,
+        None,
+    )
+    .await
+    .context("Failed to complete task.")
+    .map_err(ExecutionError::Transient)?;
+    transaction
+        .commit()
         .await
-        .context("Failed to delete task.")
+        .context("Failed to complete task.")
         .map_err(ExecutionError::Transient)?;
     Ok(ExecutionOutcome::TaskCompleted)
 }
 
+// Sends up to `batch_size` queued deliveries with a single call to the email API.
+// Every message is recorded on its own: a rejected address is retried (or dead-lettered)
+// without affecting the rest of the batch.
+#[tracing::instrument(skip(pool, email_client, base_url, hmac_secret, settings), fields(n_tasks=tracing::field::Empty), err(Debug))]
+pub async fn try_execute_batch(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &Url,
+    hmac_secret: &Secret<String>,
+    settings: &DeliveryWorkerSettings,
+    batch_size: u16,
+) -> Result<ExecutionOutcome, ExecutionError> {
+    let (mut transaction, tasks) = dequeue_tasks(pool, batch_size)
+        .await
+        .context("Failed to dequeue tasks.")
+        .map_err(ExecutionError::Transient)?;
+    if tasks.is_empty() {
+        return Ok(ExecutionOutcome::EmptyQueue);
+    }
+    Span::current().record("n_tasks", &tasks.len());
+
+    let mut issues = HashMap::new();
+    for task in &tasks {
+        if let Entry::Vacant(entry) = issues.entry(task.newsletter_issue_id) {
+            let issue = get_issue(pool, task.newsletter_issue_id)
+                .await
+                .context("Failed to retrieve issue from database.")
+                .map_err(ExecutionError::Transient)?;
+            entry.insert(issue);
+        }
+    }
+
+    let mut deliverable = Vec::with_capacity(tasks.len());
+    for task in tasks {
+        match SubscriberEmail::parse(task.subscriber_email.clone()) {
+            Ok(email) => deliverable.push((task, email)),
+            Err(e) => {
+                tracing::error!(
+                    error.message = %e,
+                    subscriber_email = %task.subscriber_email,
+                    "Skipping a confirmed subscriber. Their stored contact details are invalid.",
+                );
+                complete_task(
+                    &mut transaction,
+                    task.newsletter_issue_id,
+                    &task.subscriber_email,
+                    DeliveryOutcome::Skipped,
+                    Some(&e),
+                )
+                .await
+                .context("Failed to complete task.")
+                .map_err(ExecutionError::Transient)?;
+            }
+        }
+    }
+
+    let contents = deliverable
+        .iter()
+        .map(|(task, _)| {
+            let issue = &issues[&task.newsletter_issue_id];
+            IssueContent::new(issue, task, base_url, hmac_secret)
+        })
+        .collect::<Vec<_>>();
+    let headers = contents.iter().map(|c| c.headers()).collect::<Vec<_>>();
+    let emails = deliverable
+        .iter()
+        .zip(&contents)
+        .zip(&headers)
+        .map(|(((task, email), content), headers)| Email {
+            recipient: email,
+            subject: &issues[&task.newsletter_issue_id].title,
+            html_content: &content.html,
+            text_content: &content.text,
+            headers,
+        })
+        .collect::<Vec<_>>();
+
+    let batch_error = match email_client.send_email_batch(&emails).await {
+        Ok(outcome) => {
+            for ((task, _), result) in deliverable.iter().zip(outcome) {
+                match result {
+                    Ok(()) => complete_task(
+                        &mut transaction,
+                        task.newsletter_issue_id,
+                        &task.subscriber_email,
+                        DeliveryOutcome::Delivered,
+                        None,
+                    )
+                    .await
+                    .context("Failed to complete task.")
+                    .map_err(ExecutionError::Transient)?,
+                    // Retrying a message the provider will never accept only delays the
+                    // failure, so it goes straight to the delivery failures.
+                    Err(e) if e.is::<PermanentFailure>() => {
+                        tracing::error!(
+                            error.message = %e,
+                            subscriber_email = %task.subscriber_email,
+                            "The provider will never deliver the issue to a confirmed subscriber.",
+                        );
+                        move_task_to_failures(
+                            &mut transaction,
+                            task.newsletter_issue_id,
+                            &task.subscriber_email,
+                            task.n_retries,
+                            &e.to_string(),
+                        )
+                        .await
+                        .context("Failed to record the failed delivery.")
+                        .map_err(ExecutionError::Transient)?;
+                    }
+                    Err(e) => {
+                        tracing::error!(
+                            error.message = %e,
+                            subscriber_email = %task.subscriber_email,
+                            n_retries = task.n_retries,
+                            "Failed to deliver issue to a confirmed subscriber.",
+                        );
+                        fail_task(&mut transaction, task, &e.to_string(), settings)
+                            .await
+                            .context("Failed to record the failed delivery.")
+                            .map_err(ExecutionError::Transient)?;
+                    }
+                }
+            }
+            None
+        }
+        Err(e) => {
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to send a batch of issues.",
+            );
+            for (task, _) in &deliverable {
+                fail_task(&mut transaction, task, &e.to_string(), settings)
+                    .await
+                    .context("Failed to record the failed delivery.")
+                    .map_err(ExecutionError::Transient)?;
+            }
+            Some(e)
+        }
+    };
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the batch outcome.")
+        .map_err(ExecutionError::Transient)?;
+
+    match batch_error {
+        Some(e) => Err(ExecutionError::Transient(
+            e.context("Failed to send batch."),
+        )),
+        None => Ok(ExecutionOutcome::TaskCompleted),
+    }
+}
+
 struct NewsletterIssue {
     title: String,
     text_content: String,
@@ -130,39 +337,165 @@ async fn get_issue(pool: &PgPool, issue_id: Uuid) -> Result<NewsletterIssue, any
     Ok(issue)
 }
 
+// The issue body as sent to a given subscriber: variables are rendered from their
+// subscription and links to their preferences and to unsubscribe are appended.
+struct IssueContent {
+    html: String,
+    text: String,
+    list_unsubscribe: String,
+}
+
+impl IssueContent {
+    fn new(
+        issue: &NewsletterIssue,
+        task: &Task,
+        base_url: &Url,
+        hmac_secret: &Secret<String>,
+    ) -> Self {
+        // The unsubscribe token also grants access to the preferences page.
+        let token = UnsubscribeToken::new(task.subscriber_id, hmac_secret);
+        let unsubscribe_link = base_url
+            .join(&format!("subscriptions/unsubscribe?token={}", token))
+            .unwrap();
+        let preferences_link = base_url
+            .join(&format!("preferences?token={}", token))
+            .unwrap();
+        let variables = TemplateVariables {
+            name: &task.subscriber_name,
+            email: &task.subscriber_email,
+            unsubscribe_url: unsubscribe_link.as_str(),
+            preferences_url: preferences_link.as_str(),
+        };
+        let html = format!(
+            "{}<br /><p><a href=\"{}\">Manage your preferences</a> or <a href=\"{}\">unsubscribe</a> from this newsletter.</p>",
+            variables.render(&issue.html_content, true),
+            preferences_link,
+            unsubscribe_link
+        );
+        let text = format!(
+            "{}\n\nManage your preferences: {}\nUnsubscribe from this newsletter: {}",
+            variables.render(&issue.text_content, false),
+            preferences_link,
+            unsubscribe_link
+        );
+        Self {
+            html,
+            text,
+            list_unsubscribe: format!("<{}>", unsubscribe_link),
+        }
+    }
+
+    // RFC 8058: lets mail clients offer a native one-click unsubscribe button.
+    fn headers(&self) -> [EmailHeader<'_>; 2] {
+        [
+            EmailHeader {
+                name: "List-Unsubscribe",
+                value: &self.list_unsubscribe,
+            },
+            EmailHeader {
+                name: "List-Unsubscribe-Post",
+                value: "List-Unsubscribe=One-Click",
+            },
+        ]
+    }
+}
+
+struct Task {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+    subscriber_id: Uuid,
+    subscriber_name: String,
+    n_retries: i16,
+}
+
 #[tracing::instrument(skip_all)]
-async fn dequeue_task(
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Task)>, anyhow::Error> {
+    let (transaction, mut tasks) = dequeue_tasks(pool, 1).await?;
+    Ok(tasks.pop().map(|task| (transaction, task)))
+}
+
+// Subscribers who left or paused since the issue was published are not picked up,
+// even if their deliveries are still queued.
+#[tracing::instrument(skip(pool))]
+async fn dequeue_tasks(
     pool: &PgPool,
-) -> Result<Option<(PgTransaction, Uuid, String)>, anyhow::Error> {
+    batch_size: u16,
+) -> Result<(PgTransaction, Vec<Task>), anyhow::Error> {
     let mut transaction = pool.begin().await?;
-    let r = sqlx::query!(
+    let tasks = sqlx::query_as!(
+        Task,
         r#"
-        SELECT newsletter_issue_id, subscriber_email
-        FROM issue_delivery_queue
-        FOR UPDATE
+        SELECT
+            q.newsletter_issue_id,
+            q.subscriber_email,
+            s.id AS subscriber_id,
+            s.name AS subscriber_name,
+            q.n_retries
+        FROM issue_delivery_queue q
+        JOIN subscriptions s ON s.email = q.subscriber_email
+        WHERE q.execute_after <= now()
+            AND s.status = 'confirmed'
+            AND (s.paused_until IS NULL OR s.paused_until <= now())
+        FOR UPDATE OF q
         SKIP LOCKED
-        LIMIT 1
+        LIMIT $1
         "#,
+        i64::from(batch_size),
     )
-    .fetch_optional(&mut transaction)
+    .fetch_all(&mut transaction)
     .await?;
-    if let Some(r) = r {
-        Ok(Some((
-            transaction,
-            r.newsletter_issue_id,
-            r.subscriber_email,
-        )))
-    } else {
-        Ok(None)
+    Ok((transaction, tasks))
+}
+
+#[derive(Clone, Copy, Debug)]
+enum DeliveryOutcome {
+    Delivered,
+    Failed,
+    Skipped,
+}
+
+impl DeliveryOutcome {
+    fn as_str(&self) -> &'static str {
+        match self {
+            DeliveryOutcome::Delivered => "delivered",
+            DeliveryOutcome::Failed => "failed",
+            DeliveryOutcome::Skipped => "skipped",
+        }
     }
 }
 
-#[tracing::instrument(skip_all)]
-async fn delete_task(
-    mut transaction: PgTransaction,
+// Records the outcome of a task and removes it from the queue.
+#[tracing::instrument(skip(transaction, email, error))]
+async fn complete_task(
+    transaction: &mut PgTransaction,
     issue_id: Uuid,
     email: &str,
+    outcome: DeliveryOutcome,
+    error: Option<&str>,
 ) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_outcomes (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            error,
+            recorded_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            outcome = EXCLUDED.outcome,
+            error = EXCLUDED.error,
+            recorded_at = EXCLUDED.recorded_at
+        "#,
+        issue_id,
+        email,
+        outcome.as_str(),
+        error
+    )
+    .execute(&mut *transaction)
+    .await?;
     sqlx::query!(
         r#"
         DELETE FROM issue_delivery_queue
@@ -173,17 +506,241 @@ async fn delete_task(
         issue_id,
         email
     )
-    .execute(&mut transaction)
+    .execute(&mut *transaction)
     .await?;
-    transaction.commit().await?;
     Ok(())
 }
 
-async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyhow::Error> {
+// Schedules another attempt, or moves the task to the delivery failures once the
+// retry budget is exhausted. Returns whether the task has been given up on.
+async fn fail_task(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    error: &str,
+    settings: &DeliveryWorkerSettings,
+) -> Result<bool, anyhow::Error> {
+    if task.n_retries >= settings.max_retries {
+        move_task_to_failures(
+            transaction,
+            task.newsletter_issue_id,
+            &task.subscriber_email,
+            task.n_retries,
+            error,
+        )
+        .await?;
+        tracing::info!("Retry budget exhausted, moved the task to delivery failures.");
+        return Ok(true);
+    }
+    let backoff = settings.backoff(task.n_retries);
+    schedule_retry(
+        transaction,
+        task.newsletter_issue_id,
+        &task.subscriber_email,
+        backoff,
+    )
+    .await?;
+    Ok(false)
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE issue_delivery_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $3
+        WHERE
+            newsletter_issue_id = $1 AND
+            subscriber_email = $2
+        "#,
+        issue_id,
+        email,
+        execute_after
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn move_task_to_failures(
+    transaction: &mut PgTransaction,
+    issue_id: Uuid,
+    email: &str,
+    n_retries: i16,
+    last_error: &str,
+) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_failures (
+            newsletter_issue_id,
+            subscriber_email,
+            n_retries,
+            last_error,
+            failed_at
+        )
+        VALUES ($1, $2, $3, $4, now())
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO UPDATE
+        SET
+            n_retries = EXCLUDED.n_retries,
+            last_error = EXCLUDED.last_error,
+            failed_at = EXCLUDED.failed_at
+        "#,
+        issue_id,
+        email,
+        n_retries,
+        last_error
+    )
+    .execute(&mut *transaction)
+    .await?;
+    complete_task(
+        transaction,
+        issue_id,
+        email,
+        DeliveryOutcome::Failed,
+        Some(last_error),
+    )
+    .await
+}
+
+// Subscribers on several of the issue's lists still get a single copy. Issues aimed at
+// a segment only go to the subscribers matching it.
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_delivery_tasks(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO issue_delivery_queue (
+            newsletter_issue_id,
+            subscriber_email
+        )
+        SELECT DISTINCT $1::uuid, s.email
+        FROM subscriptions s
+        JOIN list_memberships m ON m.subscriber_id = s.id
+        JOIN newsletter_issue_lists il ON il.list_id = m.list_id
+        JOIN newsletter_issues i ON i.newsletter_issue_id = il.newsletter_issue_id
+        LEFT JOIN segments sg ON sg.segment_id = i.segment_id
+        WHERE
+            il.newsletter_issue_id = $1 AND
+            s.status = 'confirmed' AND
+            m.status = 'confirmed' AND
+            (s.paused_until IS NULL OR s.paused_until <= now()) AND
+            (sg.segment_id IS NULL OR subscriber_matches_segment(s.tags, s.attributes, sg.conditions))
+        "#,
+        newsletter_issue_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Drops the deliveries still queued for an address, recording them as skipped.
+#[tracing::instrument(skip(transaction))]
+pub async fn skip_queued_deliveries(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    reason: &str,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        WITH dropped AS (
+            DELETE FROM issue_delivery_queue
+            WHERE subscriber_email = $1
+            RETURNING newsletter_issue_id, subscriber_email
+        )
+        INSERT INTO issue_delivery_outcomes (
+            newsletter_issue_id,
+            subscriber_email,
+            outcome,
+            error,
+            recorded_at
+        )
+        SELECT newsletter_issue_id, subscriber_email, 'skipped', $2, now()
+        FROM dropped
+        ON CONFLICT (newsletter_issue_id, subscriber_email) DO NOTHING
+        "#,
+        email,
+        reason,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Publishes every scheduled issue whose time has come and enqueues its deliveries.
+// Returns the number of issues that have been fanned out.
+#[tracing::instrument(skip_all, err(Debug))]
+pub async fn fan_out_scheduled_issues(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let issues = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = 'published',
+            published_at = now()
+        WHERE
+            status = 'scheduled' AND
+            scheduled_for <= now()
+        RETURNING newsletter_issue_id
+        "#,
+    )
+    .fetch_all(&mut transaction)
+    .await
+    .context("Failed to publish scheduled issues.")?;
+    for issue in &issues {
+        enqueue_delivery_tasks(&mut transaction, issue.newsletter_issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+    transaction.commit().await?;
+    Ok(issues.len() as u64)
+}
+
+const SCHEDULE_CHECK_INTERVAL: Duration = Duration::from_secs(10);
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: Url,
+    hmac_secret: Secret<String>,
+    settings: DeliveryWorkerSettings,
+) -> Result<(), anyhow::Error> {
+    let mut next_schedule_check = Instant::now();
     loop {
-        match try_execute_task(&pool, &email_client).await {
+        // Checked on a timer rather than when the queue runs dry, so that a long backlog
+        // of deliveries does not hold back issues whose time has come.
+        if Instant::now() >= next_schedule_check {
+            let _ = fan_out_scheduled_issues(&pool).await;
+            next_schedule_check = Instant::now() + SCHEDULE_CHECK_INTERVAL;
+        }
+        let outcome = match settings.batch_size {
+            Some(batch_size) => {
+                try_execute_batch(
+                    &pool,
+                    &email_client,
+                    &base_url,
+                    &hmac_secret,
+                    &settings,
+                    batch_size,
+                )
+                .await
+            }
+            None => {
+                try_execute_task(&pool, &email_client, &base_url, &hmac_secret, &settings).await
+            }
+        };
+        match outcome {
             Ok(ExecutionOutcome::EmptyQueue) => {
-                tokio::time::sleep(Duration::from_secs(10)).await;
+                tokio::time::sleep_until(next_schedule_check).await;
             }
             Ok(ExecutionOutcome::TaskCompleted) => {}
             Err(ExecutionError::Transient(_)) => {
@@ -197,5 +754,16 @@ async fn worker_loop(pool: PgPool, email_client: EmailClient) -> Result<(), anyh
 pub async fn run_worker_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
     let connection_pool = get_connection_pool(&configuration.database);
     let email_client = configuration.email_client.client();
-    worker_loop(connection_pool, email_client).await
+    let base_url = configuration
+        .application
+        .base_url()
+        .expect("Failed to get application base URL.");
+    worker_loop(
+        connection_pool,
+        email_client,
+        base_url,
+        configuration.application.hmac_secret,
+        configuration.delivery_worker,
+    )
+    .await
 }
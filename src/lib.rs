@@ -1,11 +1,18 @@
 pub mod authentication;
 pub mod configuration;
+pub mod confirmation_email_worker;
 pub mod domain;
 pub mod email_client;
 pub mod idempotency;
 pub mod issue_delivery_worker;
+pub mod mailing_lists;
+pub mod password_reset_email_worker;
+pub mod resend_cooldown;
 pub mod routes;
+pub mod segments;
 pub mod session_state;
 pub mod startup;
+pub mod subscriber_import;
+pub mod subscription_token_sweeper;
 pub mod telemetry;
 pub mod util;
@@ -0,0 +1,204 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+// Subscribers and issues that do not name a list belong to this one.
+pub const DEFAULT_LIST: &str = "newsletter";
+
+pub struct MailingList {
+    pub list_id: Uuid,
+    pub slug: String,
+    pub name: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ListSelectionError {
+    #[error("There is no list called '{0}'.")]
+    UnknownList(String),
+    #[error(transparent)]
+    Unexpected(#[from] sqlx::Error),
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_lists(pool: &PgPool) -> Result<Vec<MailingList>, sqlx::Error> {
+    sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Resolves the lists picked by their slugs, falling back to the default list.
+#[tracing::instrument(skip(pool))]
+pub async fn select_lists(
+    pool: &PgPool,
+    slugs: &[String],
+) -> Result<Vec<MailingList>, ListSelectionError> {
+    let slugs = if slugs.is_empty() {
+        vec![DEFAULT_LIST.to_string()]
+    } else {
+        slugs.to_vec()
+    };
+    let lists = sqlx::query_as!(
+        MailingList,
+        "SELECT list_id, slug, name FROM lists WHERE slug = ANY($1)",
+        &slugs,
+    )
+    .fetch_all(pool)
+    .await?;
+    if let Some(unknown) = slugs.iter().find(|s| !lists.iter().any(|l| &l.slug == *s)) {
+        return Err(ListSelectionError::UnknownList(unknown.clone()));
+    }
+    Ok(lists)
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn assign_issue_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issue_lists (newsletter_issue_id, list_id)
+        SELECT $1, list_id FROM unnest($2::uuid[]) AS list_id
+        ON CONFLICT DO NOTHING
+        "#,
+        newsletter_issue_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+pub struct JoinedList {
+    pub status: String,
+    // Whether the subscriber was not a member of the list before.
+    pub inserted: bool,
+}
+
+// Each list is confirmed on its own: joining one waits for a confirmation, unless the
+// subscriber is already a confirmed member.
+#[tracing::instrument(skip(transaction))]
+pub async fn join_list(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<JoinedList, sqlx::Error> {
+    sqlx::query_as!(
+        JoinedList,
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        VALUES ($1, $2, 'pending_confirmation')
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE
+        SET status = CASE
+            WHEN list_memberships.status = 'confirmed' THEN 'confirmed'
+            ELSE 'pending_confirmation'
+        END
+        RETURNING status, (xmax = 0) AS "inserted!"
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .fetch_one(transaction)
+    .await
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn confirm_pending_membership(
+    transaction: &mut Transaction<'_, Postgres>,
+    list_id: Uuid,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'confirmed'
+        WHERE list_id = $1 AND subscriber_id = $2 AND status = 'pending_confirmation'
+        "#,
+        list_id,
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Makes the subscriber a confirmed member of exactly the given lists.
+#[tracing::instrument(skip(transaction))]
+pub async fn set_memberships(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_ids: &[Uuid],
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE list_memberships
+        SET status = 'unsubscribed'
+        WHERE subscriber_id = $1 AND list_id <> ALL($2)
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        SELECT list_id, $1, 'confirmed' FROM unnest($2::uuid[]) AS list_id
+        ON CONFLICT (list_id, subscriber_id) DO UPDATE SET status = 'confirmed'
+        "#,
+        subscriber_id,
+        list_ids,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn leave_all_lists(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1",
+        subscriber_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+pub struct Membership {
+    pub slug: String,
+    pub name: String,
+    pub status: Option<String>,
+}
+
+impl Membership {
+    pub fn is_confirmed(&self) -> bool {
+        self.status.as_deref() == Some("confirmed")
+    }
+}
+
+// Every list, along with the subscriber's membership status if they ever joined it.
+#[tracing::instrument(skip(pool))]
+pub async fn get_memberships(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Vec<Membership>, sqlx::Error> {
+    sqlx::query_as!(
+        Membership,
+        r#"
+        SELECT l.slug, l.name, m.status AS "status?"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id AND m.subscriber_id = $1
+        ORDER BY l.name
+        "#,
+        subscriber_id,
+    )
+    .fetch_all(pool)
+    .await
+}
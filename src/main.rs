@@ -1,8 +1,11 @@
 use std::fmt::{Debug, Display};
 use tokio::task::JoinError;
 use zero2prod::configuration::get_configuration;
+use zero2prod::confirmation_email_worker::run_confirmation_worker_until_stopped;
 use zero2prod::issue_delivery_worker::run_worker_until_stopped;
+use zero2prod::password_reset_email_worker::run_password_reset_worker_until_stopped;
 use zero2prod::startup::Application;
+use zero2prod::subscription_token_sweeper::run_sweeper_until_stopped;
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
 #[tokio::main]
@@ -13,11 +16,20 @@ async fn main() -> anyhow::Result<()> {
     let configuration = get_configuration().expect("Failed to read configuration.");
     let application = Application::build(configuration.clone()).await?;
     let application_task = tokio::spawn(application.run_until_stopped());
-    let worker_task = tokio::spawn(run_worker_until_stopped(configuration));
+    let worker_task = tokio::spawn(run_worker_until_stopped(configuration.clone()));
+    let confirmation_worker_task =
+        tokio::spawn(run_confirmation_worker_until_stopped(configuration.clone()));
+    let password_reset_worker_task = tokio::spawn(run_password_reset_worker_until_stopped(
+        configuration.clone(),
+    ));
+    let sweeper_task = tokio::spawn(run_sweeper_until_stopped(configuration));
 
     tokio::select! {
         o = application_task => report_exit("API", o),
         o = worker_task => report_exit("Background worker", o),
+        o = confirmation_worker_task => report_exit("Confirmation email worker", o),
+        o = password_reset_worker_task => report_exit("Password reset email worker", o),
+        o = sweeper_task => report_exit("Subscription token sweeper", o),
     };
     Ok(())
 }
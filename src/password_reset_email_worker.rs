@@ -0,0 +1,215 @@
+use crate::configuration::{DeliveryWorkerSettings, Settings};
+use crate::domain::password_reset_token::PasswordResetToken;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::issue_delivery_worker::{ExecutionError, ExecutionOutcome};
+use crate::routes::send_reset_email;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use sqlx::{PgPool, Postgres, Transaction};
+use std::time::Duration;
+
+type PgTransaction = Transaction<'static, Postgres>;
+
+struct Task {
+    password_reset_token: String,
+    email: Option<String>,
+    usable: bool,
+    n_retries: i16,
+}
+
+// Password reset links are sent here rather than while handling the request, so that the
+// response time does not tell whether the address belongs to an account.
+#[tracing::instrument(skip_all, err(Debug))]
+pub async fn try_send_password_reset_email(
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &Url,
+    settings: &DeliveryWorkerSettings,
+) -> Result<ExecutionOutcome, ExecutionError> {
+    let (mut transaction, task) = match dequeue_task(pool)
+        .await
+        .context("Failed to dequeue a password reset email.")
+        .map_err(ExecutionError::Transient)?
+    {
+        Some(dequeued) => dequeued,
+        None => return Ok(ExecutionOutcome::EmptyQueue),
+    };
+
+    // Links that were used or have expired in the meantime are not sent.
+    if task.usable {
+        let link = SubscriberEmail::parse(task.email.as_deref().unwrap_or_default()).and_then(
+            |recipient| {
+                Ok((
+                    recipient,
+                    PasswordResetToken::parse(&task.password_reset_token)?,
+                ))
+            },
+        );
+        let (recipient, password_reset_token) = match link {
+            Ok(link) => link,
+            Err(e) => {
+                delete_task(&mut transaction, &task)
+                    .await
+                    .context("Failed to delete the password reset email.")
+                    .map_err(ExecutionError::Transient)?;
+                transaction
+                    .commit()
+                    .await
+                    .context("Failed to delete the password reset email.")
+                    .map_err(ExecutionError::Transient)?;
+                return Err(ExecutionError::Fatal(
+                    anyhow::anyhow!(e).context("Invalid password reset details."),
+                ));
+            }
+        };
+        if let Err(e) =
+            send_reset_email(email_client, &recipient, base_url, &password_reset_token).await
+        {
+            let gave_up = task.n_retries >= settings.max_retries;
+            if gave_up {
+                delete_task(&mut transaction, &task).await
+            } else {
+                schedule_retry(&mut transaction, &task, settings.backoff(task.n_retries)).await
+            }
+            .context("Failed to record the failed password reset email.")
+            .map_err(ExecutionError::Transient)?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to record the failed password reset email.")
+                .map_err(ExecutionError::Transient)?;
+            if gave_up {
+                return Err(ExecutionError::Fatal(
+                    e.context("Failed to send a password reset email, giving up."),
+                ));
+            }
+            return Err(ExecutionError::Transient(
+                e.context("Failed to send a password reset email."),
+            ));
+        }
+    }
+    delete_task(&mut transaction, &task)
+        .await
+        .context("Failed to complete the password reset email.")
+        .map_err(ExecutionError::Transient)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to complete the password reset email.")
+        .map_err(ExecutionError::Transient)?;
+    Ok(ExecutionOutcome::TaskCompleted)
+}
+
+#[tracing::instrument(skip_all)]
+async fn dequeue_task(pool: &PgPool) -> Result<Option<(PgTransaction, Task)>, anyhow::Error> {
+    let mut transaction = pool.begin().await?;
+    let task = sqlx::query_as!(
+        Task,
+        r#"
+        SELECT
+            q.password_reset_token,
+            u.email,
+            (t.used_at IS NULL AND t.expires_at > now()) AS "usable!",
+            q.n_retries
+        FROM password_reset_email_queue q
+        JOIN password_reset_tokens t ON t.password_reset_token = q.password_reset_token
+        JOIN users u ON u.user_id = t.user_id
+        WHERE q.execute_after <= now()
+        FOR UPDATE OF q
+        SKIP LOCKED
+        LIMIT 1
+        "#,
+    )
+    .fetch_optional(&mut transaction)
+    .await?;
+    Ok(task.map(|task| (transaction, task)))
+}
+
+#[tracing::instrument(skip_all)]
+async fn delete_task(transaction: &mut PgTransaction, task: &Task) -> Result<(), anyhow::Error> {
+    sqlx::query!(
+        "DELETE FROM password_reset_email_queue WHERE password_reset_token = $1",
+        task.password_reset_token,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+async fn schedule_retry(
+    transaction: &mut PgTransaction,
+    task: &Task,
+    backoff: Duration,
+) -> Result<(), anyhow::Error> {
+    let execute_after = Utc::now() + chrono::Duration::from_std(backoff)?;
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_email_queue
+        SET
+            n_retries = n_retries + 1,
+            execute_after = $2
+        WHERE password_reset_token = $1
+        "#,
+        task.password_reset_token,
+        execute_after,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip_all)]
+pub async fn enqueue_password_reset_email(
+    transaction: &mut Transaction<'_, Postgres>,
+    password_reset_token: &PasswordResetToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "INSERT INTO password_reset_email_queue (password_reset_token) VALUES ($1)",
+        password_reset_token.as_ref(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+async fn worker_loop(
+    pool: PgPool,
+    email_client: EmailClient,
+    base_url: Url,
+    settings: DeliveryWorkerSettings,
+) -> Result<(), anyhow::Error> {
+    loop {
+        match try_send_password_reset_email(&pool, &email_client, &base_url, &settings).await {
+            Ok(ExecutionOutcome::EmptyQueue) => {
+                tokio::time::sleep(Duration::from_secs(10)).await;
+            }
+            Ok(ExecutionOutcome::TaskCompleted) => {}
+            Err(ExecutionError::Transient(_)) => {
+                tokio::time::sleep(Duration::from_secs(1)).await;
+            }
+            Err(ExecutionError::Fatal(_)) => {}
+        }
+    }
+}
+
+pub async fn run_password_reset_worker_until_stopped(
+    configuration: Settings,
+) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    let email_client = configuration.email_client.client();
+    let base_url = configuration
+        .application
+        .base_url()
+        .expect("Failed to get application base URL.");
+    worker_loop(
+        connection_pool,
+        email_client,
+        base_url,
+        configuration.delivery_worker,
+    )
+    .await
+}
@@ -0,0 +1,99 @@
+use crate::configuration::ConfirmationResendSettings;
+use anyhow::Context;
+use redis::aio::ConnectionManager;
+use secrecy::{ExposeSecret, Secret};
+use std::time::Duration;
+
+// Spaces out confirmation and password reset emails per recipient address, and the requests
+// for them per client address, so that neither form can be used to flood an inbox.
+#[derive(Clone)]
+pub struct ResendCooldown {
+    redis: ConnectionManager,
+    settings: ConfirmationResendSettings,
+}
+
+impl ResendCooldown {
+    pub async fn new(
+        redis_uri: &Secret<String>,
+        settings: ConfirmationResendSettings,
+    ) -> Result<Self, anyhow::Error> {
+        let client = redis::Client::open(redis_uri.expose_secret().as_str())
+            .context("Failed to parse the Redis URI.")?;
+        let redis = ConnectionManager::new(client)
+            .await
+            .context("Failed to connect to Redis.")?;
+        Ok(Self { redis, settings })
+    }
+
+    // Returns how long the client address still has to wait, or starts its cooldown.
+    #[tracing::instrument(name = "Start resend cooldown for a client address", skip(self))]
+    pub async fn start_for_ip(&self, ip: &str) -> Result<Option<Duration>, anyhow::Error> {
+        self.start(
+            format!("resend_cooldown:ip:{}", ip),
+            self.settings.ip_cooldown_seconds,
+        )
+        .await
+    }
+
+    // Returns how long the subscriber address still has to wait, or starts its cooldown.
+    #[tracing::instrument(name = "Start resend cooldown for an email address", skip(self))]
+    pub async fn start_for_email(&self, email: &str) -> Result<Option<Duration>, anyhow::Error> {
+        self.start(
+            format!("resend_cooldown:email:{}", email.to_lowercase()),
+            self.settings.address_cooldown_seconds,
+        )
+        .await
+    }
+
+    // Password resets have their own cooldowns, so that asking for a reset link does not hold
+    // back a confirmation email to the same address.
+    #[tracing::instrument(
+        name = "Start password reset cooldown for a client address",
+        skip(self)
+    )]
+    pub async fn start_reset_for_ip(&self, ip: &str) -> Result<Option<Duration>, anyhow::Error> {
+        self.start(
+            format!("password_reset_cooldown:ip:{}", ip),
+            self.settings.ip_cooldown_seconds,
+        )
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "Start password reset cooldown for an email address",
+        skip(self)
+    )]
+    pub async fn start_reset_for_email(
+        &self,
+        email: &str,
+    ) -> Result<Option<Duration>, anyhow::Error> {
+        self.start(
+            format!("password_reset_cooldown:email:{}", email.to_lowercase()),
+            self.settings.address_cooldown_seconds,
+        )
+        .await
+    }
+
+    async fn start(&self, key: String, seconds: u64) -> Result<Option<Duration>, anyhow::Error> {
+        let mut redis = self.redis.clone();
+        // SET NX only succeeds when no cooldown is running.
+        let started: Option<String> = redis::cmd("SET")
+            .arg(&key)
+            .arg(1)
+            .arg("NX")
+            .arg("EX")
+            .arg(seconds.max(1))
+            .query_async(&mut redis)
+            .await
+            .context("Failed to store a resend cooldown in Redis.")?;
+        if started.is_some() {
+            return Ok(None);
+        }
+        let ttl_ms: i64 = redis::cmd("PTTL")
+            .arg(&key)
+            .query_async(&mut redis)
+            .await
+            .context("Failed to read a resend cooldown from Redis.")?;
+        Ok(Some(Duration::from_millis(ttl_ms.max(1) as u64)))
+    }
+}
@@ -0,0 +1,70 @@
+use crate::authentication::UserId;
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct ApiTokenSummary {
+    api_token_id: Uuid,
+    name: String,
+    token_prefix: String,
+    created_at: DateTime<Utc>,
+    last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Template)]
+#[template(path = "api_tokens.html")]
+struct ApiTokensTemplate<'a> {
+    api_tokens: Vec<ApiTokenSummary>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/api_tokens")]
+#[tracing::instrument(skip(flash_messages, pool), fields(user_id=%*user_id))]
+pub async fn list_api_tokens(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let api_tokens = get_active_api_tokens(&pool, **user_id)
+        .await
+        .context("Failed to retrieve the API tokens of the user.")
+        .map_err(e500)?;
+
+    let api_tokens_page = ApiTokensTemplate {
+        api_tokens,
+        messages,
+    };
+    let api_tokens_html = api_tokens_page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(api_tokens_html))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_active_api_tokens(
+    pool: &PgPool,
+    user_id: Uuid,
+) -> Result<Vec<ApiTokenSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ApiTokenSummary,
+        r#"
+        SELECT api_token_id, name, token_prefix, created_at, last_used_at
+        FROM api_tokens
+        WHERE user_id = $1 AND revoked_at IS NULL
+        ORDER BY created_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,69 @@
+use crate::authentication::{create_api_token, revoke_api_token, UserId};
+use crate::util::{e500, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use askama::Template;
+use secrecy::ExposeSecret;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+}
+
+#[derive(Template)]
+#[template(path = "api_token_created.html")]
+struct ApiTokenCreatedTemplate<'a> {
+    name: &'a str,
+    token: &'a str,
+}
+
+#[post("/api_tokens")]
+#[tracing::instrument(skip(form, pool), fields(user_id=%*user_id))]
+pub async fn create_user_api_token(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let name = form.0.name.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please give the token a name.").send();
+        return Ok(see_other("/admin/api_tokens"));
+    }
+    let token = create_api_token(pool.get_ref(), **user_id, name)
+        .await
+        .map_err(e500)?;
+
+    // The token is only ever shown here, so it is rendered rather than flashed.
+    let page_html = ApiTokenCreatedTemplate {
+        name,
+        token: token.expose_secret(),
+    }
+    .render()
+    .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+#[post("/api_tokens/{api_token_id}/revoke")]
+#[tracing::instrument(skip(pool), fields(user_id=%*user_id))]
+pub async fn revoke_user_api_token(
+    api_token_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let revoked = revoke_api_token(pool.get_ref(), **user_id, api_token_id.into_inner())
+        .await
+        .context("Failed to revoke the API token.")
+        .map_err(e500)?;
+    if revoked {
+        FlashMessage::info("The API token has been revoked.").send();
+    } else {
+        FlashMessage::error("The API token does not exist or has already been revoked.").send();
+    }
+    Ok(see_other("/admin/api_tokens"))
+}
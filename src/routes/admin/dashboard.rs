@@ -1,4 +1,4 @@
-use crate::authentication::UserId;
+use crate::authentication::{Permission, Role, UserId};
 use crate::util::{e500, get_username};
 use actix_web::http::header::ContentType;
 use actix_web::{get, web, HttpResponse};
@@ -9,19 +9,22 @@ use sqlx::PgPool;
 #[template(path = "admin_dashboard.html")]
 struct DashboardTemplate<'a> {
     username: &'a str,
+    role: Role,
 }
 
 #[get("/dashboard")]
-#[tracing::instrument(skip(pool, user_id), fields(user_id=%*user_id))]
+#[tracing::instrument(skip(pool, user_id, role), fields(user_id=%*user_id))]
 pub async fn admin_dashboard(
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
 
     let admin_dashboard = DashboardTemplate {
         username: username.as_str(),
+        role: role.into_inner(),
     };
     let admin_dashboard_html = admin_dashboard.render().map_err(e500)?;
 
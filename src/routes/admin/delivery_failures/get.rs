@@ -0,0 +1,64 @@
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct DeliveryFailure {
+    newsletter_issue_id: Uuid,
+    title: String,
+    subscriber_email: String,
+    n_retries: i16,
+    last_error: String,
+    failed_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "delivery_failures.html")]
+struct DeliveryFailuresTemplate<'a> {
+    failures: Vec<DeliveryFailure>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/delivery_failures")]
+pub async fn delivery_failures(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let failures = get_delivery_failures(&pool).await.map_err(e500)?;
+
+    let delivery_failures = DeliveryFailuresTemplate { failures, messages };
+    let delivery_failures_html = delivery_failures.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(delivery_failures_html))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_delivery_failures(pool: &PgPool) -> Result<Vec<DeliveryFailure>, sqlx::Error> {
+    sqlx::query_as!(
+        DeliveryFailure,
+        r#"
+        SELECT
+            f.newsletter_issue_id,
+            i.title,
+            f.subscriber_email,
+            f.n_retries,
+            f.last_error,
+            f.failed_at
+        FROM issue_delivery_failures f
+        JOIN newsletter_issues i ON i.newsletter_issue_id = f.newsletter_issue_id
+        ORDER BY f.failed_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,113 @@
+use crate::authentication::{Permission, Role};
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    newsletter_issue_id: Uuid,
+    subscriber_email: String,
+}
+
+// Only deliveries to confirmed subscribers go back to the queue: the worker never picks up
+// the others, so they stay listed here instead of sitting in the queue forever.
+#[post("/delivery_failures/requeue")]
+#[tracing::instrument(name = "Re-queue a failed delivery", skip(form, pool))]
+pub async fn requeue_delivery_failure(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::Publish)?;
+    let n_requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_failures
+            WHERE
+                newsletter_issue_id = $1 AND
+                subscriber_email = $2 AND
+                EXISTS (
+                    SELECT 1 FROM subscriptions s
+                    WHERE s.email = subscriber_email AND s.status = 'confirmed'
+                )
+            RETURNING newsletter_issue_id, subscriber_email
+        ),
+        cleared AS (
+            DELETE FROM issue_delivery_outcomes o
+            USING requeued r
+            WHERE
+                o.newsletter_issue_id = r.newsletter_issue_id AND
+                o.subscriber_email = r.subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, subscriber_email FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+        form.0.newsletter_issue_id,
+        form.0.subscriber_email,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to re-queue the failed delivery.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_requeued == 0 {
+        FlashMessage::error(
+            "The delivery was not re-queued: the subscriber is no longer confirmed.",
+        )
+        .send();
+    } else {
+        requeued_message(n_requeued).send();
+    }
+    Ok(see_other("/admin/delivery_failures"))
+}
+
+#[post("/delivery_failures/requeue_all")]
+#[tracing::instrument(name = "Re-queue all failed deliveries", skip(pool))]
+pub async fn requeue_all_delivery_failures(
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::Publish)?;
+    let n_requeued = sqlx::query!(
+        r#"
+        WITH requeued AS (
+            DELETE FROM issue_delivery_failures
+            WHERE EXISTS (
+                SELECT 1 FROM subscriptions s
+                WHERE s.email = subscriber_email AND s.status = 'confirmed'
+            )
+            RETURNING newsletter_issue_id, subscriber_email
+        ),
+        cleared AS (
+            DELETE FROM issue_delivery_outcomes o
+            USING requeued r
+            WHERE
+                o.newsletter_issue_id = r.newsletter_issue_id AND
+                o.subscriber_email = r.subscriber_email
+        )
+        INSERT INTO issue_delivery_queue (newsletter_issue_id, subscriber_email)
+        SELECT newsletter_issue_id, subscriber_email FROM requeued
+        ON CONFLICT DO NOTHING
+        "#,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to re-queue failed deliveries.")
+    .map_err(e500)?
+    .rows_affected();
+
+    requeued_message(n_requeued).send();
+    Ok(see_other("/admin/delivery_failures"))
+}
+
+fn requeued_message(n_requeued: u64) -> FlashMessage {
+    FlashMessage::info(format!(
+        "{} failed deliveries have been re-queued.",
+        n_requeued
+    ))
+}
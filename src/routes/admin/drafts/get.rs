@@ -0,0 +1,193 @@
+use crate::authentication::{Permission, Role, UserId};
+use crate::domain::TemplateVariables;
+use crate::mailing_lists::{get_lists, MailingList, DEFAULT_LIST};
+use crate::segments::{get_segments, Segment};
+use crate::util::e500;
+use actix_web::http::header::{ContentType, CONTENT_SECURITY_POLICY, X_CONTENT_TYPE_OPTIONS};
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+pub(super) struct Draft {
+    pub(super) newsletter_issue_id: Uuid,
+    pub(super) title: String,
+    pub(super) text_content: String,
+    pub(super) html_content: String,
+}
+
+#[derive(Template)]
+#[template(path = "drafts.html")]
+struct DraftsTemplate<'a> {
+    drafts: Vec<Draft>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/drafts")]
+pub async fn list_drafts(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let drafts = get_drafts(&pool)
+        .await
+        .context("Failed to retrieve drafts.")
+        .map_err(e500)?;
+
+    let drafts_page = DraftsTemplate { drafts, messages };
+    let drafts_html = drafts_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(drafts_html))
+}
+
+#[derive(Template)]
+#[template(path = "draft.html")]
+struct DraftTemplate<'a> {
+    draft: Draft,
+    idempotency_key: String,
+    lists: Vec<MailingList>,
+    default_list: &'a str,
+    segments: Vec<Segment>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/drafts/{newsletter_issue_id}")]
+pub async fn edit_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let draft = match get_draft(&pool, newsletter_issue_id.into_inner())
+        .await
+        .context("Failed to retrieve draft.")
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let lists = get_lists(&pool)
+        .await
+        .context("Failed to retrieve the lists.")
+        .map_err(e500)?;
+    let segments = get_segments(&pool)
+        .await
+        .context("Failed to retrieve the segments.")
+        .map_err(e500)?;
+
+    let draft_page = DraftTemplate {
+        draft,
+        idempotency_key: Uuid::new_v4().to_string(),
+        lists,
+        default_list: DEFAULT_LIST,
+        segments,
+        messages,
+    };
+    let draft_html = draft_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(draft_html))
+}
+
+// Draft HTML is written by any editor but previewed from the admin origin, so it is
+// sandboxed: scripts in it must not run with the session of whoever opens it.
+const PREVIEW_CONTENT_SECURITY_POLICY: &str =
+    "sandbox; default-src 'none'; img-src * data:; style-src 'unsafe-inline'";
+
+// The admin stands in for the subscriber; the subscription links are dummy ones. Test
+// emails are rendered the same way, so the preview shows what the test will look like.
+pub(super) fn stand_in_variables<'a>(username: &'a str, email: &'a str) -> TemplateVariables<'a> {
+    TemplateVariables {
+        name: username,
+        email,
+        unsubscribe_url: "#",
+        preferences_url: "#",
+    }
+}
+
+#[get("/drafts/{newsletter_issue_id}/preview/{format}")]
+pub async fn preview_draft(
+    path: web::Path<(Uuid, String)>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::EditDrafts)?;
+    let (newsletter_issue_id, format) = path.into_inner();
+    let draft = match get_draft(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve draft.")
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let user = sqlx::query!(
+        r#"SELECT username, email FROM users WHERE user_id = $1"#,
+        *user_id.into_inner()
+    )
+    .fetch_one(pool.get_ref())
+    .await
+    .context("Failed to retrieve the user.")
+    .map_err(e500)?;
+    let variables = stand_in_variables(&user.username, user.email.as_deref().unwrap_or_default());
+
+    match format.as_str() {
+        "html" => Ok(HttpResponse::Ok()
+            .content_type(ContentType::html())
+            .insert_header((CONTENT_SECURITY_POLICY, PREVIEW_CONTENT_SECURITY_POLICY))
+            .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+            .body(variables.render(&draft.html_content, true))),
+        "text" => Ok(HttpResponse::Ok()
+            .content_type(ContentType::plaintext())
+            .insert_header((X_CONTENT_TYPE_OPTIONS, "nosniff"))
+            .body(variables.render(&draft.text_content, false))),
+        _ => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_drafts(pool: &PgPool) -> Result<Vec<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE status = 'draft'
+        ORDER BY published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+pub(super) async fn get_draft(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<Draft>, sqlx::Error> {
+    sqlx::query_as!(
+        Draft,
+        r#"
+        SELECT newsletter_issue_id, title, text_content, html_content
+        FROM newsletter_issues
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{edit_draft, list_drafts, preview_draft};
+pub use post::{publish_draft, save_draft, send_test_draft, update_draft};
@@ -0,0 +1,327 @@
+use super::get::{get_draft, stand_in_variables};
+use crate::authentication::{Permission, Role, UserId};
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::mailing_lists::{assign_issue_lists, select_lists, ListSelectionError};
+use crate::routes::admin::newsletter::{success_message, validate_issue_content};
+use crate::segments::{assign_issue_segment, select_segment, SegmentSelectionError};
+use crate::util::{e500, get_username, parse_form, parse_schedule, see_other, NonEmptyString};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct DraftFormData {
+    title: NonEmptyString,
+    text_content: NonEmptyString,
+    html_content: NonEmptyString,
+}
+
+#[post("/drafts")]
+#[tracing::instrument(name = "Save a newsletter draft", skip(form, pool))]
+pub async fn save_draft(
+    form: Result<web::Form<DraftFormData>, actix_web::Error>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::EditDrafts)?;
+    let form = match form {
+        Ok(f) => f,
+        Err(e) => {
+            return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
+        }
+    };
+    let newsletter_issue_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO newsletter_issues (
+            newsletter_issue_id,
+            title,
+            text_content,
+            html_content,
+            published_at,
+            status
+        )
+        VALUES ($1, $2, $3, $4, now(), 'draft')
+        "#,
+        newsletter_issue_id,
+        form.0.title.as_ref(),
+        form.0.text_content.as_ref(),
+        form.0.html_content.as_ref(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the draft.")
+    .map_err(e500)?;
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&format!("/admin/drafts/{}", newsletter_issue_id)))
+}
+
+#[post("/drafts/{newsletter_issue_id}")]
+#[tracing::instrument(name = "Update a newsletter draft", skip(form, pool))]
+pub async fn update_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: Result<web::Form<DraftFormData>, actix_web::Error>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::EditDrafts)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", newsletter_issue_id);
+    let form = match form {
+        Ok(f) => f,
+        Err(e) => {
+            return Ok(send_flash_message_and_redirect(e, &draft_page));
+        }
+    };
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            title = $2,
+            text_content = $3,
+            html_content = $4
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        newsletter_issue_id,
+        form.0.title.as_ref(),
+        form.0.text_content.as_ref(),
+        form.0.html_content.as_ref(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the draft.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+
+    FlashMessage::info("The draft has been saved.").send();
+    Ok(see_other(&draft_page))
+}
+
+#[post("/drafts/{newsletter_issue_id}/test")]
+#[tracing::instrument(
+    name = "Send a test of a newsletter draft",
+    skip(pool, email_client),
+    fields(user_id=%*user_id)
+)]
+pub async fn send_test_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::EditDrafts)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", newsletter_issue_id);
+    let draft = match get_draft(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve draft.")
+        .map_err(e500)?
+    {
+        Some(draft) => draft,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let user_id = user_id.into_inner();
+    let email = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, *user_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to retrieve the user email address.")
+        .map_err(e500)?
+        .email;
+    let email = match email.map(SubscriberEmail::parse) {
+        Some(Ok(email)) => email,
+        _ => {
+            return Ok(send_flash_message_and_redirect(
+                "Set your email address before sending a test.",
+                &draft_page,
+            ));
+        }
+    };
+
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let variables = stand_in_variables(&username, email.as_ref());
+    let subject = format!("[TEST] {}", draft.title);
+    if let Err(e) = email_client
+        .send_email(
+            &email,
+            &subject,
+            &variables.render(&draft.html_content, true),
+            &variables.render(&draft.text_content, false),
+            &[],
+        )
+        .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send a test email.",
+        );
+        return Ok(send_flash_message_and_redirect(
+            "Failed to send the test email.",
+            &draft_page,
+        ));
+    }
+
+    FlashMessage::info(format!("A test email has been sent to {}.", email)).send();
+    Ok(see_other(&draft_page))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PublishFormData {
+    idempotency_key: IdempotencyKey,
+    #[serde(default)]
+    scheduled_for: String,
+    #[serde(default)]
+    lists: Vec<String>,
+    segment: Option<String>,
+}
+
+#[post("/drafts/{newsletter_issue_id}/publish")]
+#[tracing::instrument(
+    name = "Publish a newsletter draft",
+    skip(body, pool),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_draft(
+    newsletter_issue_id: web::Path<Uuid>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::Publish)?;
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let draft_page = format!("/admin/drafts/{}", newsletter_issue_id);
+    let form: PublishFormData = match parse_form(&body) {
+        Ok(f) => f,
+        Err(e) => {
+            return Ok(send_flash_message_and_redirect(e, &draft_page));
+        }
+    };
+    // A missing draft is handled below, as it may be a retry of a publish that went through.
+    if let Some(draft) = get_draft(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve draft.")
+        .map_err(e500)?
+    {
+        if let Err(e) = validate_issue_content(&draft.text_content, &draft.html_content) {
+            return Ok(send_flash_message_and_redirect(e, &draft_page));
+        }
+    }
+    let scheduled_for = if form.scheduled_for.is_empty() {
+        None
+    } else {
+        match parse_schedule(&form.scheduled_for) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                return Ok(send_flash_message_and_redirect(e, &draft_page));
+            }
+        }
+    };
+    let lists = match select_lists(&pool, &form.lists).await {
+        Ok(lists) => lists,
+        Err(e @ ListSelectionError::UnknownList(_)) => {
+            return Ok(send_flash_message_and_redirect(e, &draft_page));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let segment_id = match select_segment(&pool, form.segment.as_deref()).await {
+        Ok(segment_id) => segment_id,
+        Err(e @ SegmentSelectionError::UnknownSegment(_)) => {
+            return Ok(send_flash_message_and_redirect(e, &draft_page));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let user_id = user_id.into_inner();
+    let mut transaction = match try_processing(&pool, &form.idempotency_key, *user_id)
+        .await
+        .map_err(e500)?
+    {
+        NextAction::StartProcessing(t) => t,
+        NextAction::ReturnSavedResponse(saved_response) => {
+            success_message(scheduled_for).send();
+            return Ok(saved_response);
+        }
+    };
+    let n_published = mark_draft_as_published(&mut transaction, newsletter_issue_id, scheduled_for)
+        .await
+        .context("Failed to publish the draft.")
+        .map_err(e500)?;
+    if n_published == 0 {
+        // Dropping the transaction releases the idempotency key.
+        return Ok(send_flash_message_and_redirect(
+            "Only drafts can be published.",
+            &draft_page,
+        ));
+    }
+    let list_ids: Vec<Uuid> = lists.iter().map(|l| l.list_id).collect();
+    assign_issue_lists(&mut transaction, newsletter_issue_id, &list_ids)
+        .await
+        .context("Failed to store the lists of the newsletter issue")
+        .map_err(e500)?;
+    assign_issue_segment(&mut transaction, newsletter_issue_id, segment_id)
+        .await
+        .context("Failed to store the segment of the newsletter issue")
+        .map_err(e500)?;
+
+    if scheduled_for.is_none() {
+        enqueue_delivery_tasks(&mut transaction, newsletter_issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+    success_message(scheduled_for).send();
+    let response = see_other("/admin/issues");
+    let response = save_response(transaction, &form.idempotency_key, *user_id, response)
+        .await
+        .map_err(e500)?;
+    Ok(response)
+}
+
+#[tracing::instrument(skip(transaction))]
+async fn mark_draft_as_published(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    scheduled_for: Option<DateTime<Utc>>,
+) -> Result<u64, sqlx::Error> {
+    let status = if scheduled_for.is_some() {
+        "scheduled"
+    } else {
+        "published"
+    };
+    let result = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET
+            status = $2,
+            scheduled_for = $3,
+            published_at = now()
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'draft'
+        "#,
+        newsletter_issue_id,
+        status,
+        scheduled_for
+    )
+    .execute(transaction)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+fn send_flash_message_and_redirect(error: impl ToString, location: &str) -> HttpResponse {
+    FlashMessage::error(error.to_string()).send();
+    see_other(location)
+}
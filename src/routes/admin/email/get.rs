@@ -0,0 +1,46 @@
+use crate::authentication::UserId;
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+#[derive(Template)]
+#[template(path = "change_email.html")]
+struct EmailFormTemplate<'a> {
+    email: &'a str,
+    messages: Vec<&'a str>,
+}
+
+#[get("/email")]
+pub async fn change_email_form(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let email = sqlx::query!(r#"SELECT email FROM users WHERE user_id = $1"#, *user_id)
+        .fetch_one(pool.get_ref())
+        .await
+        .context("Failed to retrieve the user email address.")
+        .map_err(e500)?
+        .email
+        .unwrap_or_default();
+
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+
+    let email_form = EmailFormTemplate {
+        email: email.as_str(),
+        messages,
+    };
+    let email_form_html = email_form.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(email_form_html))
+}
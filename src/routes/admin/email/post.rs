@@ -0,0 +1,42 @@
+use crate::authentication::UserId;
+use crate::domain::SubscriberEmail;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[tracing::instrument(skip(form, pool), fields(user_id=%*user_id))]
+#[post("/email")]
+pub async fn change_email(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/email"));
+        }
+    };
+
+    sqlx::query!(
+        r#"UPDATE users SET email = $1 WHERE user_id = $2"#,
+        email.as_ref(),
+        *user_id
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the user email address.")
+    .map_err(e500)?;
+
+    FlashMessage::info("Your email address has been updated.").send();
+    Ok(see_other("/admin/email"))
+}
@@ -0,0 +1,173 @@
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct IssueSummary {
+    newsletter_issue_id: Uuid,
+    title: String,
+    published_at: String,
+    status: String,
+    scheduled_for: Option<DateTime<Utc>>,
+    n_queued: i64,
+    n_delivered: i64,
+    n_failed: i64,
+    n_skipped: i64,
+}
+
+#[derive(Template)]
+#[template(path = "issues.html")]
+struct IssuesTemplate<'a> {
+    issues: Vec<IssueSummary>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/issues")]
+pub async fn list_issues(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let issues = get_issue_summaries(&pool)
+        .await
+        .context("Failed to retrieve newsletter issues.")
+        .map_err(e500)?;
+
+    let issues_page = IssuesTemplate { issues, messages };
+    let issues_html = issues_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(issues_html))
+}
+
+struct RecipientStatus {
+    subscriber_email: String,
+    status: String,
+    error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "issue_delivery_status.html")]
+struct IssueDeliveryStatusTemplate<'a> {
+    title: &'a str,
+    recipients: Vec<RecipientStatus>,
+}
+
+#[get("/issues/{newsletter_issue_id}")]
+pub async fn issue_delivery_status(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let newsletter_issue_id = newsletter_issue_id.into_inner();
+    let title = match get_issue_title(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve newsletter issue.")
+        .map_err(e500)?
+    {
+        Some(title) => title,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let recipients = get_recipient_statuses(&pool, newsletter_issue_id)
+        .await
+        .context("Failed to retrieve issue delivery outcomes.")
+        .map_err(e500)?;
+
+    let status_page = IssueDeliveryStatusTemplate {
+        title: title.as_str(),
+        recipients,
+    };
+    let status_html = status_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(status_html))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_issue_summaries(pool: &PgPool) -> Result<Vec<IssueSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        IssueSummary,
+        r#"
+        SELECT
+            i.newsletter_issue_id,
+            i.title,
+            i.published_at,
+            i.status,
+            i.scheduled_for,
+            (
+                SELECT COUNT(*) FROM issue_delivery_queue q
+                WHERE q.newsletter_issue_id = i.newsletter_issue_id
+            ) AS "n_queued!",
+            COUNT(o.outcome) FILTER (WHERE o.outcome = 'delivered') AS "n_delivered!",
+            COUNT(o.outcome) FILTER (WHERE o.outcome = 'failed') AS "n_failed!",
+            COUNT(o.outcome) FILTER (WHERE o.outcome = 'skipped') AS "n_skipped!"
+        FROM newsletter_issues i
+        LEFT JOIN issue_delivery_outcomes o ON o.newsletter_issue_id = i.newsletter_issue_id
+        WHERE i.status <> 'draft'
+        GROUP BY i.newsletter_issue_id
+        ORDER BY i.published_at DESC
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_issue_title(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Option<String>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"SELECT title FROM newsletter_issues WHERE newsletter_issue_id = $1"#,
+        newsletter_issue_id,
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| r.title))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_recipient_statuses(
+    pool: &PgPool,
+    newsletter_issue_id: Uuid,
+) -> Result<Vec<RecipientStatus>, sqlx::Error> {
+    sqlx::query_as!(
+        RecipientStatus,
+        r#"
+        SELECT
+            subscriber_email AS "subscriber_email!",
+            status AS "status!",
+            error,
+            updated_at AS "updated_at!"
+        FROM (
+            SELECT
+                subscriber_email,
+                'queued' AS status,
+                CASE WHEN n_retries > 0
+                    THEN 'Retried ' || n_retries || ' time(s)'
+                END AS error,
+                execute_after AS updated_at
+            FROM issue_delivery_queue
+            WHERE newsletter_issue_id = $1
+            UNION ALL
+            SELECT subscriber_email, outcome, error, recorded_at
+            FROM issue_delivery_outcomes
+            WHERE newsletter_issue_id = $1
+        ) AS recipients
+        ORDER BY subscriber_email
+        "#,
+        newsletter_issue_id,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{issue_delivery_status, list_issues};
+pub use post::{cancel_scheduled_issue, reschedule_issue};
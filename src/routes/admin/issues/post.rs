@@ -0,0 +1,93 @@
+use crate::authentication::{Permission, Role};
+use crate::util::{e500, parse_schedule, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[post("/issues/{newsletter_issue_id}/cancel")]
+#[tracing::instrument(name = "Cancel a scheduled issue", skip(pool))]
+pub async fn cancel_scheduled_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::Publish)?;
+    let n_cancelled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET status = 'cancelled'
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        newsletter_issue_id.into_inner(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to cancel the scheduled issue.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_cancelled == 0 {
+        not_scheduled_message().send();
+    } else {
+        FlashMessage::info("The scheduled issue has been cancelled.").send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct RescheduleFormData {
+    scheduled_for: String,
+}
+
+#[post("/issues/{newsletter_issue_id}/reschedule")]
+#[tracing::instrument(name = "Reschedule an issue", skip(form, pool))]
+pub async fn reschedule_issue(
+    newsletter_issue_id: web::Path<Uuid>,
+    form: web::Form<RescheduleFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::Publish)?;
+    let scheduled_for = match parse_schedule(&form.0.scheduled_for) {
+        Ok(t) => t,
+        Err(e) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other("/admin/issues"));
+        }
+    };
+    let n_rescheduled = sqlx::query!(
+        r#"
+        UPDATE newsletter_issues
+        SET scheduled_for = $2
+        WHERE
+            newsletter_issue_id = $1 AND
+            status = 'scheduled'
+        "#,
+        newsletter_issue_id.into_inner(),
+        scheduled_for,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to reschedule the issue.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_rescheduled == 0 {
+        not_scheduled_message().send();
+    } else {
+        FlashMessage::info(format!(
+            "The issue has been rescheduled for {}.",
+            scheduled_for.to_rfc2822()
+        ))
+        .send();
+    }
+    Ok(see_other("/admin/issues"))
+}
+
+fn not_scheduled_message() -> FlashMessage {
+    FlashMessage::error("Only issues that have not started sending can be changed.")
+}
@@ -0,0 +1,70 @@
+use crate::authentication::{Permission, Role};
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+struct ListSummary {
+    slug: String,
+    name: String,
+    n_confirmed: i64,
+    n_pending: i64,
+}
+
+#[derive(Template)]
+#[template(path = "lists.html")]
+struct ListsTemplate<'a> {
+    lists: Vec<ListSummary>,
+    can_manage: bool,
+    messages: Vec<&'a str>,
+}
+
+#[get("/lists")]
+#[tracing::instrument(skip(flash_messages, pool, role))]
+pub async fn list_mailing_lists(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let lists = get_list_summaries(&pool)
+        .await
+        .context("Failed to retrieve the lists.")
+        .map_err(e500)?;
+
+    let lists_page = ListsTemplate {
+        lists,
+        can_manage: role.can(Permission::ManageSubscribers),
+        messages,
+    };
+    let lists_html = lists_page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(lists_html))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_list_summaries(pool: &PgPool) -> Result<Vec<ListSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        ListSummary,
+        r#"
+        SELECT
+            l.slug,
+            l.name,
+            count(*) FILTER (WHERE m.status = 'confirmed') AS "n_confirmed!",
+            count(*) FILTER (WHERE m.status = 'pending_confirmation') AS "n_pending!"
+        FROM lists l
+        LEFT JOIN list_memberships m ON m.list_id = l.list_id
+        GROUP BY l.list_id
+        ORDER BY l.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::list_mailing_lists;
+pub use post::create_mailing_list;
@@ -0,0 +1,72 @@
+use crate::authentication::{Permission, Role};
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    slug: String,
+}
+
+#[post("/lists")]
+#[tracing::instrument(skip(form, pool, role))]
+pub async fn create_mailing_list(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let name = form.0.name.trim();
+    let slug = form.0.slug.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please give the list a name.").send();
+        return Ok(see_other("/admin/lists"));
+    }
+    if let Err(e) = validate_slug(slug) {
+        FlashMessage::error(e).send();
+        return Ok(see_other("/admin/lists"));
+    }
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO lists (list_id, slug, name)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (slug) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        slug,
+        name,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the new list.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error(format!("A list called '{}' already exists.", slug)).send();
+    } else {
+        FlashMessage::info(format!("The list '{}' has been created.", name)).send();
+    }
+    Ok(see_other("/admin/lists"))
+}
+
+// Slugs end up in subscription forms and API requests, so they are kept URL-friendly.
+fn validate_slug(slug: &str) -> Result<(), String> {
+    let well_formed = !slug.is_empty()
+        && slug.len() <= 50
+        && slug
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if well_formed {
+        Ok(())
+    } else {
+        Err(format!(
+            "'{}' is not a valid list slug: use up to 50 lowercase letters, digits and dashes.",
+            slug
+        ))
+    }
+}
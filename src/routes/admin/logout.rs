@@ -1,12 +1,26 @@
+use crate::authentication::{revoke_session, UserId};
 use crate::session_state::TypedSession;
+use crate::util::e500;
 use actix_web::http::header::LOCATION;
-use actix_web::{get, HttpResponse};
+use actix_web::{get, web, HttpResponse};
+use anyhow::Context;
+use sqlx::PgPool;
 
 #[get("/logout")]
-#[tracing::instrument(skip(session))]
-pub async fn logout_user(session: TypedSession) -> HttpResponse {
+#[tracing::instrument(skip(session, pool), fields(user_id=%*user_id))]
+pub async fn logout_user(
+    session: TypedSession,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(session_id) = session.get_session_id().map_err(e500)? {
+        revoke_session(pool.get_ref(), **user_id, session_id)
+            .await
+            .context("Failed to revoke the session.")
+            .map_err(e500)?;
+    }
     session.purge();
-    HttpResponse::SeeOther()
+    Ok(HttpResponse::SeeOther()
         .insert_header((LOCATION, "/login"))
-        .finish()
+        .finish())
 }
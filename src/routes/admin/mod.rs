@@ -1,9 +1,33 @@
+mod api_tokens;
 mod dashboard;
+mod delivery_failures;
+mod drafts;
+mod email;
+mod issues;
+mod lists;
 mod logout;
 mod newsletter;
 mod password;
+mod segments;
+mod sessions;
+mod subscriber_import;
+mod subscribers;
+mod two_factor;
+mod users;
 
+pub use api_tokens::*;
 pub use dashboard::admin_dashboard;
+pub use delivery_failures::*;
+pub use drafts::*;
+pub use email::*;
+pub use issues::*;
+pub use lists::*;
 pub use logout::logout_user;
 pub use newsletter::*;
 pub use password::*;
+pub use segments::*;
+pub use sessions::*;
+pub use subscriber_import::*;
+pub use subscribers::*;
+pub use two_factor::*;
+pub use users::*;
@@ -1,29 +1,48 @@
+use crate::mailing_lists::{get_lists, MailingList, DEFAULT_LIST};
+use crate::segments::{get_segments, Segment};
 use crate::util::e500;
 use actix_web::http::header::ContentType;
-use actix_web::{get, HttpResponse};
+use actix_web::{get, web, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
 use askama::Template;
+use sqlx::PgPool;
 use uuid::Uuid;
 
 #[derive(Template)]
 #[template(path = "newsletter_form.html")]
 struct NewsletterFormTemplate<'a> {
     idempotency_key: String,
+    lists: Vec<MailingList>,
+    default_list: &'a str,
+    segments: Vec<Segment>,
     messages: Vec<&'a str>,
 }
 
 #[get("/newsletter")]
 pub async fn newsletter_form(
     flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let messages = flash_messages
         .iter()
         .map(|m| m.content())
         .collect::<Vec<_>>();
+    let lists = get_lists(&pool)
+        .await
+        .context("Failed to retrieve the lists.")
+        .map_err(e500)?;
+    let segments = get_segments(&pool)
+        .await
+        .context("Failed to retrieve the segments.")
+        .map_err(e500)?;
 
     let newsletter_form = NewsletterFormTemplate {
         messages,
         idempotency_key: Uuid::new_v4().to_string(),
+        lists,
+        default_list: DEFAULT_LIST,
+        segments,
     };
     let newsletter_form_html = newsletter_form.render().map_err(e500)?;
 
@@ -3,3 +3,4 @@ mod post;
 
 pub use get::newsletter_form;
 pub use post::publish_newsletter;
+pub(crate) use post::{insert_newsletter_issue, success_message, validate_issue_content};
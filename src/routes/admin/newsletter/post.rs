@@ -1,9 +1,14 @@
-use crate::authentication::UserId;
+use crate::authentication::{Permission, Role, UserId};
+use crate::domain::validate_template;
 use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
-use crate::util::{e500, see_other, NonEmptyString};
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::mailing_lists::{assign_issue_lists, select_lists, ListSelectionError};
+use crate::segments::{assign_issue_segment, select_segment, SegmentSelectionError};
+use crate::util::{e500, parse_form, parse_schedule, see_other, NonEmptyString};
 use actix_web::{post, web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
 use anyhow::Context;
+use chrono::{DateTime, Utc};
 use sqlx::{PgPool, Postgres, Transaction};
 use uuid::Uuid;
 
@@ -13,66 +18,121 @@ pub struct FormData {
     text_content: NonEmptyString,
     html_content: NonEmptyString,
     idempotency_key: IdempotencyKey,
+    #[serde(default)]
+    scheduled_for: String,
+    // Slugs of the lists the issue goes to, the default list when none is picked.
+    #[serde(default)]
+    lists: Vec<String>,
+    // Name of the segment narrowing the audience, everyone on the lists when empty.
+    segment: Option<String>,
 }
 
 #[post("/newsletter")]
 #[tracing::instrument(
     name = "Publish a newsletter issue",
-    skip(form, pool),
+    skip(body, pool),
     fields(user_id=%*user_id)
 )]
 pub async fn publish_newsletter(
-    form: Result<web::Form<FormData>, actix_web::Error>,
+    body: web::Bytes,
     pool: web::Data<PgPool>,
     user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
 ) -> Result<HttpResponse, actix_web::Error> {
-    let form = match form {
+    role.require(Permission::Publish)?;
+    let form: FormData = match parse_form(&body) {
         Ok(f) => f,
         Err(e) => {
             return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
         }
     };
+    if let Err(e) = validate_issue_content(form.text_content.as_ref(), form.html_content.as_ref()) {
+        return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
+    }
+    let scheduled_for = if form.scheduled_for.is_empty() {
+        None
+    } else {
+        match parse_schedule(&form.scheduled_for) {
+            Ok(t) => Some(t),
+            Err(e) => {
+                return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
+            }
+        }
+    };
+    let lists = match select_lists(&pool, &form.lists).await {
+        Ok(lists) => lists,
+        Err(e @ ListSelectionError::UnknownList(_)) => {
+            return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+    let segment_id = match select_segment(&pool, form.segment.as_deref()).await {
+        Ok(segment_id) => segment_id,
+        Err(e @ SegmentSelectionError::UnknownSegment(_)) => {
+            return Ok(send_flash_message_and_redirect(e, "/admin/newsletter"));
+        }
+        Err(e) => return Err(e500(e)),
+    };
     let user_id = user_id.into_inner();
-    let mut transaction = match try_processing(&pool, &form.0.idempotency_key, *user_id)
+    let mut transaction = match try_processing(&pool, &form.idempotency_key, *user_id)
         .await
         .map_err(e500)?
     {
         NextAction::StartProcessing(t) => t,
         NextAction::ReturnSavedResponse(saved_response) => {
-            success_message().send();
+            success_message(scheduled_for).send();
             return Ok(saved_response);
         }
     };
     let issue_id = insert_newsletter_issue(
         &mut transaction,
-        form.0.title.as_ref(),
-        form.0.text_content.as_ref(),
-        form.0.html_content.as_ref(),
+        form.title.as_ref(),
+        form.text_content.as_ref(),
+        form.html_content.as_ref(),
+        scheduled_for,
     )
     .await
     .context("Failed to store newsletter issue details")
     .map_err(e500)?;
-
-    enqueue_delivery_tasks(&mut transaction, issue_id)
+    let list_ids: Vec<Uuid> = lists.iter().map(|l| l.list_id).collect();
+    assign_issue_lists(&mut transaction, issue_id, &list_ids)
         .await
-        .context("Failed to enqueue delivery tasks")
+        .context("Failed to store the lists of the newsletter issue")
         .map_err(e500)?;
-    success_message().send();
+    assign_issue_segment(&mut transaction, issue_id, segment_id)
+        .await
+        .context("Failed to store the segment of the newsletter issue")
+        .map_err(e500)?;
+
+    // Scheduled issues are fanned out by the delivery worker once their time has come.
+    if scheduled_for.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")
+            .map_err(e500)?;
+    }
+    success_message(scheduled_for).send();
     let response = see_other("/admin/newsletter");
-    let response = save_response(transaction, &form.0.idempotency_key, *user_id, response)
+    let response = save_response(transaction, &form.idempotency_key, *user_id, response)
         .await
         .map_err(e500)?;
     Ok(response)
 }
 
 #[tracing::instrument(skip_all)]
-async fn insert_newsletter_issue(
+pub(crate) async fn insert_newsletter_issue(
     transaction: &mut Transaction<'_, Postgres>,
     title: &str,
     text_content: &str,
     html_content: &str,
+    scheduled_for: Option<DateTime<Utc>>,
 ) -> Result<Uuid, sqlx::Error> {
     let newsletter_issue_id = Uuid::new_v4();
+    let status = if scheduled_for.is_some() {
+        "scheduled"
+    } else {
+        "published"
+    };
     sqlx::query!(
         r#"
         INSERT INTO newsletter_issues (
@@ -80,40 +140,27 @@ async fn insert_newsletter_issue(
             title,
             text_content,
             html_content,
-            published_at
+            published_at,
+            status,
+            scheduled_for
         )
-        VALUES ($1, $2, $3, $4, now())
+        VALUES ($1, $2, $3, $4, now(), $5, $6)
         "#,
         newsletter_issue_id,
         title,
         text_content,
-        html_content
+        html_content,
+        status,
+        scheduled_for
     )
     .execute(transaction)
     .await?;
     Ok(newsletter_issue_id)
 }
 
-#[tracing::instrument(skip_all)]
-async fn enqueue_delivery_tasks(
-    transaction: &mut Transaction<'_, Postgres>,
-    newsletter_issue_id: Uuid,
-) -> Result<(), sqlx::Error> {
-    sqlx::query!(
-        r#"
-        INSERT INTO issue_delivery_queue (
-            newsletter_issue_id,
-            subscriber_email
-        )
-        SELECT $1, email
-        FROM subscriptions
-        WHERE status = 'confirmed'
-        "#,
-        newsletter_issue_id,
-    )
-    .execute(transaction)
-    .await?;
-    Ok(())
+pub(crate) fn validate_issue_content(text_content: &str, html_content: &str) -> Result<(), String> {
+    validate_template(text_content).map_err(|e| format!("Plain text body: {}", e))?;
+    validate_template(html_content).map_err(|e| format!("HTML body: {}", e))
 }
 
 fn send_flash_message_and_redirect(error: impl ToString, location: &str) -> HttpResponse {
@@ -121,6 +168,14 @@ fn send_flash_message_and_redirect(error: impl ToString, location: &str) -> Http
     see_other(location)
 }
 
-fn success_message() -> FlashMessage {
-    FlashMessage::info("The newsletter issue has been accepted - emails will go out shortly.")
+pub(crate) fn success_message(scheduled_for: Option<DateTime<Utc>>) -> FlashMessage {
+    match scheduled_for {
+        Some(t) => FlashMessage::info(format!(
+            "The newsletter issue has been scheduled - emails will go out on {}.",
+            t.to_rfc2822()
+        )),
+        None => FlashMessage::info(
+            "The newsletter issue has been accepted - emails will go out shortly.",
+        ),
+    }
 }
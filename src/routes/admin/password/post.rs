@@ -1,9 +1,13 @@
-use crate::authentication::{update_password_hash, validate_credentials, Credentials, UserId};
+use crate::authentication::{
+    revoke_all_sessions, update_password_hash, validate_credentials, validate_new_password,
+    Credentials, PasswordHashing, UserId,
+};
 use crate::session_state::TypedSession;
 use crate::util::{e500, get_username, see_other};
 use actix_web::{post, web, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::{ExposeSecret, Secret};
+use anyhow::Context;
+use secrecy::Secret;
 use sqlx::PgPool;
 
 #[derive(serde::Deserialize)]
@@ -14,7 +18,7 @@ pub struct FormData {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(form, pool, session, hashing),
     fields(username=tracing::field::Empty, user_id=%*user_id)
 )]
 #[post("/password")]
@@ -23,21 +27,14 @@ pub async fn change_password(
     pool: web::Data<PgPool>,
     session: TypedSession,
     user_id: web::ReqData<UserId>,
+    hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, actix_web::Error> {
     let user_id = user_id.into_inner();
     let username = get_username(*user_id, &pool).await.map_err(e500)?;
     tracing::Span::current().record("username", &tracing::field::display(&username));
 
-    if form.0.new_password.expose_secret() != form.0.confirm_new_password.expose_secret() {
-        FlashMessage::error("New password does not match with confirmation password.").send();
-        return Ok(see_other("/admin/password"));
-    }
-
-    if !matches!(form.0.new_password.expose_secret().len(), 12..=127) {
-        FlashMessage::error(
-            "New password must at least 12 characters long but shorter than 128 characters.",
-        )
-        .send();
+    if let Err(e) = validate_new_password(&form.0.new_password, &form.0.confirm_new_password) {
+        FlashMessage::error(e).send();
         return Ok(see_other("/admin/password"));
     }
 
@@ -46,10 +43,25 @@ pub async fn change_password(
         password: form.0.current_password,
     };
 
-    match validate_credentials(credentials.clone(), &pool).await {
+    match validate_credentials(credentials, &pool, &hashing).await {
         Ok(_user_id) => {
-            update_password_hash(credentials, &pool)
+            let mut transaction = pool
+                .begin()
+                .await
+                .context("Failed to acquire a Postgres connection from the pool.")
+                .map_err(e500)?;
+            update_password_hash(&mut transaction, *user_id, form.0.new_password, &hashing)
+                .await
+                .map_err(e500)?;
+            // Every device has to login again with the new password, this one included.
+            revoke_all_sessions(&mut transaction, *user_id)
+                .await
+                .context("Failed to revoke the sessions of the user.")
+                .map_err(e500)?;
+            transaction
+                .commit()
                 .await
+                .context("Failed to commit the password change.")
                 .map_err(e500)?;
             session.purge();
             FlashMessage::info("Password updated successfully. Please login to continue.").send();
@@ -0,0 +1,79 @@
+use crate::authentication::{Permission, Role};
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+
+struct SegmentSummary {
+    name: String,
+    expression: String,
+    n_recipients: i64,
+}
+
+#[derive(Template)]
+#[template(path = "segments.html")]
+struct SegmentsTemplate<'a> {
+    segments: Vec<SegmentSummary>,
+    can_manage: bool,
+    messages: Vec<&'a str>,
+}
+
+#[get("/segments")]
+#[tracing::instrument(skip(flash_messages, pool, role))]
+pub async fn list_segments(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let segments = get_segment_summaries(&pool)
+        .await
+        .context("Failed to retrieve the segments.")
+        .map_err(e500)?;
+
+    let segments_page = SegmentsTemplate {
+        segments,
+        can_manage: role.can(Permission::ManageSubscribers),
+        messages,
+    };
+    let segments_html = segments_page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(segments_html))
+}
+
+// Recipients are counted the way `enqueue_delivery_tasks` picks them for an issue sent to
+// every list: only confirmed members of at least one list are counted.
+#[tracing::instrument(skip(pool))]
+async fn get_segment_summaries(pool: &PgPool) -> Result<Vec<SegmentSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        SegmentSummary,
+        r#"
+        SELECT
+            sg.name,
+            sg.expression,
+            (
+                SELECT count(*)
+                FROM subscriptions s
+                WHERE
+                    s.status = 'confirmed' AND
+                    (s.paused_until IS NULL OR s.paused_until <= now()) AND
+                    EXISTS (
+                        SELECT 1 FROM list_memberships m
+                        WHERE m.subscriber_id = s.id AND m.status = 'confirmed'
+                    ) AND
+                    subscriber_matches_segment(s.tags, s.attributes, sg.conditions)
+            ) AS "n_recipients!"
+        FROM segments sg
+        ORDER BY sg.name
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,60 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::SegmentExpression;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    name: String,
+    expression: String,
+}
+
+#[post("/segments")]
+#[tracing::instrument(skip(form, pool, role))]
+pub async fn create_segment(
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let name = form.0.name.trim();
+    let expression = form.0.expression.trim();
+    if name.is_empty() {
+        FlashMessage::error("Please give the segment a name.").send();
+        return Ok(see_other("/admin/segments"));
+    }
+    let conditions = match SegmentExpression::parse(expression) {
+        Ok(parsed) => parsed.to_json(),
+        Err(e) => {
+            FlashMessage::error(format!("The segment expression is invalid: {}", e)).send();
+            return Ok(see_other("/admin/segments"));
+        }
+    };
+
+    let n_inserted = sqlx::query!(
+        r#"
+        INSERT INTO segments (segment_id, name, expression, conditions)
+        VALUES ($1, $2, $3, $4::jsonb)
+        ON CONFLICT (name) DO NOTHING
+        "#,
+        Uuid::new_v4(),
+        name,
+        expression,
+        conditions as String,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the new segment.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_inserted == 0 {
+        FlashMessage::error(format!("A segment called '{}' already exists.", name)).send();
+    } else {
+        FlashMessage::info(format!("The segment '{}' has been created.", name)).send();
+    }
+    Ok(see_other("/admin/segments"))
+}
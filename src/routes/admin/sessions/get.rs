@@ -0,0 +1,87 @@
+use crate::authentication::UserId;
+use crate::session_state::TypedSession;
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct ActiveSession {
+    session_id: Uuid,
+    user_agent: Option<String>,
+    ip_address: Option<String>,
+    created_at: DateTime<Utc>,
+    last_seen_at: DateTime<Utc>,
+    is_current: bool,
+}
+
+#[derive(Template)]
+#[template(path = "sessions.html")]
+struct SessionsTemplate<'a> {
+    sessions: Vec<ActiveSession>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/sessions")]
+#[tracing::instrument(skip(flash_messages, pool, session), fields(user_id=%*user_id))]
+pub async fn list_sessions(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let current_session_id = session.get_session_id().map_err(e500)?;
+    let sessions = get_active_sessions(&pool, **user_id, current_session_id)
+        .await
+        .context("Failed to retrieve the sessions of the user.")
+        .map_err(e500)?;
+
+    let sessions_page = SessionsTemplate { sessions, messages };
+    let sessions_html = sessions_page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(sessions_html))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_active_sessions(
+    pool: &PgPool,
+    user_id: Uuid,
+    current_session_id: Option<Uuid>,
+) -> Result<Vec<ActiveSession>, sqlx::Error> {
+    // Session state expires from Redis a day after it was last written, so anything not seen
+    // for longer than that is gone even if it was never revoked.
+    let sessions = sqlx::query!(
+        r#"
+        SELECT session_id, user_agent, ip_address, created_at, last_seen_at
+        FROM user_sessions
+        WHERE
+            user_id = $1 AND
+            revoked_at IS NULL AND
+            last_seen_at > now() - interval '1 day'
+        ORDER BY last_seen_at DESC
+        "#,
+        user_id,
+    )
+    .fetch_all(pool)
+    .await?
+    .into_iter()
+    .map(|r| ActiveSession {
+        session_id: r.session_id,
+        user_agent: r.user_agent,
+        ip_address: r.ip_address,
+        created_at: r.created_at,
+        last_seen_at: r.last_seen_at,
+        is_current: Some(r.session_id) == current_session_id,
+    })
+    .collect();
+    Ok(sessions)
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::list_sessions;
+pub use post::{revoke_other_user_sessions, revoke_user_session};
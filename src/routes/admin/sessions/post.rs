@@ -0,0 +1,47 @@
+use crate::authentication::{revoke_other_sessions, revoke_session, UserId};
+use crate::session_state::TypedSession;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[post("/sessions/{session_id}/revoke")]
+#[tracing::instrument(skip(pool), fields(user_id=%*user_id))]
+pub async fn revoke_user_session(
+    session_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    // Scoped to the user, so that nobody can end someone else's session by guessing its id.
+    let revoked = revoke_session(pool.get_ref(), **user_id, session_id.into_inner())
+        .await
+        .context("Failed to revoke the session.")
+        .map_err(e500)?;
+    if revoked {
+        FlashMessage::info("The session has been revoked.").send();
+    } else {
+        FlashMessage::error("The session does not exist or has already ended.").send();
+    }
+    Ok(see_other("/admin/sessions"))
+}
+
+#[post("/sessions/revoke_others")]
+#[tracing::instrument(skip(pool, session), fields(user_id=%*user_id))]
+pub async fn revoke_other_user_sessions(
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let current_session_id = session
+        .get_session_id()
+        .map_err(e500)?
+        .ok_or_else(|| e500("The session has no id."))?;
+    let n_revoked = revoke_other_sessions(pool.get_ref(), **user_id, current_session_id)
+        .await
+        .context("Failed to revoke the other sessions.")
+        .map_err(e500)?;
+    FlashMessage::info(format!("Revoked {} other session(s).", n_revoked)).send();
+    Ok(see_other("/admin/sessions"))
+}
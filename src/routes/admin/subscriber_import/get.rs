@@ -0,0 +1,100 @@
+use crate::authentication::{Permission, Role};
+use crate::mailing_lists::{get_lists, MailingList, DEFAULT_LIST};
+use crate::subscriber_import::{dry_run, AcceptedRow, SkippedRow};
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "subscriber_import_form.html")]
+struct ImportFormTemplate<'a> {
+    messages: Vec<&'a str>,
+}
+
+#[get("")]
+pub async fn subscriber_import_form(
+    flash_messages: IncomingFlashMessages,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let form_html = ImportFormTemplate { messages }.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(form_html))
+}
+
+#[derive(Template)]
+#[template(path = "subscriber_import_report.html")]
+struct ImportReportTemplate<'a> {
+    import_id: Uuid,
+    imported_at: Option<DateTime<Utc>>,
+    accepted: Vec<AcceptedRow>,
+    duplicates: Vec<SkippedRow>,
+    rejected: Vec<SkippedRow>,
+    lists: Vec<MailingList>,
+    default_list: &'a str,
+    messages: Vec<&'a str>,
+}
+
+// The dry run is redone on every visit, so the report reflects the subscribers who
+// joined since the upload.
+#[get("/{import_id}")]
+pub async fn subscriber_import_report(
+    import_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let import_id = import_id.into_inner();
+    let upload = match sqlx::query!(
+        "SELECT csv, imported_at FROM subscriber_imports WHERE import_id = $1",
+        import_id,
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to retrieve the uploaded file.")
+    .map_err(e500)?
+    {
+        Some(upload) => upload,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    // The file was checked when it was uploaded, so any error is unexpected.
+    let report = dry_run(pool.get_ref(), &upload.csv)
+        .await
+        .context("Failed to check the uploaded file.")
+        .map_err(e500)?;
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let lists = get_lists(&pool)
+        .await
+        .context("Failed to retrieve the lists.")
+        .map_err(e500)?;
+
+    let report_page = ImportReportTemplate {
+        import_id,
+        imported_at: upload.imported_at,
+        accepted: report.accepted,
+        duplicates: report.duplicates,
+        rejected: report.rejected,
+        lists,
+        default_list: DEFAULT_LIST,
+        messages,
+    };
+    let report_html = report_page.render().map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(report_html))
+}
@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{subscriber_import_form, subscriber_import_report};
+pub use post::{run_subscriber_import, upload_subscriber_import};
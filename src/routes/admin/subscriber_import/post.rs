@@ -0,0 +1,174 @@
+use crate::authentication::{Permission, Role, UserId};
+use crate::mailing_lists::{select_lists, ListSelectionError};
+use crate::subscriber_import::{dry_run, import_subscribers, parse_csv, ImportMode};
+use crate::util::{e400, e500, parse_form, see_other};
+use actix_web::http::header::CONTENT_TYPE;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::PgPool;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+struct UploadFormData {
+    csv: String,
+}
+
+// The file is pasted into the upload form, or sent as is with `Content-Type: text/csv`.
+fn read_csv(request: &HttpRequest, body: &[u8]) -> Result<String, String> {
+    let is_csv = request
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.starts_with("text/csv"))
+        .unwrap_or(false);
+    if is_csv {
+        String::from_utf8(body.to_vec()).map_err(|_| "The CSV file must be UTF-8 encoded.".into())
+    } else {
+        parse_form::<UploadFormData>(body).map(|f| f.csv)
+    }
+}
+
+#[post("")]
+#[tracing::instrument(
+    name = "Upload a subscriber import",
+    skip(request, body, pool, role),
+    fields(user_id=%*user_id)
+)]
+pub async fn upload_subscriber_import(
+    request: HttpRequest,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let csv = match read_csv(&request, &body).and_then(|csv| parse_csv(&csv).map(|_| csv)) {
+        Ok(csv) => csv,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/subscribers/import"));
+        }
+    };
+
+    let import_id = Uuid::new_v4();
+    sqlx::query!(
+        "INSERT INTO subscriber_imports (import_id, uploaded_by, csv) VALUES ($1, $2, $3)",
+        import_id,
+        **user_id,
+        csv,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to store the uploaded file.")
+    .map_err(e500)?;
+    Ok(see_other(&format!(
+        "/admin/subscribers/import/{}",
+        import_id
+    )))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ImportFormData {
+    mode: String,
+    #[serde(default)]
+    consent_source: String,
+    #[serde(default)]
+    list: String,
+}
+
+#[post("/{import_id}")]
+#[tracing::instrument(name = "Import subscribers", skip(form, pool, role))]
+pub async fn run_subscriber_import(
+    import_id: web::Path<Uuid>,
+    form: web::Form<ImportFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let import_id = import_id.into_inner();
+    let report_page = format!("/admin/subscribers/import/{}", import_id);
+    let form = form.into_inner();
+    let mode = match form.mode.as_str() {
+        "confirmed" if form.consent_source.trim().is_empty() => {
+            FlashMessage::error(
+                "Please record how these subscribers gave their consent before importing them as confirmed.",
+            )
+            .send();
+            return Ok(see_other(&report_page));
+        }
+        "confirmed" => ImportMode::Confirmed {
+            consent_source: form.consent_source.trim().to_string(),
+        },
+        "pending" => ImportMode::Pending,
+        _ => return Err(e400("The import mode must be `confirmed` or `pending`.")),
+    };
+    let slugs: Vec<String> = Some(form.list)
+        .filter(|l| !l.is_empty())
+        .into_iter()
+        .collect();
+    let list = match select_lists(&pool, &slugs).await {
+        Ok(mut lists) => lists.remove(0),
+        Err(e @ ListSelectionError::UnknownList(_)) => {
+            FlashMessage::error(e.to_string()).send();
+            return Ok(see_other(&report_page));
+        }
+        Err(e) => return Err(e500(e)),
+    };
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    // Marking the file first keeps a double submission from importing it twice.
+    let upload = sqlx::query!(
+        r#"
+        UPDATE subscriber_imports
+        SET imported_at = now()
+        WHERE import_id = $1 AND imported_at IS NULL
+        RETURNING csv
+        "#,
+        import_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to mark the file as imported.")
+    .map_err(e500)?;
+    let csv = match upload {
+        Some(upload) => upload.csv,
+        None => {
+            FlashMessage::error("This file has already been imported.").send();
+            return Ok(see_other(&report_page));
+        }
+    };
+    let report = dry_run(&mut transaction, &csv)
+        .await
+        .context("Failed to check the uploaded file.")
+        .map_err(e500)?;
+    let subscriber_ids =
+        import_subscribers(&mut transaction, &report.accepted, &mode, list.list_id)
+            .await
+            .context("Failed to import the subscribers.")
+            .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the import.")
+        .map_err(e500)?;
+
+    let message = match mode {
+        ImportMode::Confirmed { .. } => format!(
+            "{} subscribers have been imported to {}.",
+            subscriber_ids.len(),
+            list.name
+        ),
+        ImportMode::Pending => format!(
+            "{} subscribers have been imported to {} - their confirmation emails will go out shortly.",
+            subscriber_ids.len(),
+            list.name
+        ),
+    };
+    FlashMessage::info(message).send();
+    Ok(see_other("/admin/subscribers"))
+}
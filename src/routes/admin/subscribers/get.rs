@@ -0,0 +1,366 @@
+use crate::authentication::{Permission, Role};
+use crate::mailing_lists::{get_memberships, Membership};
+use crate::util::e500;
+use actix_web::http::header::{ContentDisposition, ContentType, DispositionParam, DispositionType};
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use std::borrow::Cow;
+use uuid::Uuid;
+
+const PAGE_SIZE: i64 = 50;
+const STATUSES: [&str; 5] = [
+    "pending_confirmation",
+    "confirmed",
+    "unsubscribed",
+    "bounced",
+    "complained",
+];
+
+#[derive(serde::Deserialize, serde::Serialize)]
+pub struct SubscriberFilter {
+    #[serde(default)]
+    search: String,
+    #[serde(default)]
+    status: String,
+    #[serde(default = "first_page")]
+    page: i64,
+}
+
+fn first_page() -> i64 {
+    1
+}
+
+impl SubscriberFilter {
+    // Links to other pages (and to the export) keep the current search and filter.
+    fn query_string(&self, page: i64) -> String {
+        serde_urlencoded::to_string(SubscriberFilter {
+            search: self.search.clone(),
+            status: self.status.clone(),
+            page,
+        })
+        .unwrap_or_default()
+    }
+
+    fn has_status(&self, status: &str) -> bool {
+        self.status == status
+    }
+}
+
+struct SubscriberSummary {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "subscribers.html")]
+struct SubscribersTemplate<'a> {
+    subscribers: Vec<SubscriberSummary>,
+    filter: &'a SubscriberFilter,
+    statuses: [&'static str; 5],
+    n_subscribers: i64,
+    n_pages: i64,
+    previous_page: Option<String>,
+    next_page: Option<String>,
+    export_query: String,
+    can_manage: bool,
+    messages: Vec<&'a str>,
+}
+
+#[get("/subscribers")]
+pub async fn list_subscribers(
+    filter: web::Query<SubscriberFilter>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let mut filter = filter.into_inner();
+    let n_subscribers = count_subscribers(&pool, &filter)
+        .await
+        .context("Failed to count subscribers.")
+        .map_err(e500)?;
+    let n_pages = ((n_subscribers + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+    // Pages past the end show the last one, and keep the offset from overflowing.
+    filter.page = filter.page.clamp(1, n_pages);
+    let subscribers = get_subscribers(&pool, &filter, Some(filter.page))
+        .await
+        .context("Failed to retrieve subscribers.")
+        .map_err(e500)?;
+
+    let subscribers_page = SubscribersTemplate {
+        subscribers,
+        filter: &filter,
+        statuses: STATUSES,
+        n_subscribers,
+        n_pages,
+        previous_page: (filter.page > 1).then(|| filter.query_string(filter.page - 1)),
+        next_page: (filter.page < n_pages).then(|| filter.query_string(filter.page + 1)),
+        export_query: filter.query_string(1),
+        can_manage: role.can(Permission::ManageSubscribers),
+        messages,
+    };
+    let subscribers_html = subscribers_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(subscribers_html))
+}
+
+// Exports every subscriber matching the filter, regardless of the page.
+#[get("/subscribers/export")]
+#[tracing::instrument(name = "Export subscribers", skip(filter, pool))]
+pub async fn export_subscribers(
+    filter: web::Query<SubscriberFilter>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscribers = get_subscribers(&pool, &filter, None)
+        .await
+        .context("Failed to retrieve subscribers.")
+        .map_err(e500)?;
+    let csv = subscribers_to_csv(&subscribers)
+        .context("Failed to write subscribers as CSV.")
+        .map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type("text/csv; charset=utf-8")
+        .insert_header(ContentDisposition {
+            disposition: DispositionType::Attachment,
+            parameters: vec![DispositionParam::Filename("subscribers.csv".into())],
+        })
+        .body(csv))
+}
+
+// Names and addresses come from the public signup form. Spreadsheets run cells that start
+// like a formula, so those are prefixed with a quote to be shown as text.
+fn escape_formula(cell: &str) -> Cow<'_, str> {
+    if cell.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", cell))
+    } else {
+        Cow::Borrowed(cell)
+    }
+}
+
+fn subscribers_to_csv(subscribers: &[SubscriberSummary]) -> Result<Vec<u8>, anyhow::Error> {
+    let mut writer = csv::Writer::from_writer(vec![]);
+    writer.write_record(["email", "name", "status", "subscribed_at"])?;
+    for s in subscribers {
+        writer.write_record([
+            escape_formula(&s.email).as_ref(),
+            escape_formula(&s.name).as_ref(),
+            s.status.as_str(),
+            s.subscribed_at.to_rfc3339().as_str(),
+        ])?;
+    }
+    Ok(writer.into_inner()?)
+}
+
+struct Subscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    status: String,
+    subscribed_at: DateTime<Utc>,
+    confirmation_sent_at: Option<DateTime<Utc>>,
+    confirmation_expires_at: Option<DateTime<Utc>>,
+    confirmed_at: Option<DateTime<Utc>>,
+    consent_source: Option<String>,
+    // Both in the shape the edit form takes them back.
+    tags: String,
+    attributes: String,
+}
+
+struct Delivery {
+    newsletter_issue_id: Uuid,
+    title: String,
+    status: String,
+    error: Option<String>,
+    updated_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "subscriber.html")]
+struct SubscriberTemplate<'a> {
+    subscriber: Subscriber,
+    memberships: Vec<Membership>,
+    deliveries: Vec<Delivery>,
+    can_manage: bool,
+    messages: Vec<&'a str>,
+}
+
+#[get("/subscribers/{subscriber_id}")]
+pub async fn subscriber_details(
+    subscriber_id: web::Path<Uuid>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber = match get_subscriber(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber.")
+        .map_err(e500)?
+    {
+        Some(subscriber) => subscriber,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+    let memberships = get_memberships(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber's lists.")
+        .map_err(e500)?;
+    let deliveries = get_deliveries(&pool, &subscriber.email)
+        .await
+        .context("Failed to retrieve the delivery history.")
+        .map_err(e500)?;
+
+    let subscriber_page = SubscriberTemplate {
+        subscriber,
+        memberships,
+        deliveries,
+        can_manage: role.can(Permission::ManageSubscribers),
+        messages,
+    };
+    let subscriber_html = subscriber_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(subscriber_html))
+}
+
+#[tracing::instrument(skip_all)]
+async fn count_subscribers(pool: &PgPool, filter: &SubscriberFilter) -> Result<i64, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT COUNT(*) AS "count!"
+        FROM subscriptions
+        WHERE
+            ($1 = '' OR strpos(lower(email), lower($1)) > 0 OR strpos(lower(name), lower($1)) > 0) AND
+            ($2 = '' OR status = $2)
+        "#,
+        filter.search.trim(),
+        filter.status,
+    )
+    .fetch_one(pool)
+    .await?;
+    Ok(row.count)
+}
+
+// Without a page, every matching subscriber is returned.
+#[tracing::instrument(skip(pool, filter))]
+async fn get_subscribers(
+    pool: &PgPool,
+    filter: &SubscriberFilter,
+    page: Option<i64>,
+) -> Result<Vec<SubscriberSummary>, sqlx::Error> {
+    let (limit, offset) = match page {
+        Some(page) => (Some(PAGE_SIZE), (page - 1) * PAGE_SIZE),
+        None => (None, 0),
+    };
+    sqlx::query_as!(
+        SubscriberSummary,
+        r#"
+        SELECT id, email, name, status, subscribed_at
+        FROM subscriptions
+        WHERE
+            ($1 = '' OR strpos(lower(email), lower($1)) > 0 OR strpos(lower(name), lower($1)) > 0) AND
+            ($2 = '' OR status = $2)
+        ORDER BY subscribed_at DESC, email
+        LIMIT $3 OFFSET $4
+        "#,
+        filter.search.trim(),
+        filter.status,
+        limit,
+        offset,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_subscriber(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Subscriber>, sqlx::Error> {
+    sqlx::query_as!(
+        Subscriber,
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            s.status,
+            s.subscribed_at,
+            t.created_at AS "confirmation_sent_at?",
+            t.expires_at AS "confirmation_expires_at?",
+            t.used_at AS "confirmed_at?",
+            s.consent_source,
+            array_to_string(s.tags, ', ') AS "tags!",
+            COALESCE(
+                (SELECT string_agg(key || '=' || value, E'\n' ORDER BY key) FROM jsonb_each_text(s.attributes)),
+                ''
+            ) AS "attributes!"
+        FROM subscriptions s
+        LEFT JOIN LATERAL (
+            SELECT created_at, expires_at, used_at
+            FROM subscription_tokens
+            WHERE subscriber_id = s.id
+            ORDER BY created_at DESC
+            LIMIT 1
+        ) t ON true
+        WHERE s.id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_deliveries(pool: &PgPool, email: &str) -> Result<Vec<Delivery>, sqlx::Error> {
+    sqlx::query_as!(
+        Delivery,
+        r#"
+        SELECT
+            d.newsletter_issue_id AS "newsletter_issue_id!",
+            i.title,
+            d.status AS "status!",
+            d.error,
+            d.updated_at AS "updated_at!"
+        FROM (
+            SELECT
+                newsletter_issue_id,
+                'queued' AS status,
+                CASE WHEN n_retries > 0
+                    THEN 'Retried ' || n_retries || ' time(s)'
+                END AS error,
+                execute_after AS updated_at
+            FROM issue_delivery_queue
+            WHERE subscriber_email = $1
+            UNION ALL
+            SELECT newsletter_issue_id, outcome, error, recorded_at
+            FROM issue_delivery_outcomes
+            WHERE subscriber_email = $1
+        ) AS d
+        JOIN newsletter_issues i ON i.newsletter_issue_id = d.newsletter_issue_id
+        ORDER BY d.updated_at DESC
+        "#,
+        email,
+    )
+    .fetch_all(pool)
+    .await
+}
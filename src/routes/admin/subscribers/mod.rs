@@ -0,0 +1,5 @@
+mod get;
+mod post;
+
+pub use get::{export_subscribers, list_subscribers, subscriber_details};
+pub use post::{delete_subscriber, unsubscribe_subscriber, update_subscriber_attributes};
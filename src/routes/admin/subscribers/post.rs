@@ -0,0 +1,191 @@
+use crate::authentication::{Permission, Role};
+use crate::domain::{SubscriberAttributes, SubscriberTags};
+use crate::issue_delivery_worker::skip_queued_deliveries;
+use crate::mailing_lists::leave_all_lists;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[post("/subscribers/{subscriber_id}/unsubscribe")]
+#[tracing::instrument(name = "Unsubscribe a subscriber on their behalf", skip(pool))]
+pub async fn unsubscribe_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let email = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE
+            id = $1 AND
+            status IN ('pending_confirmation', 'confirmed')
+        RETURNING email
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to unsubscribe the subscriber.")
+    .map_err(e500)?
+    .map(|r| r.email);
+
+    match email {
+        Some(email) => {
+            skip_queued_deliveries(
+                &mut transaction,
+                &email,
+                "The subscriber has been unsubscribed by an administrator.",
+            )
+            .await
+            .context("Failed to drop the queued deliveries.")
+            .map_err(e500)?;
+            leave_all_lists(&mut transaction, subscriber_id)
+                .await
+                .context("Failed to remove the subscriber from their lists.")
+                .map_err(e500)?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the unsubscription.")
+                .map_err(e500)?;
+            FlashMessage::info("The subscriber has been unsubscribed.").send();
+        }
+        None => {
+            FlashMessage::error("The subscriber does not exist or is no longer subscribed.").send();
+        }
+    }
+    Ok(see_other(&format!("/admin/subscribers/{}", subscriber_id)))
+}
+
+// Deletion removes the subscriber along with their tokens and pending deliveries;
+// the outcomes of past deliveries are kept for the issue statistics.
+#[post("/subscribers/{subscriber_id}/delete")]
+#[tracing::instrument(name = "Delete a subscriber", skip(pool))]
+pub async fn delete_subscriber(
+    subscriber_id: web::Path<Uuid>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let deleted = delete_subscription(&mut transaction, subscriber_id.into_inner())
+        .await
+        .context("Failed to delete the subscriber.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the deletion of the subscriber.")
+        .map_err(e500)?;
+
+    if deleted {
+        FlashMessage::info("The subscriber has been deleted.").send();
+    } else {
+        FlashMessage::error("The subscriber does not exist.").send();
+    }
+    Ok(see_other("/admin/subscribers"))
+}
+
+// The delivery worker joins queued deliveries on `subscriptions`: rows left behind
+// for a deleted address would never be dequeued, so they go first.
+#[tracing::instrument(skip(transaction))]
+async fn delete_subscription(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let email = match sqlx::query!(
+        "SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    {
+        Some(row) => row.email,
+        None => return Ok(false),
+    };
+    sqlx::query!(
+        "DELETE FROM issue_delivery_queue WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM issue_delivery_failures WHERE subscriber_email = $1",
+        email
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!(
+        "DELETE FROM subscription_tokens WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(&mut *transaction)
+    .await?;
+    sqlx::query!("DELETE FROM subscriptions WHERE id = $1", subscriber_id)
+        .execute(&mut *transaction)
+        .await?;
+    Ok(true)
+}
+
+#[derive(serde::Deserialize)]
+pub struct AttributesFormData {
+    tags: String,
+    attributes: String,
+}
+
+#[post("/subscribers/{subscriber_id}/attributes")]
+#[tracing::instrument(
+    name = "Update the tags and attributes of a subscriber",
+    skip(form, pool)
+)]
+pub async fn update_subscriber_attributes(
+    subscriber_id: web::Path<Uuid>,
+    form: web::Form<AttributesFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageSubscribers)?;
+    let subscriber_id = subscriber_id.into_inner();
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    let parsed = SubscriberTags::parse(&form.tags)
+        .and_then(|tags| Ok((tags, SubscriberAttributes::parse(&form.attributes)?)));
+    let (tags, attributes) = match parsed {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&subscriber_page));
+        }
+    };
+
+    let n_updated = sqlx::query!(
+        "UPDATE subscriptions SET tags = $2, attributes = $3::jsonb WHERE id = $1",
+        subscriber_id,
+        tags.as_ref(),
+        attributes.to_json() as String,
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the tags and attributes.")
+    .map_err(e500)?
+    .rows_affected();
+    if n_updated == 0 {
+        return Ok(HttpResponse::NotFound().finish());
+    }
+    FlashMessage::info("The tags and attributes have been saved.").send();
+    Ok(see_other(&subscriber_page))
+}
@@ -0,0 +1,87 @@
+use crate::authentication::{
+    count_unused_recovery_codes, generate_totp_secret, get_totp_secret, provisioning_uri,
+    qr_code_svg, UserId,
+};
+use crate::session_state::TypedSession;
+use crate::util::{e500, get_username};
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use askama::Template;
+use secrecy::{ExposeSecret, Secret};
+use sqlx::PgPool;
+
+struct TwoFactorSetup {
+    qr_code_svg: String,
+    provisioning_uri: String,
+    secret: String,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor.html")]
+struct TwoFactorTemplate<'a> {
+    enabled: bool,
+    n_recovery_codes: i64,
+    setup: Option<TwoFactorSetup>,
+    messages: Vec<&'a str>,
+}
+
+#[get("/two_factor")]
+#[tracing::instrument(skip(flash_messages, pool, session), fields(user_id=%*user_id))]
+pub async fn two_factor_settings(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+
+    let page = if get_totp_secret(pool.get_ref(), *user_id)
+        .await
+        .map_err(e500)?
+        .is_some()
+    {
+        let n_recovery_codes = count_unused_recovery_codes(&pool, *user_id)
+            .await
+            .map_err(e500)?;
+        TwoFactorTemplate {
+            enabled: true,
+            n_recovery_codes,
+            setup: None,
+            messages,
+        }
+    } else {
+        // The secret is kept in the session until a code generated from it has been confirmed.
+        let secret = match session.get_pending_totp_secret().map_err(e500)? {
+            Some(secret) => Secret::new(secret),
+            None => {
+                let secret = generate_totp_secret();
+                session
+                    .insert_pending_totp_secret(secret.expose_secret())
+                    .map_err(e500)?;
+                secret
+            }
+        };
+        let username = get_username(*user_id, &pool).await.map_err(e500)?;
+        let provisioning_uri = provisioning_uri(&secret, &username).map_err(e500)?;
+        TwoFactorTemplate {
+            enabled: false,
+            n_recovery_codes: 0,
+            setup: Some(TwoFactorSetup {
+                qr_code_svg: qr_code_svg(&provisioning_uri).map_err(e500)?,
+                provisioning_uri,
+                secret: secret.expose_secret().clone(),
+            }),
+            messages,
+        }
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
@@ -0,0 +1,118 @@
+use crate::authentication::{
+    disable_two_factor, enable_two_factor, generate_recovery_codes, validate_credentials,
+    verify_totp_code, Credentials, PasswordHashing, UserId,
+};
+use crate::session_state::TypedSession;
+use crate::util::{e500, get_username, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use askama::Template;
+use chrono::Utc;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct EnableFormData {
+    code: String,
+}
+
+#[derive(Template)]
+#[template(path = "two_factor_recovery_codes.html")]
+struct RecoveryCodesTemplate {
+    recovery_codes: Vec<String>,
+}
+
+#[post("/two_factor")]
+#[tracing::instrument(skip(form, pool, session), fields(user_id=%*user_id))]
+pub async fn enable_two_factor_authentication(
+    form: web::Form<EnableFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    user_id: web::ReqData<UserId>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let secret = match session.get_pending_totp_secret().map_err(e500)? {
+        Some(secret) => Secret::new(secret),
+        None => {
+            FlashMessage::error("The set up has expired. Please scan the new QR code.").send();
+            return Ok(see_other("/admin/two_factor"));
+        }
+    };
+    let verified_step =
+        match verify_totp_code(&secret, form.0.code.trim(), Utc::now().timestamp() as u64)
+            .map_err(e500)?
+        {
+            Some(step) => step,
+            None => {
+                FlashMessage::error(
+                    "The code is invalid. Check the clock of your device and try again.",
+                )
+                .send();
+                return Ok(see_other("/admin/two_factor"));
+            }
+        };
+
+    let recovery_codes = generate_recovery_codes();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    enable_two_factor(
+        &mut transaction,
+        *user_id,
+        &secret,
+        verified_step,
+        &recovery_codes,
+    )
+    .await
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit enabling two-factor authentication.")
+        .map_err(e500)?;
+    session.remove_pending_totp_secret();
+
+    // Recovery codes are only ever shown here, so they are rendered rather than flashed.
+    let page_html = RecoveryCodesTemplate { recovery_codes }
+        .render()
+        .map_err(e500)?;
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+#[derive(serde::Deserialize)]
+pub struct DisableFormData {
+    current_password: Secret<String>,
+}
+
+#[post("/two_factor/disable")]
+#[tracing::instrument(skip(form, pool, hashing), fields(user_id=%*user_id))]
+pub async fn disable_two_factor_authentication(
+    form: web::Form<DisableFormData>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let user_id = user_id.into_inner();
+    let username = get_username(*user_id, &pool).await.map_err(e500)?;
+    let credentials = Credentials {
+        username,
+        password: form.0.current_password,
+    };
+    if validate_credentials(credentials, &pool, &hashing)
+        .await
+        .is_err()
+    {
+        FlashMessage::error("Current password is incorrect.").send();
+        return Ok(see_other("/admin/two_factor"));
+    }
+
+    disable_two_factor(&pool, *user_id).await.map_err(e500)?;
+    FlashMessage::info("Two-factor authentication has been turned off.").send();
+    Ok(see_other("/admin/two_factor"))
+}
@@ -0,0 +1,94 @@
+use crate::authentication::{Permission, Role};
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+struct UserSummary {
+    user_id: Uuid,
+    username: String,
+    email: Option<String>,
+    role: String,
+}
+
+struct PendingInvitation {
+    email: String,
+    role: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[derive(Template)]
+#[template(path = "users.html")]
+struct UsersTemplate<'a> {
+    users: Vec<UserSummary>,
+    invitations: Vec<PendingInvitation>,
+    roles: [Role; 4],
+    messages: Vec<&'a str>,
+}
+
+#[get("/users")]
+pub async fn list_users(
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let users = get_users(&pool)
+        .await
+        .context("Failed to retrieve users.")
+        .map_err(e500)?;
+    let invitations = get_pending_invitations(&pool)
+        .await
+        .context("Failed to retrieve pending invitations.")
+        .map_err(e500)?;
+
+    let users_page = UsersTemplate {
+        users,
+        invitations,
+        roles: Role::ALL,
+        messages,
+    };
+    let users_html = users_page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(users_html))
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_users(pool: &PgPool) -> Result<Vec<UserSummary>, sqlx::Error> {
+    sqlx::query_as!(
+        UserSummary,
+        r#"
+        SELECT user_id, username, email, role
+        FROM users
+        ORDER BY username
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
+
+#[tracing::instrument(skip_all)]
+async fn get_pending_invitations(pool: &PgPool) -> Result<Vec<PendingInvitation>, sqlx::Error> {
+    sqlx::query_as!(
+        PendingInvitation,
+        r#"
+        SELECT email, role, expires_at
+        FROM user_invitations
+        WHERE accepted_at IS NULL AND expires_at > now()
+        ORDER BY created_at
+        "#,
+    )
+    .fetch_all(pool)
+    .await
+}
@@ -0,0 +1,228 @@
+use crate::authentication::{Permission, Role, UserId};
+use crate::domain::invitation_token::InvitationToken;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::startup::ApplicationBaseUrl;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RoleFormData {
+    role: String,
+}
+
+#[post("/users/{user_id}/role")]
+#[tracing::instrument(name = "Change the role of a user", skip(form, pool))]
+pub async fn change_user_role(
+    user_id: web::Path<Uuid>,
+    form: web::Form<RoleFormData>,
+    pool: web::Data<PgPool>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let new_role = match Role::parse(&form.0.role) {
+        Ok(r) => r,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let user_id = user_id.into_inner();
+    let username = match sqlx::query!(r#"SELECT username FROM users WHERE user_id = $1"#, user_id)
+        .fetch_optional(pool.get_ref())
+        .await
+        .context("Failed to retrieve the user.")
+        .map_err(e500)?
+    {
+        Some(r) => r.username,
+        None => return Ok(HttpResponse::NotFound().finish()),
+    };
+
+    // The update is skipped if it would leave nobody able to manage users.
+    let n_updated = sqlx::query!(
+        r#"
+        UPDATE users
+        SET role = $2
+        WHERE
+            user_id = $1 AND
+            (
+                $2 = 'owner' OR
+                EXISTS (SELECT 1 FROM users WHERE role = 'owner' AND user_id <> $1)
+            )
+        "#,
+        user_id,
+        new_role.as_str(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the user role.")
+    .map_err(e500)?
+    .rows_affected();
+
+    if n_updated == 0 {
+        FlashMessage::error("There must always be at least one owner.").send();
+    } else {
+        FlashMessage::info(format!("{} is now a {}.", username, new_role)).send();
+    }
+    Ok(see_other("/admin/users"))
+}
+
+#[derive(serde::Deserialize)]
+pub struct InvitationFormData {
+    email: String,
+    role: String,
+}
+
+#[post("/users/invitations")]
+#[tracing::instrument(
+    name = "Invite a new user",
+    skip(form, pool, email_client, base_url),
+    fields(user_id=%*user_id)
+)]
+pub async fn invite_user(
+    form: web::Form<InvitationFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, actix_web::Error> {
+    role.require(Permission::ManageUsers)?;
+    let invited_role = match Role::parse(&form.0.role) {
+        Ok(r) => r,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/admin/users"));
+        }
+    };
+
+    let existing_user = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(pool.get_ref())
+    .await
+    .context("Failed to look up existing users.")
+    .map_err(e500)?;
+    if existing_user.is_some() {
+        FlashMessage::error(format!("{} already has an account.", email)).send();
+        return Ok(see_other("/admin/users"));
+    }
+
+    let invitation_token = InvitationToken::new();
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    store_invitation(
+        &mut transaction,
+        &invitation_token,
+        &email,
+        invited_role,
+        *user_id.into_inner(),
+    )
+    .await
+    .context("Failed to store the invitation.")
+    .map_err(e500)?;
+    // The invitation is only kept if the email carrying its link went out.
+    if let Err(e) = send_invitation_email(
+        &email_client,
+        &email,
+        invited_role,
+        &base_url.0,
+        &invitation_token,
+    )
+    .await
+    {
+        tracing::error!(
+            error.cause_chain = ?e,
+            error.message = %e,
+            "Failed to send an invitation email.",
+        );
+        FlashMessage::error("Failed to send the invitation email.").send();
+        return Ok(see_other("/admin/users"));
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the invitation.")
+        .map_err(e500)?;
+
+    FlashMessage::info(format!("An invitation has been sent to {}.", email)).send();
+    Ok(see_other("/admin/users"))
+}
+
+#[tracing::instrument(skip(transaction, invitation_token))]
+async fn store_invitation(
+    transaction: &mut Transaction<'_, Postgres>,
+    invitation_token: &InvitationToken,
+    email: &SubscriberEmail,
+    role: Role,
+    invited_by: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO user_invitations (invitation_token, email, role, invited_by, expires_at)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        invitation_token.as_ref(),
+        email.as_ref(),
+        role.as_str(),
+        invited_by,
+        Utc::now() + InvitationToken::validity(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(email_client, base_url, invitation_token))]
+async fn send_invitation_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    role: Role,
+    base_url: &Url,
+    invitation_token: &InvitationToken,
+) -> Result<(), anyhow::Error> {
+    let invitation_link = base_url
+        .join(&format!("invitations/{}", invitation_token.as_ref()))
+        .unwrap();
+    let plain_body = format!(
+        "You have been invited to help run our newsletter as {}.\n\
+        Visit {} to choose a username and password. The link is valid for {} hours.",
+        role,
+        invitation_link,
+        InvitationToken::validity().num_hours()
+    );
+    let html_body = format!(
+        "You have been invited to help run our newsletter as {}.<br />\
+        Click <a href=\"{}\">here</a> to choose a username and password. \
+        The link is valid for {} hours.",
+        role,
+        invitation_link,
+        InvitationToken::validity().num_hours()
+    );
+    email_client
+        .send_email(
+            recipient,
+            "You have been invited",
+            &html_body,
+            &plain_body,
+            &[],
+        )
+        .await
+}
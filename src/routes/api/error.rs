@@ -0,0 +1,107 @@
+use crate::util::error_chain_fmt;
+use actix_web::body::BoxBody;
+use actix_web::http::header::{HeaderValue, WWW_AUTHENTICATE};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+use std::fmt;
+use std::fmt::Formatter;
+
+// Errors of the JSON API are JSON as well, so that clients can branch on `code` and
+// point at the offending `field` instead of parsing messages.
+#[derive(thiserror::Error)]
+pub enum ApiError {
+    #[error("{message}")]
+    Validation {
+        field: Option<&'static str>,
+        message: String,
+    },
+    #[error("Missing or invalid API token.")]
+    Unauthorized,
+    #[error("{0}")]
+    Forbidden(String),
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl ApiError {
+    pub fn validation(field: &'static str, message: impl ToString) -> Self {
+        Self::Validation {
+            field: Some(field),
+            message: message.to_string(),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Validation { .. } => "validation",
+            Self::Unauthorized => "unauthorized",
+            Self::Forbidden(_) => "forbidden",
+            Self::Unexpected(_) => "internal_error",
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+struct ErrorBody<'a> {
+    error: ErrorDetails<'a>,
+}
+
+#[derive(serde::Serialize)]
+struct ErrorDetails<'a> {
+    code: &'a str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    field: Option<&'a str>,
+}
+
+impl fmt::Debug for ApiError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for ApiError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Validation { .. } => StatusCode::BAD_REQUEST,
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Forbidden(_) => StatusCode::FORBIDDEN,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        let message = match self {
+            // The cause chain is logged, not handed out.
+            Self::Unexpected(_) => "An unexpected error occurred.".to_string(),
+            _ => self.to_string(),
+        };
+        let field = match self {
+            Self::Validation { field, .. } => *field,
+            _ => None,
+        };
+        let mut response = json_error(self.status_code(), self.code(), message, field);
+        if let Self::Unauthorized = self {
+            response
+                .headers_mut()
+                .insert(WWW_AUTHENTICATE, HeaderValue::from_static("Bearer"));
+        }
+        response
+    }
+}
+
+// Shared with the endpoints that answer JSON clients outside of the API scope.
+pub(crate) fn json_error(
+    status_code: StatusCode,
+    code: &str,
+    message: String,
+    field: Option<&str>,
+) -> HttpResponse {
+    HttpResponse::build(status_code).json(ErrorBody {
+        error: ErrorDetails {
+            code,
+            message,
+            field,
+        },
+    })
+}
@@ -0,0 +1,6 @@
+mod error;
+mod newsletters;
+
+pub(crate) use error::json_error;
+pub use error::ApiError;
+pub use newsletters::publish_newsletter_via_api;
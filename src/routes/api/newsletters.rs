@@ -0,0 +1,191 @@
+use super::ApiError;
+use crate::authentication::{Permission, Role, UserId};
+use crate::idempotency::{save_response, try_processing, IdempotencyKey, NextAction};
+use crate::issue_delivery_worker::enqueue_delivery_tasks;
+use crate::mailing_lists::{assign_issue_lists, select_lists, ListSelectionError};
+use crate::routes::{insert_newsletter_issue, validate_issue_content};
+use crate::segments::{assign_issue_segment, select_segment, SegmentSelectionError};
+use crate::util::parse_schedule;
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+const IDEMPOTENCY_KEY_HEADER: &str = "Idempotency-Key";
+
+#[derive(serde::Deserialize)]
+pub struct NewsletterRequest {
+    title: String,
+    text_content: String,
+    html_content: String,
+    #[serde(default)]
+    scheduled_for: Option<String>,
+    // Slugs of the lists the issue goes to, the default list when empty.
+    #[serde(default)]
+    lists: Vec<String>,
+    // Name of a segment narrowing the audience.
+    #[serde(default)]
+    segment: Option<String>,
+}
+
+struct ValidNewsletter {
+    title: String,
+    text_content: String,
+    html_content: String,
+    scheduled_for: Option<DateTime<Utc>>,
+    lists: Vec<String>,
+    segment: Option<String>,
+}
+
+impl TryFrom<NewsletterRequest> for ValidNewsletter {
+    type Error = ApiError;
+    fn try_from(request: NewsletterRequest) -> Result<Self, Self::Error> {
+        for (field, value) in [
+            ("title", &request.title),
+            ("text_content", &request.text_content),
+            ("html_content", &request.html_content),
+        ] {
+            if value.trim().is_empty() {
+                return Err(ApiError::validation(field, "The value cannot be empty."));
+            }
+        }
+        validate_issue_content(&request.text_content, &request.html_content).map_err(|e| {
+            ApiError::Validation {
+                field: None,
+                message: e,
+            }
+        })?;
+        let scheduled_for = request
+            .scheduled_for
+            .filter(|s| !s.is_empty())
+            .map(|s| parse_schedule(&s))
+            .transpose()
+            .map_err(|e| ApiError::validation("scheduled_for", e))?;
+        Ok(Self {
+            title: request.title,
+            text_content: request.text_content,
+            html_content: request.html_content,
+            scheduled_for,
+            lists: request.lists,
+            segment: request.segment,
+        })
+    }
+}
+
+#[derive(serde::Serialize)]
+struct NewsletterResponse {
+    newsletter_issue_id: Uuid,
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled_for: Option<String>,
+}
+
+// The header is optional, but clients that retry on timeouts should always send it.
+fn idempotency_key(request: &HttpRequest) -> Result<Option<IdempotencyKey>, ApiError> {
+    let value = match request.headers().get(IDEMPOTENCY_KEY_HEADER) {
+        Some(value) => value,
+        None => return Ok(None),
+    };
+    let value = value
+        .to_str()
+        .map_err(|_| ApiError::validation("Idempotency-Key", "The header must be ASCII."))?;
+    IdempotencyKey::try_from(value.to_string())
+        .map(Some)
+        .map_err(|e| ApiError::validation("Idempotency-Key", e))
+}
+
+#[post("/newsletters")]
+#[tracing::instrument(
+    name = "Publish a newsletter issue through the API",
+    skip(request, body, pool, role),
+    fields(user_id=%*user_id)
+)]
+pub async fn publish_newsletter_via_api(
+    request: HttpRequest,
+    body: web::Json<NewsletterRequest>,
+    pool: web::Data<PgPool>,
+    user_id: web::ReqData<UserId>,
+    role: web::ReqData<Role>,
+) -> Result<HttpResponse, ApiError> {
+    if !role.can(Permission::Publish) {
+        return Err(ApiError::Forbidden(format!(
+            "The {} role is not allowed to send newsletter issues.",
+            role.as_str()
+        )));
+    }
+    let newsletter = ValidNewsletter::try_from(body.into_inner())?;
+    let lists = select_lists(&pool, &newsletter.lists)
+        .await
+        .map_err(|e| match e {
+            ListSelectionError::UnknownList(_) => ApiError::validation("lists", e),
+            ListSelectionError::Unexpected(e) => anyhow::Error::new(e)
+                .context("Failed to get the lists from the database.")
+                .into(),
+        })?;
+    let segment_id = select_segment(&pool, newsletter.segment.as_deref())
+        .await
+        .map_err(|e| match e {
+            SegmentSelectionError::UnknownSegment(_) => ApiError::validation("segment", e),
+            SegmentSelectionError::Unexpected(e) => anyhow::Error::new(e)
+                .context("Failed to get the segment from the database.")
+                .into(),
+        })?;
+    let idempotency_key = idempotency_key(&request)?;
+    let user_id = user_id.into_inner();
+
+    let mut transaction = match &idempotency_key {
+        Some(idempotency_key) => match try_processing(&pool, idempotency_key, *user_id).await? {
+            NextAction::StartProcessing(t) => t,
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+        },
+        None => pool
+            .begin()
+            .await
+            .context("Failed to acquire a Postgres connection from the pool.")?,
+    };
+    let issue_id = insert_newsletter_issue(
+        &mut transaction,
+        &newsletter.title,
+        &newsletter.text_content,
+        &newsletter.html_content,
+        newsletter.scheduled_for,
+    )
+    .await
+    .context("Failed to store newsletter issue details")?;
+    let list_ids: Vec<Uuid> = lists.iter().map(|l| l.list_id).collect();
+    assign_issue_lists(&mut transaction, issue_id, &list_ids)
+        .await
+        .context("Failed to store the lists of the newsletter issue")?;
+    assign_issue_segment(&mut transaction, issue_id, segment_id)
+        .await
+        .context("Failed to store the segment of the newsletter issue")?;
+    // Scheduled issues are fanned out by the delivery worker once their time has come.
+    if newsletter.scheduled_for.is_none() {
+        enqueue_delivery_tasks(&mut transaction, issue_id)
+            .await
+            .context("Failed to enqueue delivery tasks")?;
+    }
+
+    let response = HttpResponse::Accepted().json(NewsletterResponse {
+        newsletter_issue_id: issue_id,
+        status: if newsletter.scheduled_for.is_some() {
+            "scheduled"
+        } else {
+            "published"
+        },
+        scheduled_for: newsletter.scheduled_for.map(|t| t.to_rfc3339()),
+    });
+    match idempotency_key {
+        Some(idempotency_key) => {
+            Ok(save_response(transaction, &idempotency_key, *user_id, response).await?)
+        }
+        None => {
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit the newsletter issue.")?;
+            Ok(response)
+        }
+    }
+}
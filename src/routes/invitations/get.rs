@@ -0,0 +1,99 @@
+use crate::authentication::Role;
+use crate::domain::invitation_token::InvitationToken;
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::{PgExecutor, PgPool};
+
+pub(super) struct Invitation {
+    pub(super) email: String,
+    pub(super) role: Role,
+}
+
+#[derive(Template)]
+#[template(path = "accept_invitation.html")]
+struct InvitationTemplate<'a> {
+    invitation_token: &'a str,
+    email: &'a str,
+    role: Role,
+    messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "invitation_invalid.html")]
+struct InvalidInvitationTemplate;
+
+#[get("/invitations/{invitation_token}")]
+pub async fn invitation_form(
+    invitation_token: web::Path<String>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_token = match InvitationToken::parse(invitation_token.into_inner()) {
+        Ok(t) => t,
+        Err(_) => return invalid_invitation(),
+    };
+    let invitation = match get_pending_invitation(pool.get_ref(), &invitation_token)
+        .await
+        .context("Failed to retrieve the invitation.")
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => return invalid_invitation(),
+    };
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+
+    let page = InvitationTemplate {
+        invitation_token: invitation_token.as_ref(),
+        email: &invitation.email,
+        role: invitation.role,
+        messages,
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+pub(super) fn invalid_invitation() -> Result<HttpResponse, actix_web::Error> {
+    let page_html = InvalidInvitationTemplate.render().map_err(e500)?;
+    Ok(HttpResponse::Gone()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+// Only invitations that have neither expired nor been accepted yet can be used.
+#[tracing::instrument(skip_all)]
+pub(super) async fn get_pending_invitation(
+    executor: impl PgExecutor<'_>,
+    invitation_token: &InvitationToken,
+) -> Result<Option<Invitation>, anyhow::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT email, role
+        FROM user_invitations
+        WHERE
+            invitation_token = $1 AND
+            accepted_at IS NULL AND
+            expires_at > now()
+        FOR UPDATE
+        "#,
+        invitation_token.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await?;
+    row.map(|r| {
+        Ok(Invitation {
+            email: r.email,
+            role: Role::parse(&r.role).map_err(anyhow::Error::msg)?,
+        })
+    })
+    .transpose()
+}
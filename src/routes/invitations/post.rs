@@ -0,0 +1,105 @@
+use super::get::{get_pending_invitation, invalid_invitation};
+use crate::authentication::{
+    create_user, start_session, validate_new_password, Credentials, PasswordHashing,
+};
+use crate::domain::invitation_token::InvitationToken;
+use crate::session_state::TypedSession;
+use crate::util::{e500, see_other};
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use secrecy::Secret;
+use sqlx::PgPool;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    username: String,
+    password: Secret<String>,
+    confirm_password: Secret<String>,
+}
+
+#[tracing::instrument(
+    skip(request, invitation_token, form, pool, session, hashing),
+    fields(username=%form.username, user_id=tracing::field::Empty)
+)]
+#[post("/invitations/{invitation_token}")]
+pub async fn accept_invitation(
+    request: HttpRequest,
+    invitation_token: web::Path<String>,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let invitation_token = match InvitationToken::parse(invitation_token.into_inner()) {
+        Ok(t) => t,
+        Err(_) => return invalid_invitation(),
+    };
+    let invitation_page = format!("/invitations/{}", invitation_token.as_ref());
+    let username = form.0.username.trim().to_string();
+    if username.is_empty() {
+        FlashMessage::error("Please choose a username.").send();
+        return Ok(see_other(&invitation_page));
+    }
+    if let Err(e) = validate_new_password(&form.0.password, &form.0.confirm_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&invitation_page));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let invitation = match get_pending_invitation(&mut transaction, &invitation_token)
+        .await
+        .context("Failed to retrieve the invitation.")
+        .map_err(e500)?
+    {
+        Some(invitation) => invitation,
+        None => return invalid_invitation(),
+    };
+    let username_taken = sqlx::query!(r#"SELECT user_id FROM users WHERE username = $1"#, username)
+        .fetch_optional(&mut transaction)
+        .await
+        .context("Failed to look up the username.")
+        .map_err(e500)?
+        .is_some();
+    if username_taken {
+        FlashMessage::error("That username is already taken.").send();
+        return Ok(see_other(&invitation_page));
+    }
+
+    let credentials = Credentials {
+        username,
+        password: form.0.password,
+    };
+    let user_id = create_user(
+        &mut transaction,
+        credentials,
+        &invitation.email,
+        invitation.role,
+        &hashing,
+    )
+    .await
+    .map_err(e500)?;
+    sqlx::query!(
+        r#"UPDATE user_invitations SET accepted_at = now() WHERE invitation_token = $1"#,
+        invitation_token.as_ref(),
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the invitation as accepted.")
+    .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new user.")
+        .map_err(e500)?;
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+
+    start_session(&session, user_id, &request, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
@@ -1,4 +1,5 @@
-use crate::util::e500;
+use crate::session_state::TypedSession;
+use crate::util::{e500, see_other};
 use actix_web::http::header::ContentType;
 use actix_web::{get, HttpResponse};
 use actix_web_flash_messages::IncomingFlashMessages;
@@ -25,3 +26,29 @@ pub async fn login_form(
         .content_type(ContentType::html())
         .body(login_form_html))
 }
+
+#[derive(Template)]
+#[template(path = "login_two_factor.html")]
+struct TwoFactorTemplate<'a> {
+    messages: Vec<&'a str>,
+}
+
+#[get("/login/two_factor")]
+pub async fn two_factor_form(
+    flash_messages: IncomingFlashMessages,
+    session: TypedSession,
+) -> Result<HttpResponse, actix_web::Error> {
+    if session.get_pending_login().map_err(e500)?.is_none() {
+        return Ok(see_other("/login"));
+    }
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let two_factor_form = TwoFactorTemplate { messages };
+    let two_factor_form_html = two_factor_form.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(two_factor_form_html))
+}
@@ -1,12 +1,20 @@
-use crate::authentication::{validate_credentials, AuthError, Credentials};
-use crate::session_state::TypedSession;
-use crate::util::{error_chain_fmt, see_other};
+use crate::authentication::{
+    get_totp_secret, start_session, validate_credentials, verify_second_factor, AuthError,
+    Credentials, LoginThrottle, PasswordHashing,
+};
+use crate::session_state::{PendingLogin, TypedSession};
+use crate::util::{client_ip, e500, error_chain_fmt, retry_after_seconds, see_other};
 use actix_web::error::InternalError;
-use actix_web::{post, web, HttpResponse};
+use actix_web::{post, web, HttpRequest, HttpResponse};
 use actix_web_flash_messages::FlashMessage;
-use secrecy::Secret;
+use chrono::Utc;
+use secrecy::{ExposeSecret, Secret};
 use sqlx::PgPool;
 
+// How long the second login step may take, and how many wrong codes it tolerates.
+const PENDING_LOGIN_TTL_SECONDS: i64 = 300;
+const MAX_FAILED_SECOND_FACTOR_ATTEMPTS: u8 = 5;
+
 #[derive(serde::Deserialize)]
 pub struct FormData {
     username: String,
@@ -17,6 +25,11 @@ pub struct FormData {
 pub enum LoginError {
     #[error("Authentication failed")]
     AuthError(#[source] anyhow::Error),
+    #[error(
+        "Too many failed login attempts. Please try again in {} seconds.",
+        retry_after_seconds(.0)
+    )]
+    TooManyAttempts(std::time::Duration),
     #[error("Something went wrong")]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -28,33 +41,75 @@ impl std::fmt::Debug for LoginError {
 }
 
 #[tracing::instrument(
-    skip(form, pool, session),
+    skip(request, form, pool, session, throttle, hashing),
     fields(username=tracing::field::Empty, user_id=tracing::field::Empty)
 )]
 #[post("/login")]
 pub async fn login(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
     session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+    hashing: web::Data<PasswordHashing>,
 ) -> Result<HttpResponse, InternalError<LoginError>> {
     let credentials = Credentials {
         username: form.0.username,
         password: form.0.password,
     };
     tracing::Span::current().record("username", &tracing::field::display(&credentials.username));
+    let username = credentials.username.clone();
+    let ip = client_ip(&request);
+    let ip = ip.as_deref();
+
+    // Blocked attempts are turned away before any password hash is computed.
+    if let Some(retry_after) = throttle
+        .retry_after(&username, ip)
+        .await
+        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?
+    {
+        return Err(login_redirect(LoginError::TooManyAttempts(retry_after)));
+    }
 
-    match validate_credentials(credentials, &pool).await {
+    match validate_credentials(credentials, &pool, &hashing).await {
         Ok(user_id) => {
             tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
-            session.renew();
-            session
-                .insert_user_id(user_id)
-                .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+            let totp_secret = get_totp_secret(pool.get_ref(), user_id)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            // The failure counters are only reset once the second factor has been checked
+            // too, otherwise starting over would buy a fresh batch of guesses for the code.
+            if totp_secret.is_some() {
+                session.renew();
+                let pending = PendingLogin {
+                    user_id,
+                    username,
+                    started_at: Utc::now().timestamp(),
+                    failed_attempts: 0,
+                };
+                session
+                    .insert_pending_login(&pending)
+                    .map_err(|e| login_redirect(LoginError::UnexpectedError(e.into())))?;
+                return Ok(see_other("/login/two_factor"));
+            }
+            throttle
+                .record_success(&username)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+            start_session(&session, user_id, &request, &pool)
+                .await
+                .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
             Ok(see_other("/admin/dashboard"))
         }
         Err(e) => {
             let e = match e {
-                AuthError::InvalidCredentials(_) => LoginError::AuthError(e.into()),
+                AuthError::InvalidCredentials(_) => {
+                    throttle
+                        .record_failure(&username, ip)
+                        .await
+                        .map_err(|e| login_redirect(LoginError::UnexpectedError(e)))?;
+                    LoginError::AuthError(e.into())
+                }
                 AuthError::UnexpectedError(_) => LoginError::UnexpectedError(e.into()),
             };
             Err(login_redirect(e))
@@ -67,3 +122,74 @@ fn login_redirect(e: LoginError) -> InternalError<LoginError> {
     let response = see_other("/login");
     InternalError::from_response(e, response)
 }
+
+#[derive(serde::Deserialize)]
+pub struct TwoFactorFormData {
+    code: Secret<String>,
+}
+
+#[tracing::instrument(
+    skip(request, form, pool, session, throttle),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/login/two_factor")]
+pub async fn verify_two_factor(
+    request: HttpRequest,
+    form: web::Form<TwoFactorFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    throttle: web::Data<LoginThrottle>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let mut pending = match session.get_pending_login().map_err(e500)? {
+        Some(pending) => pending,
+        None => return Ok(see_other("/login")),
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&pending.user_id));
+    let now = Utc::now();
+    if now.timestamp() - pending.started_at > PENDING_LOGIN_TTL_SECONDS {
+        session.remove_pending_login();
+        FlashMessage::error("Your login has expired. Please start again.").send();
+        return Ok(see_other("/login"));
+    }
+
+    // Two-factor authentication may have been turned off since the password was checked.
+    if let Some(secret) = get_totp_secret(pool.get_ref(), pending.user_id)
+        .await
+        .map_err(e500)?
+    {
+        let verified = verify_second_factor(
+            &pool,
+            pending.user_id,
+            &secret,
+            form.0.code.expose_secret(),
+            now.timestamp() as u64,
+        )
+        .await
+        .map_err(e500)?;
+        if !verified {
+            throttle
+                .record_failure(&pending.username, client_ip(&request).as_deref())
+                .await
+                .map_err(e500)?;
+            pending.failed_attempts += 1;
+            if pending.failed_attempts >= MAX_FAILED_SECOND_FACTOR_ATTEMPTS {
+                session.remove_pending_login();
+                FlashMessage::error("Too many invalid codes. Please login again.").send();
+                return Ok(see_other("/login"));
+            }
+            session.insert_pending_login(&pending).map_err(e500)?;
+            FlashMessage::error("The authentication code is invalid.").send();
+            return Ok(see_other("/login/two_factor"));
+        }
+    }
+
+    session.remove_pending_login();
+    throttle
+        .record_success(&pending.username)
+        .await
+        .map_err(e500)?;
+    start_session(&session, pending.user_id, &request, &pool)
+        .await
+        .map_err(e500)?;
+    Ok(see_other("/admin/dashboard"))
+}
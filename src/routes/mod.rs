@@ -1,13 +1,30 @@
 mod admin;
+mod api;
 mod health_check;
 mod home;
+mod invitations;
 mod login;
+mod password_reset;
 mod subscription_confirm;
+mod subscription_preferences;
+mod subscription_resend;
+mod subscription_unsubscribe;
 mod subscriptions;
+mod webhooks;
 
 pub use admin::*;
+pub use api::*;
 pub use health_check::*;
 pub use home::*;
+pub use invitations::*;
 pub use login::*;
-pub use subscription_confirm::confirm;
+pub use password_reset::*;
+pub use subscription_confirm::{confirm, resend_confirmation};
+pub use subscription_preferences::{
+    pause_deliveries, preferences_page, update_subscriber_lists, update_subscriber_name,
+};
+pub use subscription_resend::{request_confirmation_resend, resend_confirmation_form};
+pub use subscription_unsubscribe::{unsubscribe, unsubscribe_form};
 pub use subscriptions::subscription;
+pub(crate) use subscriptions::{confirmation_token, send_confirmation_email};
+pub use webhooks::postmark_webhook;
@@ -0,0 +1,106 @@
+use crate::domain::password_reset_token::PasswordResetToken;
+use crate::util::e500;
+use actix_web::http::header::ContentType;
+use actix_web::{get, web, HttpResponse};
+use actix_web_flash_messages::IncomingFlashMessages;
+use anyhow::Context;
+use askama::Template;
+use sqlx::{PgExecutor, PgPool};
+use uuid::Uuid;
+
+#[derive(Template)]
+#[template(path = "password_reset_request.html")]
+struct PasswordResetRequestTemplate<'a> {
+    messages: Vec<&'a str>,
+}
+
+#[get("/password_reset")]
+pub async fn password_reset_request_form(
+    flash_messages: IncomingFlashMessages,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let page = PasswordResetRequestTemplate { messages };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+#[derive(Template)]
+#[template(path = "password_reset.html")]
+struct PasswordResetTemplate<'a> {
+    password_reset_token: &'a str,
+    messages: Vec<&'a str>,
+}
+
+#[derive(Template)]
+#[template(path = "password_reset_invalid.html")]
+struct InvalidResetLinkTemplate;
+
+#[get("/password_reset/{password_reset_token}")]
+pub async fn password_reset_form(
+    password_reset_token: web::Path<String>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let password_reset_token = match PasswordResetToken::parse(password_reset_token.into_inner()) {
+        Ok(t) => t,
+        Err(_) => return invalid_reset_link(),
+    };
+    if get_pending_reset(pool.get_ref(), &password_reset_token)
+        .await
+        .context("Failed to retrieve the password reset token.")
+        .map_err(e500)?
+        .is_none()
+    {
+        return invalid_reset_link();
+    }
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+
+    let page = PasswordResetTemplate {
+        password_reset_token: password_reset_token.as_ref(),
+        messages,
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+pub(super) fn invalid_reset_link() -> Result<HttpResponse, actix_web::Error> {
+    let page_html = InvalidResetLinkTemplate.render().map_err(e500)?;
+    Ok(HttpResponse::Gone()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+// Returns the user a reset token was issued for, as long as it has neither expired nor been used.
+#[tracing::instrument(skip_all)]
+pub(super) async fn get_pending_reset(
+    executor: impl PgExecutor<'_>,
+    password_reset_token: &PasswordResetToken,
+) -> Result<Option<Uuid>, sqlx::Error> {
+    let row = sqlx::query!(
+        r#"
+        SELECT user_id
+        FROM password_reset_tokens
+        WHERE
+            password_reset_token = $1 AND
+            used_at IS NULL AND
+            expires_at > now()
+        FOR UPDATE
+        "#,
+        password_reset_token.as_ref(),
+    )
+    .fetch_optional(executor)
+    .await?;
+    Ok(row.map(|r| r.user_id))
+}
@@ -0,0 +1,6 @@
+mod get;
+mod post;
+
+pub use get::{password_reset_form, password_reset_request_form};
+pub(crate) use post::send_reset_email;
+pub use post::{request_password_reset, reset_password};
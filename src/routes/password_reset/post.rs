@@ -0,0 +1,229 @@
+use super::get::{get_pending_reset, invalid_reset_link};
+use crate::authentication::{
+    revoke_all_sessions, update_password_hash, validate_new_password, PasswordHashing,
+};
+use crate::domain::password_reset_token::PasswordResetToken;
+use crate::domain::SubscriberEmail;
+use crate::email_client::EmailClient;
+use crate::password_reset_email_worker::enqueue_password_reset_email;
+use crate::resend_cooldown::ResendCooldown;
+use crate::session_state::TypedSession;
+use crate::util::{client_ip, e500, retry_after_seconds, see_other};
+use actix_web::{post, web, HttpRequest, HttpResponse};
+use actix_web_flash_messages::FlashMessage;
+use anyhow::Context;
+use chrono::Utc;
+use reqwest::Url;
+use secrecy::Secret;
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct RequestFormData {
+    email: String,
+}
+
+#[tracing::instrument(skip(request, form, pool, cooldown))]
+#[post("/password_reset")]
+pub async fn request_password_reset(
+    request: HttpRequest,
+    form: web::Form<RequestFormData>,
+    pool: web::Data<PgPool>,
+    cooldown: web::Data<ResendCooldown>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = match SubscriberEmail::parse(form.0.email) {
+        Ok(email) => email,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other("/password_reset"));
+        }
+    };
+    if let Some(ip) = client_ip(&request) {
+        if let Some(retry_after) = cooldown.start_reset_for_ip(&ip).await.map_err(e500)? {
+            FlashMessage::error(format!(
+                "Too many password reset requests. Please try again in {} seconds.",
+                retry_after_seconds(&retry_after)
+            ))
+            .send();
+            return Ok(see_other("/password_reset"));
+        }
+    }
+
+    // Whether the address belongs to an account is not revealed to the requester: the
+    // email is sent by the password reset worker, and an address that is cooling down gets
+    // the same answer as any other.
+    if cooldown
+        .start_reset_for_email(email.as_ref())
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        queue_reset_email(&pool, &email).await.map_err(e500)?;
+    }
+
+    FlashMessage::info(
+        "If an account uses that email address, we have sent it a link to reset the password.",
+    )
+    .send();
+    Ok(see_other("/login"))
+}
+
+#[tracing::instrument(skip(pool))]
+async fn queue_reset_email(pool: &PgPool, email: &SubscriberEmail) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")?;
+    let user_id = sqlx::query!(
+        r#"SELECT user_id FROM users WHERE lower(email) = lower($1)"#,
+        email.as_ref()
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to look up the user.")?
+    .map(|r| r.user_id);
+    if let Some(user_id) = user_id {
+        let password_reset_token = PasswordResetToken::new();
+        store_reset_token(&mut transaction, user_id, &password_reset_token)
+            .await
+            .context("Failed to store the password reset token.")?;
+        enqueue_password_reset_email(&mut transaction, &password_reset_token)
+            .await
+            .context("Failed to queue the password reset email.")?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the password reset request.")
+}
+
+#[tracing::instrument(skip(transaction, password_reset_token))]
+async fn store_reset_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    user_id: Uuid,
+    password_reset_token: &PasswordResetToken,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO password_reset_tokens (password_reset_token, user_id, expires_at)
+        VALUES ($1, $2, $3)
+        "#,
+        password_reset_token.as_ref(),
+        user_id,
+        Utc::now() + PasswordResetToken::validity(),
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(skip(email_client, base_url, password_reset_token))]
+pub(crate) async fn send_reset_email(
+    email_client: &EmailClient,
+    recipient: &SubscriberEmail,
+    base_url: &Url,
+    password_reset_token: &PasswordResetToken,
+) -> Result<(), anyhow::Error> {
+    let reset_link = base_url
+        .join(&format!("password_reset/{}", password_reset_token.as_ref()))
+        .unwrap();
+    let plain_body = format!(
+        "Somebody asked to reset the password of your account.\n\
+        Visit {} to choose a new password. The link is valid for {} minutes.\n\
+        If it was not you, you can ignore this email.",
+        reset_link,
+        PasswordResetToken::validity().num_minutes()
+    );
+    let html_body = format!(
+        "Somebody asked to reset the password of your account.<br />\
+        Click <a href=\"{}\">here</a> to choose a new password. \
+        The link is valid for {} minutes.<br />\
+        If it was not you, you can ignore this email.",
+        reset_link,
+        PasswordResetToken::validity().num_minutes()
+    );
+    email_client
+        .send_email(
+            recipient,
+            "Reset your password",
+            &html_body,
+            &plain_body,
+            &[],
+        )
+        .await
+}
+
+#[derive(serde::Deserialize)]
+pub struct ResetFormData {
+    new_password: Secret<String>,
+    confirm_new_password: Secret<String>,
+}
+
+#[tracing::instrument(
+    skip(password_reset_token, form, pool, session, hashing),
+    fields(user_id=tracing::field::Empty)
+)]
+#[post("/password_reset/{password_reset_token}")]
+pub async fn reset_password(
+    password_reset_token: web::Path<String>,
+    form: web::Form<ResetFormData>,
+    pool: web::Data<PgPool>,
+    session: TypedSession,
+    hashing: web::Data<PasswordHashing>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let password_reset_token = match PasswordResetToken::parse(password_reset_token.into_inner()) {
+        Ok(t) => t,
+        Err(_) => return invalid_reset_link(),
+    };
+    if let Err(e) = validate_new_password(&form.0.new_password, &form.0.confirm_new_password) {
+        FlashMessage::error(e).send();
+        return Ok(see_other(&format!(
+            "/password_reset/{}",
+            password_reset_token.as_ref()
+        )));
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool.")
+        .map_err(e500)?;
+    let user_id = match get_pending_reset(&mut transaction, &password_reset_token)
+        .await
+        .context("Failed to retrieve the password reset token.")
+        .map_err(e500)?
+    {
+        Some(user_id) => user_id,
+        None => return invalid_reset_link(),
+    };
+    tracing::Span::current().record("user_id", &tracing::field::display(&user_id));
+    update_password_hash(&mut transaction, user_id, form.0.new_password, &hashing)
+        .await
+        .map_err(e500)?;
+    // Any other outstanding link for the account stops working as well.
+    sqlx::query!(
+        r#"
+        UPDATE password_reset_tokens
+        SET used_at = now()
+        WHERE user_id = $1 AND used_at IS NULL
+        "#,
+        user_id,
+    )
+    .execute(&mut transaction)
+    .await
+    .context("Failed to mark the password reset token as used.")
+    .map_err(e500)?;
+    revoke_all_sessions(&mut transaction, user_id)
+        .await
+        .context("Failed to revoke the sessions of the user.")
+        .map_err(e500)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit the new password.")
+        .map_err(e500)?;
+
+    session.purge();
+    FlashMessage::info("Your password has been reset. Please login to continue.").send();
+    Ok(see_other("/login"))
+}
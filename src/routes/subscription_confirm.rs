@@ -1,9 +1,19 @@
 use crate::domain::subscription_token::SubscriptionToken;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use crate::email_client::EmailClient;
+use crate::mailing_lists::confirm_pending_membership;
+use crate::resend_cooldown::ResendCooldown;
+use crate::routes::subscriptions::{confirmation_token, send_confirmation_email};
+use crate::startup::ApplicationBaseUrl;
 use crate::util::error_chain_fmt;
+use actix_web::body::BoxBody;
+use actix_web::http::header::ContentType;
 use actix_web::http::StatusCode;
-use actix_web::{get, web, HttpResponse, ResponseError};
+use actix_web::{get, post, web, HttpResponse, ResponseError};
 use anyhow::Context;
-use sqlx::PgPool;
+use askama::Template;
+use chrono::{DateTime, Utc};
+use sqlx::{PgPool, Postgres, Transaction};
 use std::fmt;
 use std::fmt::Formatter;
 use uuid::Uuid;
@@ -19,6 +29,10 @@ pub enum SubscriptionConfirmError {
     Validation(String),
     #[error("Failed to confirm subscription because of unauthorized token.")]
     UnauthorizedToken,
+    #[error("The confirmation link has expired.")]
+    ExpiredToken(SubscriptionToken),
+    #[error("Failed to subscribe as the subscriber is already confirmed.")]
+    AlreadyConfirmed,
     #[error(transparent)]
     Unexpected(#[from] anyhow::Error),
 }
@@ -29,12 +43,38 @@ impl fmt::Debug for SubscriptionConfirmError {
     }
 }
 
+#[derive(Template)]
+#[template(path = "confirmation_link_expired.html")]
+struct ExpiredLinkTemplate<'a> {
+    subscription_token: &'a str,
+}
+
 impl ResponseError for SubscriptionConfirmError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Validation(_) | Self::AlreadyConfirmed => StatusCode::BAD_REQUEST,
             Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
             Self::UnauthorizedToken => StatusCode::UNAUTHORIZED,
+            Self::ExpiredToken(_) => StatusCode::GONE,
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse<BoxBody> {
+        match self {
+            Self::ExpiredToken(token) => {
+                let page = ExpiredLinkTemplate {
+                    subscription_token: token.as_ref(),
+                };
+                match page.render() {
+                    Ok(page_html) => HttpResponse::build(self.status_code())
+                        .content_type(ContentType::html())
+                        .body(page_html),
+                    Err(_) => HttpResponse::build(self.status_code())
+                        .content_type(ContentType::plaintext())
+                        .body(self.to_string()),
+                }
+            }
+            _ => HttpResponse::new(self.status_code()),
         }
     }
 }
@@ -48,42 +88,194 @@ pub async fn confirm(
     let subscription_token = SubscriptionToken::parse(&parameters.subscription_token)
         .map_err(SubscriptionConfirmError::Validation)?;
 
-    let id = get_subscriber_id_from_token(&pool, &subscription_token)
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")?;
+    let token = consume_token(&mut transaction, &subscription_token)
         .await
-        .context("Failed to get subscriber id from the database.")?;
-    match id {
-        // Non-existing token!
+        .context("Failed to consume subscription token in the database.")?;
+    match token {
+        // Non-existing or already used token!
         None => Err(SubscriptionConfirmError::UnauthorizedToken),
-        Some(subscriber_id) => {
-            confirm_subscriber(&pool, subscriber_id)
+        Some(token) if token.expires_at < Utc::now() => {
+            // Dropping the transaction rolls back the consumption,
+            // the expired token can still be used to request a new link.
+            Err(SubscriptionConfirmError::ExpiredToken(subscription_token))
+        }
+        Some(token) => {
+            confirm_subscriber(&mut transaction, token.subscriber_id, token.list_id)
                 .await
                 .context("Failed to update subscriber as confirmed in the database.")?;
+            transaction
+                .commit()
+                .await
+                .context("Failed to commit SQL transaction to confirm a subscriber.")?;
             Ok(HttpResponse::Ok().finish())
         }
     }
 }
 
-#[tracing::instrument(name = "Mark subscriber as confirmed", skip(subscriber_id, pool))]
-async fn confirm_subscriber(pool: &PgPool, subscriber_id: Uuid) -> Result<(), sqlx::Error> {
+#[derive(serde::Deserialize)]
+pub struct ResendFormData {
+    subscription_token: String,
+}
+
+#[post("/subscriptions/confirm/resend")]
+#[tracing::instrument(
+    name = "Resend a confirmation link for an expired token",
+    skip(form, pool, email_client, base_url, cooldown)
+)]
+pub async fn resend_confirmation(
+    form: web::Form<ResendFormData>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<EmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    cooldown: web::Data<ResendCooldown>,
+) -> Result<HttpResponse, SubscriptionConfirmError> {
+    let expired_token = SubscriptionToken::parse(&form.0.subscription_token)
+        .map_err(SubscriptionConfirmError::Validation)?;
+
+    let subscriber = get_subscriber_from_token(&pool, &expired_token)
+        .await
+        .context("Failed to get subscriber from the database.")?
+        .ok_or(SubscriptionConfirmError::UnauthorizedToken)?;
+    if !subscriber.pending {
+        return Err(SubscriptionConfirmError::AlreadyConfirmed);
+    }
+    let new_subscriber = NewSubscriber::new(
+        SubscriberEmail::parse(&subscriber.email).map_err(SubscriptionConfirmError::Validation)?,
+        SubscriberName::parse(&subscriber.name).map_err(SubscriptionConfirmError::Validation)?,
+    );
+    // Shares the cooldown of the resend form: a link sent a moment ago is still on its way.
+    if cooldown
+        .start_for_email(new_subscriber.email.as_ref())
+        .await?
+        .is_some()
+    {
+        return Ok(HttpResponse::Ok().finish());
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")?;
+    // A newer link may have been sent in the meantime.
+    let subscription_token =
+        confirmation_token(&mut transaction, subscriber.id, subscriber.list_id)
+            .await
+            .context("Failed to get a subscription token from the database")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a subscription token.")?;
+
+    send_confirmation_email(
+        &email_client,
+        new_subscriber,
+        &base_url.0,
+        &subscription_token,
+    )
+    .await
+    .context("Failed to send a confirmation email.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+// Only the list the link was sent for is confirmed. Addresses that bounced, complained or
+// unsubscribed since the link was sent are left as they are.
+#[tracing::instrument(
+    name = "Mark subscriber as confirmed",
+    skip(subscriber_id, transaction)
+)]
+async fn confirm_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"UPDATE subscriptions SET status = 'confirmed' WHERE id = $1"#,
+        r#"
+        UPDATE subscriptions
+        SET status = 'confirmed'
+        WHERE id = $1 AND status = 'pending_confirmation'
+        "#,
         subscriber_id,
     )
-    .execute(pool)
+    .execute(&mut *transaction)
     .await?;
-    Ok(())
+    confirm_pending_membership(transaction, list_id, subscriber_id).await
+}
+
+struct ConsumedToken {
+    subscriber_id: Uuid,
+    list_id: Uuid,
+    expires_at: DateTime<Utc>,
+}
+
+// Marking the token as used and reading it back happens in a single statement,
+// so two concurrent requests can never both consume the same token.
+#[tracing::instrument(
+    name = "Consume subscription token",
+    skip(subscription_token, transaction)
+)]
+async fn consume_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscription_token: &SubscriptionToken,
+) -> Result<Option<ConsumedToken>, sqlx::Error> {
+    let result = sqlx::query_as!(
+        ConsumedToken,
+        r#"
+        UPDATE subscription_tokens
+        SET used_at = now()
+        WHERE subscription_token = $1 AND used_at IS NULL
+        RETURNING subscriber_id, list_id, expires_at
+        "#,
+        subscription_token.as_ref(),
+    )
+    .fetch_optional(transaction)
+    .await?;
+    Ok(result)
+}
+
+struct TokenSubscriber {
+    id: Uuid,
+    email: String,
+    name: String,
+    list_id: Uuid,
+    // Either the address or the list the link was sent for awaits confirmation.
+    pending: bool,
 }
 
-#[tracing::instrument(name = "Get subscriber_id from token", skip(subscription_token, pool))]
-async fn get_subscriber_id_from_token(
+#[tracing::instrument(name = "Get subscriber from token", skip(subscription_token, pool))]
+async fn get_subscriber_from_token(
     pool: &PgPool,
     subscription_token: &SubscriptionToken,
-) -> Result<Option<Uuid>, sqlx::Error> {
-    let result = sqlx::query!(
-        r#"SELECT subscriber_id FROM subscription_tokens WHERE subscription_token = $1"#,
+) -> Result<Option<TokenSubscriber>, sqlx::Error> {
+    let result = sqlx::query_as!(
+        TokenSubscriber,
+        r#"
+        SELECT
+            s.id,
+            s.email,
+            s.name,
+            t.list_id,
+            (
+                s.status = 'pending_confirmation' OR
+                EXISTS (
+                    SELECT 1 FROM list_memberships m
+                    WHERE
+                        m.list_id = t.list_id AND
+                        m.subscriber_id = s.id AND
+                        m.status = 'pending_confirmation'
+                )
+            ) AS "pending!"
+        FROM subscription_tokens t
+        JOIN subscriptions s ON s.id = t.subscriber_id
+        WHERE t.subscription_token = $1
+        "#,
         subscription_token.as_ref(),
     )
     .fetch_optional(pool)
     .await?;
-    Ok(result.map(|r| r.subscriber_id))
+    Ok(result)
 }
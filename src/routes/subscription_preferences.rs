@@ -0,0 +1,352 @@
+use crate::domain::unsubscribe_token::UnsubscribeToken;
+use crate::domain::SubscriberName;
+use crate::issue_delivery_worker::skip_queued_deliveries;
+use crate::mailing_lists::{
+    get_memberships, select_lists, set_memberships, ListSelectionError, Membership,
+};
+use crate::startup::HmacSecret;
+use crate::util::{e500, error_chain_fmt, parse_form, see_other};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpResponse, ResponseError};
+use actix_web_flash_messages::{FlashMessage, IncomingFlashMessages};
+use anyhow::Context;
+use askama::Template;
+use chrono::{DateTime, Duration, Utc};
+use sqlx::PgPool;
+use std::fmt;
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+// How long a subscriber can pause deliveries for, in days.
+const PAUSE_OPTIONS: [i64; 3] = [7, 30, 90];
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum PreferencesError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("This link to your subscription preferences is not valid.")]
+    UnauthorizedToken,
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for PreferencesError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for PreferencesError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UnauthorizedToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+struct Preferences {
+    email: String,
+    name: String,
+    status: String,
+    paused_until: Option<DateTime<Utc>>,
+}
+
+#[derive(Template)]
+#[template(path = "preferences.html")]
+struct PreferencesTemplate<'a> {
+    token: &'a str,
+    preferences: Preferences,
+    // Only set while the pause is still running.
+    paused_until: Option<String>,
+    memberships: Vec<Membership>,
+    pause_options: [i64; 3],
+    messages: Vec<&'a str>,
+}
+
+// Reached from the footer of every issue: the signed token stands in for an account.
+#[get("/preferences")]
+#[tracing::instrument(
+    name = "Render subscription preferences",
+    skip(parameters, flash_messages, pool, hmac_secret)
+)]
+pub async fn preferences_page(
+    parameters: web::Query<Parameters>,
+    flash_messages: IncomingFlashMessages,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let messages = flash_messages
+        .iter()
+        .map(|m| m.content())
+        .collect::<Vec<_>>();
+    let subscriber_id = verify_token(&parameters.token, &hmac_secret)?;
+    let preferences = get_preferences(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscription preferences.")
+        .map_err(PreferencesError::Unexpected)?
+        // The token is genuine, but the subscriber no longer exists.
+        .ok_or(PreferencesError::UnauthorizedToken)?;
+
+    let memberships = get_memberships(&pool, subscriber_id)
+        .await
+        .context("Failed to retrieve the subscriber's lists.")
+        .map_err(PreferencesError::Unexpected)?;
+    let paused_until = preferences
+        .paused_until
+        .filter(|paused_until| *paused_until > Utc::now())
+        .map(format_date);
+
+    let page = PreferencesTemplate {
+        token: &parameters.token,
+        preferences,
+        paused_until,
+        memberships,
+        pause_options: PAUSE_OPTIONS,
+        messages,
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+#[derive(serde::Deserialize)]
+pub struct NameFormData {
+    name: String,
+}
+
+#[post("/preferences/name")]
+#[tracing::instrument(
+    name = "Update a subscriber's name",
+    skip(parameters, form, pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn update_subscriber_name(
+    parameters: web::Query<Parameters>,
+    form: web::Form<NameFormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_token(&parameters.token, &hmac_secret)?;
+    tracing::Span::current().record("subscriber_id", &tracing::field::display(&subscriber_id));
+    let redirect_to = format!("/preferences?token={}", parameters.token);
+    let name = match SubscriberName::parse(form.0.name) {
+        Ok(name) => name,
+        Err(e) => {
+            FlashMessage::error(e).send();
+            return Ok(see_other(&redirect_to));
+        }
+    };
+
+    sqlx::query!(
+        "UPDATE subscriptions SET name = $2 WHERE id = $1",
+        subscriber_id,
+        name.as_ref(),
+    )
+    .execute(pool.get_ref())
+    .await
+    .context("Failed to update the subscriber's name.")
+    .map_err(PreferencesError::Unexpected)?;
+
+    FlashMessage::info("Your name has been updated.").send();
+    Ok(see_other(&redirect_to))
+}
+
+#[derive(serde::Deserialize)]
+pub struct ListsFormData {
+    #[serde(default)]
+    lists: Vec<String>,
+}
+
+// Following the link from an issue proves the address is theirs, so joining a list from
+// here needs no further confirmation.
+#[post("/preferences/lists")]
+#[tracing::instrument(
+    name = "Update the lists of a subscriber",
+    skip(parameters, body, pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn update_subscriber_lists(
+    parameters: web::Query<Parameters>,
+    body: web::Bytes,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_token(&parameters.token, &hmac_secret)?;
+    tracing::Span::current().record("subscriber_id", &tracing::field::display(&subscriber_id));
+    let redirect_to = format!("/preferences?token={}", parameters.token);
+    let form: ListsFormData = parse_form(&body).map_err(PreferencesError::Validation)?;
+    // An empty selection would fall back to the default list.
+    if form.lists.is_empty() {
+        FlashMessage::error(
+            "Please pick at least one list, or unsubscribe to stop receiving our emails.",
+        )
+        .send();
+        return Ok(see_other(&redirect_to));
+    }
+    let lists = select_lists(&pool, &form.lists)
+        .await
+        .map_err(|e| match e {
+            ListSelectionError::UnknownList(_) => PreferencesError::Validation(e.to_string()),
+            ListSelectionError::Unexpected(e) => PreferencesError::Unexpected(
+                anyhow::Error::new(e).context("Failed to get the lists from the database."),
+            ),
+        })?;
+    let list_ids: Vec<Uuid> = lists.iter().map(|l| l.list_id).collect();
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")
+        .map_err(PreferencesError::Unexpected)?;
+    let confirmed = sqlx::query!(
+        "SELECT status FROM subscriptions WHERE id = $1 FOR UPDATE",
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to get the subscriber's status.")
+    .map_err(PreferencesError::Unexpected)?
+    .map(|r| r.status == "confirmed")
+    .ok_or(PreferencesError::UnauthorizedToken)?;
+    if !confirmed {
+        FlashMessage::error("Only confirmed subscriptions can change their lists.").send();
+        return Ok(see_other(&redirect_to));
+    }
+    set_memberships(&mut transaction, subscriber_id, &list_ids)
+        .await
+        .context("Failed to update the subscriber's lists.")
+        .map_err(PreferencesError::Unexpected)?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to update the lists.")
+        .map_err(PreferencesError::Unexpected)?;
+
+    FlashMessage::info("Your lists have been updated.").send();
+    Ok(see_other(&redirect_to))
+}
+
+#[derive(serde::Deserialize)]
+pub struct PauseFormData {
+    // Zero resumes deliveries.
+    days: i64,
+}
+
+#[post("/preferences/pause")]
+#[tracing::instrument(
+    name = "Pause or resume deliveries to a subscriber",
+    skip(parameters, form, pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn pause_deliveries(
+    parameters: web::Query<Parameters>,
+    form: web::Form<PauseFormData>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_token(&parameters.token, &hmac_secret)?;
+    tracing::Span::current().record("subscriber_id", &tracing::field::display(&subscriber_id));
+    let redirect_to = format!("/preferences?token={}", parameters.token);
+    let days = form.0.days;
+    if days != 0 && !PAUSE_OPTIONS.contains(&days) {
+        return Err(PreferencesError::Validation(format!(
+            "Deliveries cannot be paused for {} days.",
+            days
+        ))
+        .into());
+    }
+    let paused_until = (days > 0).then(|| Utc::now() + Duration::days(days));
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")
+        .map_err(PreferencesError::Unexpected)?;
+    let email = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET paused_until = $2
+        WHERE id = $1 AND status = 'confirmed'
+        RETURNING email
+        "#,
+        subscriber_id,
+        paused_until,
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to update the subscriber's pause.")
+    .map_err(PreferencesError::Unexpected)?
+    .map(|r| r.email);
+    let email = match email {
+        Some(email) => email,
+        None => {
+            FlashMessage::error("Only confirmed subscriptions can be paused.").send();
+            return Ok(see_other(&redirect_to));
+        }
+    };
+    // Issues already waiting for the subscriber would otherwise still reach them.
+    if paused_until.is_some() {
+        skip_queued_deliveries(
+            &mut transaction,
+            &email,
+            "Deliveries are paused by the subscriber.",
+        )
+        .await
+        .context("Failed to drop the queued deliveries.")
+        .map_err(PreferencesError::Unexpected)?;
+    }
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to pause deliveries.")
+        .map_err(PreferencesError::Unexpected)?;
+
+    match paused_until {
+        Some(paused_until) => FlashMessage::info(format!(
+            "Deliveries are paused until {}.",
+            format_date(paused_until)
+        ))
+        .send(),
+        None => FlashMessage::info("Deliveries have been resumed.").send(),
+    }
+    Ok(see_other(&redirect_to))
+}
+
+fn format_date(date: DateTime<Utc>) -> String {
+    date.format("%B %-d, %Y").to_string()
+}
+
+fn verify_token(token: &str, hmac_secret: &HmacSecret) -> Result<Uuid, PreferencesError> {
+    UnsubscribeToken::parse(token)
+        .map_err(PreferencesError::Validation)?
+        .verify(&hmac_secret.0)
+        .ok_or(PreferencesError::UnauthorizedToken)
+}
+
+#[tracing::instrument(skip(pool))]
+async fn get_preferences(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<Option<Preferences>, sqlx::Error> {
+    sqlx::query_as!(
+        Preferences,
+        r#"
+        SELECT email, name, status, paused_until
+        FROM subscriptions
+        WHERE id = $1
+        "#,
+        subscriber_id,
+    )
+    .fetch_optional(pool)
+    .await
+}
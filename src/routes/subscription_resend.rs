@@ -0,0 +1,128 @@
+use crate::confirmation_email_worker::enqueue_confirmation_emails;
+use crate::domain::SubscriberEmail;
+use crate::resend_cooldown::ResendCooldown;
+use crate::util::{client_ip, e400, e500, retry_after_seconds};
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpRequest, HttpResponse};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use std::time::Duration;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct FormData {
+    email: String,
+}
+
+#[derive(Template)]
+#[template(path = "resend_confirmation.html")]
+struct ResendConfirmationTemplate {
+    sent: bool,
+    retry_after_seconds: Option<u64>,
+}
+
+#[get("/subscriptions/resend")]
+pub async fn resend_confirmation_form() -> Result<HttpResponse, actix_web::Error> {
+    render(StatusCode::OK, false, None)
+}
+
+// The response is the same whether the address is pending, confirmed, unknown or
+// cooling down, so the form cannot be used to find out who is subscribed. The email is
+// sent by the confirmation worker, so that the response time does not give it away either.
+#[post("/subscriptions/resend")]
+#[tracing::instrument(
+    name = "Resend a confirmation link to a pending subscriber",
+    skip(request, form, pool, cooldown),
+    fields(subscriber_email = tracing::field::Empty)
+)]
+pub async fn request_confirmation_resend(
+    request: HttpRequest,
+    form: web::Form<FormData>,
+    pool: web::Data<PgPool>,
+    cooldown: web::Data<ResendCooldown>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let email = SubscriberEmail::parse(form.0.email).map_err(e400)?;
+    tracing::Span::current().record("subscriber_email", &tracing::field::display(&email));
+
+    if let Some(ip) = client_ip(&request) {
+        if let Some(retry_after) = cooldown.start_for_ip(&ip).await.map_err(e500)? {
+            return render(StatusCode::TOO_MANY_REQUESTS, false, Some(retry_after));
+        }
+    }
+    if cooldown
+        .start_for_email(email.as_ref())
+        .await
+        .map_err(e500)?
+        .is_none()
+    {
+        if let Err(e) = queue_confirmation_email(&pool, &email).await {
+            // Failing loudly would tell the caller that the address is pending.
+            tracing::error!(
+                error.cause_chain = ?e,
+                error.message = %e,
+                "Failed to queue a confirmation email."
+            );
+        }
+    }
+    render(StatusCode::OK, true, None)
+}
+
+async fn queue_confirmation_email(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<(), anyhow::Error> {
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")?;
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        r#"
+        SELECT id
+        FROM subscriptions
+        WHERE
+            email = $1 AND
+            (
+                status = 'pending_confirmation' OR
+                EXISTS (
+                    SELECT 1 FROM list_memberships m
+                    WHERE m.subscriber_id = subscriptions.id AND m.status = 'pending_confirmation'
+                )
+            )
+        "#,
+        email.as_ref(),
+    )
+    .fetch_optional(&mut transaction)
+    .await
+    .context("Failed to get the pending subscriber from the database.")?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    enqueue_confirmation_emails(&mut transaction, &subscriber_ids)
+        .await
+        .context("Failed to queue a confirmation email.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to queue a confirmation email.")
+}
+
+fn render(
+    status: StatusCode,
+    sent: bool,
+    retry_after: Option<Duration>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let retry_after_seconds = retry_after.as_ref().map(retry_after_seconds);
+    let page = ResendConfirmationTemplate {
+        sent,
+        retry_after_seconds,
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    let mut response = HttpResponse::build(status);
+    if let Some(seconds) = retry_after_seconds {
+        response.insert_header((RETRY_AFTER, seconds.to_string()));
+    }
+    Ok(response.content_type(ContentType::html()).body(page_html))
+}
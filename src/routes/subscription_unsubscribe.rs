@@ -0,0 +1,149 @@
+use crate::domain::unsubscribe_token::UnsubscribeToken;
+use crate::issue_delivery_worker::skip_queued_deliveries;
+use crate::mailing_lists::leave_all_lists;
+use crate::startup::HmacSecret;
+use crate::util::{e500, error_chain_fmt};
+use actix_web::http::header::ContentType;
+use actix_web::http::StatusCode;
+use actix_web::{get, post, web, HttpResponse, ResponseError};
+use anyhow::Context;
+use askama::Template;
+use sqlx::PgPool;
+use std::fmt;
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct Parameters {
+    token: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum UnsubscribeError {
+    #[error("{0}")]
+    Validation(String),
+    #[error("Failed to unsubscribe because of unauthorized token.")]
+    UnauthorizedToken,
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for UnsubscribeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for UnsubscribeError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Validation(_) => StatusCode::BAD_REQUEST,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            Self::UnauthorizedToken => StatusCode::UNAUTHORIZED,
+        }
+    }
+}
+
+#[derive(Template)]
+#[template(path = "unsubscribe.html")]
+struct UnsubscribeTemplate<'a> {
+    token: &'a str,
+    unsubscribed: bool,
+}
+
+// Link scanners and mail clients may prefetch GET links, so the GET only
+// renders a confirmation form; the subscription is left untouched.
+#[get("/subscriptions/unsubscribe")]
+#[tracing::instrument(name = "Render unsubscribe form", skip(parameters, hmac_secret))]
+pub async fn unsubscribe_form(
+    parameters: web::Query<Parameters>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    verify_token(&parameters.token, &hmac_secret)?;
+    render(&parameters.token, false)
+}
+
+// Handles both the confirmation form and RFC 8058 one-click requests
+// (`List-Unsubscribe=One-Click` body) sent by mail clients.
+#[post("/subscriptions/unsubscribe")]
+#[tracing::instrument(
+    name = "Unsubscribe a subscriber",
+    skip(parameters, pool, hmac_secret),
+    fields(subscriber_id = tracing::field::Empty)
+)]
+pub async fn unsubscribe(
+    parameters: web::Query<Parameters>,
+    pool: web::Data<PgPool>,
+    hmac_secret: web::Data<HmacSecret>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let subscriber_id = verify_token(&parameters.token, &hmac_secret)?;
+    tracing::Span::current().record("subscriber_id", &tracing::field::display(&subscriber_id));
+
+    let found = mark_subscriber_as_unsubscribed(&pool, subscriber_id)
+        .await
+        .context("Failed to update subscriber as unsubscribed in the database.")
+        .map_err(UnsubscribeError::Unexpected)?;
+    if !found {
+        // The token is genuine, but the subscriber no longer exists.
+        return Err(UnsubscribeError::UnauthorizedToken.into());
+    }
+    render(&parameters.token, true)
+}
+
+fn verify_token(token: &str, hmac_secret: &HmacSecret) -> Result<Uuid, UnsubscribeError> {
+    UnsubscribeToken::parse(token)
+        .map_err(UnsubscribeError::Validation)?
+        .verify(&hmac_secret.0)
+        .ok_or(UnsubscribeError::UnauthorizedToken)
+}
+
+fn render(token: &str, unsubscribed: bool) -> Result<HttpResponse, actix_web::Error> {
+    let page = UnsubscribeTemplate {
+        token,
+        unsubscribed,
+    };
+    let page_html = page.render().map_err(e500)?;
+
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(page_html))
+}
+
+// Returns whether the subscriber exists. Bounced and complained addresses keep their status,
+// like in the admin unsubscribe, but still leave their lists.
+#[tracing::instrument(name = "Mark subscriber as unsubscribed", skip(subscriber_id, pool))]
+async fn mark_subscriber_as_unsubscribed(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let email = sqlx::query!(
+        r#"SELECT email FROM subscriptions WHERE id = $1 FOR UPDATE"#,
+        subscriber_id,
+    )
+    .fetch_optional(&mut transaction)
+    .await?
+    .map(|r| r.email);
+    let email = match email {
+        Some(email) => email,
+        None => return Ok(false),
+    };
+    sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = 'unsubscribed'
+        WHERE
+            id = $1 AND
+            status IN ('pending_confirmation', 'confirmed')
+        "#,
+        subscriber_id,
+    )
+    .execute(&mut transaction)
+    .await?;
+    // Subscribing again later should not bring back every list.
+    leave_all_lists(&mut transaction, subscriber_id).await?;
+    // Issues already waiting for the subscriber, retries included, must not go out.
+    skip_queued_deliveries(&mut transaction, &email, "The subscriber has unsubscribed.").await?;
+    transaction.commit().await?;
+    Ok(true)
+}
@@ -1,15 +1,19 @@
 use crate::domain::subscription_token::SubscriptionToken;
 use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
 use crate::email_client::EmailClient;
+use crate::mailing_lists::{join_list, select_lists, ListSelectionError};
+use crate::resend_cooldown::ResendCooldown;
+use crate::routes::json_error;
 use crate::startup::ApplicationBaseUrl;
 use crate::util::error_chain_fmt;
 use actix_web::body::BoxBody;
-use actix_web::http::header::ContentType;
+use actix_web::error::InternalError;
+use actix_web::http::header::{ContentType, HeaderName, ACCEPT, CONTENT_TYPE};
 use actix_web::http::StatusCode;
-use actix_web::{post, web, HttpResponse, ResponseError};
+use actix_web::{post, web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
 use askama::Template;
-use chrono::Utc;
+use chrono::{Duration, Utc};
 use reqwest::Url;
 use serde::Deserialize;
 use sqlx::{PgPool, Postgres, Transaction};
@@ -19,29 +23,72 @@ use uuid::Uuid;
 
 #[derive(Deserialize)]
 pub struct FormData {
-    name: String,
-    email: String,
+    name: Option<String>,
+    email: Option<String>,
+    // The slug of the list to join, the default list when missing.
+    list: Option<String>,
 }
 
 impl TryFrom<FormData> for NewSubscriber {
-    type Error = String;
+    type Error = SubscribeError;
     fn try_from(value: FormData) -> Result<Self, Self::Error> {
-        let email = SubscriberEmail::parse(value.email)?;
-        let name = SubscriberName::parse(value.name)?;
+        let email = value
+            .email
+            .ok_or_else(|| SubscribeError::validation("email", "The email address is missing."))?;
+        let name = value
+            .name
+            .ok_or_else(|| SubscribeError::validation("name", "The name is missing."))?;
+        let email =
+            SubscriberEmail::parse(email).map_err(|e| SubscribeError::validation("email", e))?;
+        let name =
+            SubscriberName::parse(name).map_err(|e| SubscribeError::validation("name", e))?;
         Ok(Self { email, name })
     }
 }
 
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
-    #[error("{0}")]
-    Validation(String),
+    #[error("{message}")]
+    Validation {
+        field: &'static str,
+        message: String,
+    },
+    #[error("The request body could not be parsed: {0}")]
+    InvalidBody(String),
     #[error("Failed to subscribe as the subscriber is already confirmed.")]
     AlreadyConfirmed,
     #[error(transparent)]
     Unexpected(#[from] anyhow::Error),
 }
 
+impl SubscribeError {
+    fn validation(field: &'static str, message: impl ToString) -> Self {
+        Self::Validation {
+            field,
+            message: message.to_string(),
+        }
+    }
+
+    fn code(&self) -> &'static str {
+        match self {
+            Self::Validation { .. } => "validation",
+            Self::InvalidBody(_) => "invalid_body",
+            Self::AlreadyConfirmed => "already_confirmed",
+            Self::Unexpected(_) => "internal_error",
+        }
+    }
+
+    fn json_response(&self) -> HttpResponse {
+        let (message, field) = match self {
+            Self::Validation { field, .. } => (self.to_string(), Some(*field)),
+            // The cause chain is logged, not handed out.
+            Self::Unexpected(_) => ("An unexpected error occurred.".to_string(), None),
+            _ => (self.to_string(), None),
+        };
+        json_error(self.status_code(), self.code(), message, field)
+    }
+}
+
 impl fmt::Debug for SubscribeError {
     fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
         error_chain_fmt(&self, f)
@@ -51,37 +98,112 @@ impl fmt::Debug for SubscribeError {
 impl ResponseError for SubscribeError {
     fn status_code(&self) -> StatusCode {
         match self {
-            Self::Validation(_) | Self::AlreadyConfirmed => StatusCode::BAD_REQUEST,
+            Self::Validation { .. } | Self::InvalidBody(_) | Self::AlreadyConfirmed => {
+                StatusCode::BAD_REQUEST
+            }
             Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
         }
     }
 
     fn error_response(&self) -> HttpResponse<BoxBody> {
         match self {
-            Self::Validation(_) | Self::AlreadyConfirmed => HttpResponse::build(self.status_code())
-                .content_type(ContentType::plaintext())
-                .body(self.to_string()),
+            Self::Validation { .. } | Self::InvalidBody(_) | Self::AlreadyConfirmed => {
+                HttpResponse::build(self.status_code())
+                    .content_type(ContentType::plaintext())
+                    .body(self.to_string())
+            }
             _ => HttpResponse::new(self.status_code()),
         }
     }
 }
 
+fn has_json_header(request: &HttpRequest, header: HeaderName) -> bool {
+    request
+        .headers()
+        .get(header)
+        .and_then(|h| h.to_str().ok())
+        .map(|h| h.contains("application/json"))
+        .unwrap_or(false)
+}
+
+// The HTML form keeps getting plain text errors, while clients that send or accept JSON
+// get JSON back.
+fn wants_json(request: &HttpRequest) -> bool {
+    has_json_header(request, CONTENT_TYPE) || has_json_header(request, ACCEPT)
+}
+
+fn parse_body(request: &HttpRequest, body: &[u8]) -> Result<FormData, SubscribeError> {
+    if has_json_header(request, CONTENT_TYPE) {
+        serde_json::from_slice(body).map_err(|e| SubscribeError::InvalidBody(e.to_string()))
+    } else {
+        serde_urlencoded::from_bytes(body).map_err(|e| SubscribeError::InvalidBody(e.to_string()))
+    }
+}
+
 #[post("/subscriptions")]
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
+    skip(request, body, pool, email_client, base_url, cooldown),
     fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name,
+        subscriber_email = tracing::field::Empty,
+        subscriber_name = tracing::field::Empty,
     )
 )]
 pub async fn subscription(
-    form: web::Form<FormData>,
+    request: HttpRequest,
+    body: web::Bytes,
     pool: web::Data<PgPool>,
     email_client: web::Data<EmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
-) -> Result<HttpResponse, SubscribeError> {
-    let subscriber = form.0.try_into().map_err(SubscribeError::Validation)?;
+    cooldown: web::Data<ResendCooldown>,
+) -> Result<HttpResponse, actix_web::Error> {
+    let outcome = match parse_body(&request, &body) {
+        Ok(form) => subscribe(form, &pool, &email_client, &base_url, &cooldown).await,
+        Err(e) => Err(e),
+    };
+    match (outcome, wants_json(&request)) {
+        (Ok(()), true) => {
+            Ok(HttpResponse::Ok().json(serde_json::json!({ "status": "pending_confirmation" })))
+        }
+        (Ok(()), false) => Ok(HttpResponse::Ok().finish()),
+        (Err(e), true) => {
+            let response = e.json_response();
+            Err(InternalError::from_response(e, response).into())
+        }
+        (Err(e), false) => Err(e.into()),
+    }
+}
+
+async fn subscribe(
+    mut form: FormData,
+    pool: &PgPool,
+    email_client: &EmailClient,
+    base_url: &ApplicationBaseUrl,
+    cooldown: &ResendCooldown,
+) -> Result<(), SubscribeError> {
+    let list_slugs: Vec<String> = form.list.take().into_iter().collect();
+    let subscriber: NewSubscriber = form.try_into()?;
+    tracing::Span::current()
+        .record(
+            "subscriber_email",
+            &tracing::field::display(&subscriber.email),
+        )
+        .record(
+            "subscriber_name",
+            &tracing::field::display(subscriber.name.as_ref()),
+        );
+
+    let list = match select_lists(pool, &list_slugs).await {
+        Ok(mut lists) => lists.remove(0),
+        Err(e @ ListSelectionError::UnknownList(_)) => {
+            return Err(SubscribeError::validation("list", e))
+        }
+        Err(ListSelectionError::Unexpected(e)) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to get the list from the database.")
+                .into())
+        }
+    };
 
     let mut transaction = pool
         .begin()
@@ -91,32 +213,79 @@ pub async fn subscription(
     let subscriber_status = insert_or_get_subscriber(&mut transaction, &subscriber)
         .await
         .context("Failed to insert new subscriber in the database.")?;
+    let membership = join_list(&mut transaction, list.list_id, subscriber_status.id)
+        .await
+        .context("Failed to add the subscriber to the list.")?;
 
-    if subscriber_status.confirmed() {
+    if subscriber_status.confirmed() && membership.status == "confirmed" {
         return Err(SubscribeError::AlreadyConfirmed);
     }
 
-    let subscription_token = SubscriptionToken::new();
-    // Note: This could result in multiple subscription tokens for the same subscriber id.
-    // This is caused when subscriber (pending_confirmation) tries to subscribe again
-    // New record with new token is stored in the table when this function is called.
-    // User behaviour is not impacted i.e., new confirmation link is sent out on retry.
-    // But there could be multiple stale entries for the same subscriber even after confirmation.
-    // We could add TTL to the records to avoid stagnation. (TODO)
-    store_token(&mut transaction, subscriber_status.id, &subscription_token)
-        .await
-        .context("Failed to store subscription token in the database")?;
+    // Subscribing again while pending sends the link that is still valid, if any.
+    let subscription_token =
+        confirmation_token(&mut transaction, subscriber_status.id, list.list_id)
+            .await
+            .context("Failed to get a subscription token from the database")?;
 
     transaction
         .commit()
         .await
         .context("Failed to commit SQL transaction to store a new subscriber.")?;
 
-    send_confirmation_email(&email_client, subscriber, &base_url.0, &subscription_token)
+    // Subscribing again to the same list is a way to ask for the link again, and is spaced
+    // out like the resend form.
+    if !membership.inserted
+        && cooldown
+            .start_for_email(subscriber.email.as_ref())
+            .await?
+            .is_some()
+    {
+        return Ok(());
+    }
+    send_confirmation_email(email_client, subscriber, &base_url.0, &subscription_token)
         .await
         .context("Failed to send a confirmation email.")?;
 
-    Ok(HttpResponse::Ok().finish())
+    Ok(())
+}
+
+// A link that is about to expire is not worth sending again.
+const MIN_REMAINING_TOKEN_VALIDITY_MINUTES: i64 = 60;
+
+// Returns the pending subscriber's latest unused token for the list if it stays valid long
+// enough, so that every confirmation email carries the same link. Otherwise a new one is
+// stored.
+#[tracing::instrument(name = "Get or create a subscription token", skip(transaction))]
+pub(crate) async fn confirmation_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    list_id: Uuid,
+) -> Result<SubscriptionToken, sqlx::Error> {
+    let existing_token = sqlx::query!(
+        r#"
+        SELECT subscription_token
+        FROM subscription_tokens
+        WHERE
+            subscriber_id = $1 AND
+            list_id = $2 AND
+            used_at IS NULL AND
+            expires_at > $3
+        ORDER BY expires_at DESC
+        LIMIT 1
+        "#,
+        subscriber_id,
+        list_id,
+        Utc::now() + Duration::minutes(MIN_REMAINING_TOKEN_VALIDITY_MINUTES),
+    )
+    .fetch_optional(&mut *transaction)
+    .await?
+    .and_then(|r| SubscriptionToken::parse(r.subscription_token).ok());
+    if let Some(token) = existing_token {
+        return Ok(token);
+    }
+    let subscription_token = SubscriptionToken::new();
+    store_token(transaction, subscriber_id, list_id, &subscription_token).await?;
+    Ok(subscription_token)
 }
 
 #[tracing::instrument(
@@ -126,13 +295,16 @@ pub async fn subscription(
 async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
+    list_id: Uuid,
     subscription_token: &SubscriptionToken,
 ) -> Result<(), sqlx::Error> {
     sqlx::query!(
-        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id)
-        VALUES ($1, $2)"#,
+        r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id, list_id, expires_at)
+        VALUES ($1, $2, $3, $4)"#,
         subscription_token.as_ref(),
-        subscriber_id
+        subscriber_id,
+        list_id,
+        Utc::now() + SubscriptionToken::validity()
     )
     .execute(transaction)
     .await?;
@@ -149,12 +321,12 @@ struct EmailTemplate<'a> {
     name = "Send a confirmation email to a new subscriber",
     skip(email_client, subscriber, base_url, subscription_token)
 )]
-async fn send_confirmation_email(
+pub(crate) async fn send_confirmation_email(
     email_client: &EmailClient,
     subscriber: NewSubscriber,
     base_url: &Url,
     subscription_token: &SubscriptionToken,
-) -> Result<(), reqwest::Error> {
+) -> Result<(), anyhow::Error> {
     let confirmation_link = base_url
         .join(&format!(
             "subscriptions/confirm?subscription_token={}",
@@ -176,7 +348,7 @@ async fn send_confirmation_email(
     };
 
     email_client
-        .send_email(&subscriber.email, "Welcome!", &html_body, &plain_body)
+        .send_email(&subscriber.email, "Welcome!", &html_body, &plain_body, &[])
         .await
 }
 
@@ -205,7 +377,10 @@ async fn insert_or_get_subscriber(
         r#"
         INSERT INTO subscriptions (id, email, name, subscribed_at, status)
         VALUES ($1, $2, $3, $4, 'pending_confirmation')
-        ON CONFLICT (email) DO UPDATE SET name = EXCLUDED.name
+        ON CONFLICT (email) DO UPDATE SET name = CASE
+            WHEN subscriptions.status = 'pending_confirmation' THEN EXCLUDED.name
+            ELSE subscriptions.name
+        END
         RETURNING id, status;
         "#,
         subscriber_id,
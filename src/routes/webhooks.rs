@@ -0,0 +1,180 @@
+use crate::issue_delivery_worker::skip_queued_deliveries;
+use crate::startup::PostmarkWebhookSecret;
+use crate::util::error_chain_fmt;
+use actix_web::http::StatusCode;
+use actix_web::{post, web, HttpRequest, HttpResponse, ResponseError};
+use anyhow::Context;
+use secrecy::ExposeSecret;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use std::fmt;
+use std::fmt::Formatter;
+use uuid::Uuid;
+
+// Postmark is configured to send the shared secret in this custom header.
+const SECRET_HEADER: &str = "X-Postmark-Webhook-Secret";
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct PostmarkEvent {
+    record_type: String,
+    #[serde(rename = "Type")]
+    event_type: Option<String>,
+    email: Option<String>,
+    description: Option<String>,
+}
+
+#[derive(thiserror::Error)]
+pub enum WebhookError {
+    #[error("Missing or invalid webhook secret.")]
+    Unauthorized,
+    #[error(transparent)]
+    Unexpected(#[from] anyhow::Error),
+}
+
+impl fmt::Debug for WebhookError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        error_chain_fmt(&self, f)
+    }
+}
+
+impl ResponseError for WebhookError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            Self::Unauthorized => StatusCode::UNAUTHORIZED,
+            Self::Unexpected(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+// The status a subscription is moved to, if the event means we must stop mailing the address.
+fn suppression_status(event: &PostmarkEvent) -> Option<&'static str> {
+    match (event.record_type.as_str(), event.event_type.as_deref()) {
+        ("Bounce", Some("HardBounce")) => Some("bounced"),
+        ("SpamComplaint", _) => Some("complained"),
+        _ => None,
+    }
+}
+
+#[post("/webhooks/postmark")]
+#[tracing::instrument(
+    name = "Handle a Postmark webhook",
+    skip(request, event, pool, secret),
+    fields(record_type = %event.record_type, email = tracing::field::Empty)
+)]
+pub async fn postmark_webhook(
+    request: HttpRequest,
+    event: web::Json<PostmarkEvent>,
+    pool: web::Data<PgPool>,
+    secret: web::Data<PostmarkWebhookSecret>,
+) -> Result<HttpResponse, WebhookError> {
+    verify_secret(&request, &secret)?;
+
+    let (email, status) = match (&event.email, suppression_status(&event)) {
+        (Some(email), Some(status)) => (email, status),
+        // Deliveries, opens, soft bounces... are not relevant to suppression.
+        _ => return Ok(HttpResponse::Ok().finish()),
+    };
+    tracing::Span::current().record("email", &tracing::field::display(email));
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a postgres connection from the pool.")?;
+    store_event(&mut transaction, email, &event)
+        .await
+        .context("Failed to store the webhook event.")?;
+    suppress_subscriber(&mut transaction, email, status)
+        .await
+        .context("Failed to suppress the subscriber.")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to store a webhook event.")?;
+
+    Ok(HttpResponse::Ok().finish())
+}
+
+fn verify_secret(
+    request: &HttpRequest,
+    secret: &PostmarkWebhookSecret,
+) -> Result<(), WebhookError> {
+    let provided = request
+        .headers()
+        .get(SECRET_HEADER)
+        .ok_or(WebhookError::Unauthorized)?
+        .as_bytes();
+    // Comparing digests keeps the comparison time independent of the secret.
+    if Sha256::digest(provided) != Sha256::digest(secret.0.expose_secret().as_bytes()) {
+        return Err(WebhookError::Unauthorized);
+    }
+    Ok(())
+}
+
+#[tracing::instrument(skip(transaction, event))]
+async fn store_event(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    event: &PostmarkEvent,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO email_feedback_events (
+            id,
+            email,
+            record_type,
+            event_type,
+            description,
+            received_at
+        )
+        VALUES ($1, $2, $3, $4, $5, now())
+        "#,
+        Uuid::new_v4(),
+        email,
+        event.record_type,
+        event.event_type.as_deref().unwrap_or(&event.record_type),
+        event.description,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+// Flags the subscription and drops the deliveries still queued for the address. Postmark
+// does not keep the case the address was signed up with, so it is matched ignoring case.
+#[tracing::instrument(skip(transaction))]
+async fn suppress_subscriber(
+    transaction: &mut Transaction<'_, Postgres>,
+    email: &str,
+    status: &str,
+) -> Result<(), sqlx::Error> {
+    let mut addresses_on_file: Vec<String> = sqlx::query!(
+        r#"
+        UPDATE subscriptions
+        SET status = $2
+        WHERE
+            lower(email) = lower($1) AND
+            status IN ('pending_confirmation', 'confirmed', 'bounced')
+        RETURNING email
+        "#,
+        email,
+        status,
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.email)
+    .collect();
+    if addresses_on_file.is_empty() {
+        addresses_on_file.push(email.to_string());
+    }
+    for address in addresses_on_file {
+        skip_queued_deliveries(
+            transaction,
+            &address,
+            &format!("The address has been suppressed ({}).", status),
+        )
+        .await?;
+    }
+    Ok(())
+}
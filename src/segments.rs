@@ -0,0 +1,59 @@
+use sqlx::{PgPool, Postgres, Transaction};
+use uuid::Uuid;
+
+pub struct Segment {
+    pub segment_id: Uuid,
+    pub name: String,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum SegmentSelectionError {
+    #[error("There is no segment called '{0}'.")]
+    UnknownSegment(String),
+    #[error(transparent)]
+    Unexpected(#[from] sqlx::Error),
+}
+
+#[tracing::instrument(skip(pool))]
+pub async fn get_segments(pool: &PgPool) -> Result<Vec<Segment>, sqlx::Error> {
+    sqlx::query_as!(
+        Segment,
+        "SELECT segment_id, name FROM segments ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+// Resolves the segment picked by its name; no name means no segment, i.e. everyone
+// on the picked lists.
+#[tracing::instrument(skip(pool))]
+pub async fn select_segment(
+    pool: &PgPool,
+    name: Option<&str>,
+) -> Result<Option<Uuid>, SegmentSelectionError> {
+    let name = match name.map(str::trim) {
+        None | Some("") => return Ok(None),
+        Some(name) => name,
+    };
+    let segment = sqlx::query!("SELECT segment_id FROM segments WHERE name = $1", name)
+        .fetch_optional(pool)
+        .await?
+        .ok_or_else(|| SegmentSelectionError::UnknownSegment(name.to_string()))?;
+    Ok(Some(segment.segment_id))
+}
+
+#[tracing::instrument(skip(transaction))]
+pub async fn assign_issue_segment(
+    transaction: &mut Transaction<'_, Postgres>,
+    newsletter_issue_id: Uuid,
+    segment_id: Option<Uuid>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "UPDATE newsletter_issues SET segment_id = $2 WHERE newsletter_issue_id = $1",
+        newsletter_issue_id,
+        segment_id,
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
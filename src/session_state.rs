@@ -7,8 +7,21 @@ use uuid::Uuid;
 
 pub struct TypedSession(Session);
 
+// A login that passed the password check and still has to provide its second factor.
+#[derive(serde::Serialize, serde::Deserialize)]
+pub struct PendingLogin {
+    pub user_id: Uuid,
+    // Wrong codes are counted against the username, like wrong passwords.
+    pub username: String,
+    pub started_at: i64,
+    pub failed_attempts: u8,
+}
+
 impl TypedSession {
     const USER_ID_KEY: &'static str = "user_id";
+    const SESSION_ID_KEY: &'static str = "session_id";
+    const PENDING_LOGIN_KEY: &'static str = "pending_login";
+    const PENDING_TOTP_SECRET_KEY: &'static str = "pending_totp_secret";
 
     pub fn renew(&self) {
         self.0.renew();
@@ -25,6 +38,38 @@ impl TypedSession {
     pub fn get_user_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
         self.0.get(Self::USER_ID_KEY)
     }
+
+    pub fn insert_session_id(&self, session_id: Uuid) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::SESSION_ID_KEY, session_id)
+    }
+
+    pub fn get_session_id(&self) -> Result<Option<Uuid>, serde_json::Error> {
+        self.0.get(Self::SESSION_ID_KEY)
+    }
+
+    pub fn insert_pending_login(&self, pending: &PendingLogin) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::PENDING_LOGIN_KEY, pending)
+    }
+
+    pub fn get_pending_login(&self) -> Result<Option<PendingLogin>, serde_json::Error> {
+        self.0.get(Self::PENDING_LOGIN_KEY)
+    }
+
+    pub fn remove_pending_login(&self) {
+        self.0.remove(Self::PENDING_LOGIN_KEY);
+    }
+
+    pub fn insert_pending_totp_secret(&self, secret: &str) -> Result<(), serde_json::Error> {
+        self.0.insert(Self::PENDING_TOTP_SECRET_KEY, secret)
+    }
+
+    pub fn get_pending_totp_secret(&self) -> Result<Option<String>, serde_json::Error> {
+        self.0.get(Self::PENDING_TOTP_SECRET_KEY)
+    }
+
+    pub fn remove_pending_totp_secret(&self) {
+        self.0.remove(Self::PENDING_TOTP_SECRET_KEY);
+    }
 }
 
 impl FromRequest for TypedSession {
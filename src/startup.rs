@@ -1,7 +1,14 @@
-use crate::authentication::reject_anonymous_users;
-use crate::configuration::{DatabaseSettings, Settings};
+use crate::authentication::{
+    reject_anonymous_users, reject_invalid_api_tokens, LoginThrottle, PasswordHashing,
+};
+use crate::configuration::{
+    ConfirmationResendSettings, DatabaseSettings, LoginThrottleSettings, PasswordHashingSettings,
+    Settings,
+};
 use crate::email_client::EmailClient;
+use crate::resend_cooldown::ResendCooldown;
 use crate::routes::*;
+use crate::subscriber_import::MAX_IMPORT_BYTES;
 use actix_session::storage::RedisSessionStore;
 use actix_session::SessionMiddleware;
 use actix_web::cookie::Key;
@@ -15,7 +22,7 @@ use reqwest::Url;
 use secrecy::{ExposeSecret, Secret};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
-use std::net::TcpListener;
+use std::net::{IpAddr, TcpListener};
 use tracing_actix_web::TracingLogger;
 
 pub struct Application {
@@ -44,7 +51,12 @@ impl Application {
             email_client,
             base_url,
             configuration.application.hmac_secret,
+            configuration.application.postmark_webhook_secret,
+            configuration.application.trusted_proxies,
             configuration.redis_uri,
+            configuration.login_throttle,
+            configuration.confirmation_resend,
+            configuration.password_hashing,
         )
         .await?;
 
@@ -68,14 +80,22 @@ pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
 
 pub struct ApplicationBaseUrl(pub Url);
 pub struct HmacSecret(pub Secret<String>);
+pub struct PostmarkWebhookSecret(pub Secret<String>);
+pub struct TrustedProxies(pub Vec<IpAddr>);
 
+#[allow(clippy::too_many_arguments)]
 pub async fn run(
     listener: TcpListener,
     db_pool: PgPool,
     email_client: EmailClient,
     base_url: Url,
     hmac_secret: Secret<String>,
+    postmark_webhook_secret: Secret<String>,
+    trusted_proxies: Vec<IpAddr>,
     redis_uri: Secret<String>,
+    login_throttle_settings: LoginThrottleSettings,
+    confirmation_resend_settings: ConfirmationResendSettings,
+    password_hashing_settings: PasswordHashingSettings,
 ) -> Result<Server, anyhow::Error> {
     let db_pool = Data::new(db_pool);
     let email_client = Data::new(email_client);
@@ -84,6 +104,13 @@ pub async fn run(
     let message_store = CookieMessageStore::builder(secret_key.clone()).build();
     let message_framework = FlashMessagesFramework::builder(message_store).build();
     let redis_store = RedisSessionStore::new(redis_uri.expose_secret()).await?;
+    let login_throttle = Data::new(LoginThrottle::new(&redis_uri, login_throttle_settings).await?);
+    let resend_cooldown =
+        Data::new(ResendCooldown::new(&redis_uri, confirmation_resend_settings).await?);
+    let password_hashing = Data::new(PasswordHashing::new(&password_hashing_settings)?);
+    let hmac_secret = Data::new(HmacSecret(hmac_secret));
+    let postmark_webhook_secret = Data::new(PostmarkWebhookSecret(postmark_webhook_secret));
+    let trusted_proxies = Data::new(TrustedProxies(trusted_proxies));
     let server = HttpServer::new(move || {
         App::new()
             .wrap(TracingLogger::default())
@@ -95,23 +122,105 @@ pub async fn run(
             .service(health_check)
             .service(subscription)
             .service(confirm)
+            .service(resend_confirmation)
+            .service(resend_confirmation_form)
+            .service(request_confirmation_resend)
+            .service(unsubscribe_form)
+            .service(unsubscribe)
+            .service(preferences_page)
+            .service(update_subscriber_name)
+            .service(update_subscriber_lists)
+            .service(pause_deliveries)
+            .service(postmark_webhook)
             .service(home)
             .service(login_form)
             .service(login)
+            .service(two_factor_form)
+            .service(verify_two_factor)
+            .service(invitation_form)
+            .service(accept_invitation)
+            .service(password_reset_request_form)
+            .service(request_password_reset)
+            .service(password_reset_form)
+            .service(reset_password)
             .service(
                 web::scope("/admin")
                     .wrap(from_fn(reject_anonymous_users))
                     .service(admin_dashboard)
                     .service(newsletter_form)
                     .service(publish_newsletter)
+                    .service(list_drafts)
+                    .service(save_draft)
+                    .service(edit_draft)
+                    .service(update_draft)
+                    .service(preview_draft)
+                    .service(send_test_draft)
+                    .service(publish_draft)
                     .service(logout_user)
                     .service(change_password_form)
-                    .service(change_password),
+                    .service(change_password)
+                    .service(change_email_form)
+                    .service(change_email)
+                    .service(list_api_tokens)
+                    .service(create_user_api_token)
+                    .service(revoke_user_api_token)
+                    .service(list_sessions)
+                    .service(revoke_user_session)
+                    .service(revoke_other_user_sessions)
+                    .service(two_factor_settings)
+                    .service(enable_two_factor_authentication)
+                    .service(disable_two_factor_authentication)
+                    .service(delivery_failures)
+                    .service(requeue_delivery_failure)
+                    .service(requeue_all_delivery_failures)
+                    .service(list_subscribers)
+                    .service(export_subscribers)
+                    .service(
+                        web::scope("/subscribers/import")
+                            // Uploads carry a whole CSV file, in the encoding of a form.
+                            .app_data(web::PayloadConfig::new(3 * MAX_IMPORT_BYTES))
+                            .service(subscriber_import_form)
+                            .service(upload_subscriber_import)
+                            .service(subscriber_import_report)
+                            .service(run_subscriber_import),
+                    )
+                    .service(subscriber_details)
+                    .service(unsubscribe_subscriber)
+                    .service(delete_subscriber)
+                    .service(update_subscriber_attributes)
+                    .service(list_mailing_lists)
+                    .service(create_mailing_list)
+                    .service(list_segments)
+                    .service(create_segment)
+                    .service(list_issues)
+                    .service(issue_delivery_status)
+                    .service(cancel_scheduled_issue)
+                    .service(reschedule_issue)
+                    .service(list_users)
+                    .service(change_user_role)
+                    .service(invite_user),
+            )
+            .service(
+                web::scope("/api/v1")
+                    .wrap(from_fn(reject_invalid_api_tokens))
+                    .app_data(web::JsonConfig::default().error_handler(|e, _| {
+                        ApiError::Validation {
+                            field: None,
+                            message: e.to_string(),
+                        }
+                        .into()
+                    }))
+                    .service(publish_newsletter_via_api),
             )
             .app_data(db_pool.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
             .app_data(hmac_secret.clone())
+            .app_data(postmark_webhook_secret.clone())
+            .app_data(trusted_proxies.clone())
+            .app_data(login_throttle.clone())
+            .app_data(resend_cooldown.clone())
+            .app_data(password_hashing.clone())
     })
     .listen(listener)?
     .run();
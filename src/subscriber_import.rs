@@ -0,0 +1,261 @@
+use crate::confirmation_email_worker::enqueue_confirmation_emails;
+use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
+use sqlx::{PgExecutor, Postgres, Transaction};
+use std::collections::{HashMap, HashSet};
+use uuid::Uuid;
+
+#[derive(Debug)]
+pub struct AcceptedRow {
+    pub line: u64,
+    pub subscriber: NewSubscriber,
+}
+
+#[derive(Debug)]
+pub struct SkippedRow {
+    pub line: u64,
+    pub email: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Default)]
+pub struct ImportReport {
+    pub accepted: Vec<AcceptedRow>,
+    pub duplicates: Vec<SkippedRow>,
+    pub rejected: Vec<SkippedRow>,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ImportError {
+    #[error("{0}")]
+    InvalidCsv(String),
+    #[error(transparent)]
+    Unexpected(#[from] sqlx::Error),
+}
+
+pub enum ImportMode {
+    // The subscribers opted in elsewhere, e.g. with the service we are migrating from.
+    Confirmed { consent_source: String },
+    Pending,
+}
+
+// Uploaded files are stored whole until they are imported.
+pub const MAX_IMPORT_BYTES: usize = 5 * 1024 * 1024;
+pub const MAX_IMPORT_ROWS: usize = 50_000;
+
+// Columns are found through the header row, so that the export of the subscribers
+// page can be imported as is. Every row is validated, nothing is stored.
+pub fn parse_csv(csv: &str) -> Result<ImportReport, String> {
+    if csv.len() > MAX_IMPORT_BYTES {
+        return Err(format!(
+            "The CSV file is larger than {} MB. Please split it into smaller files.",
+            MAX_IMPORT_BYTES / (1024 * 1024)
+        ));
+    }
+    let mut reader = csv::ReaderBuilder::new()
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_reader(csv.as_bytes());
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("The CSV file cannot be read: {}", e))?
+        .clone();
+    let column = |name: &str| {
+        headers
+            .iter()
+            .position(|h| h.eq_ignore_ascii_case(name))
+            .ok_or_else(|| format!("The CSV file has no `{}` column.", name))
+    };
+    let (email_column, name_column) = (column("email")?, column("name")?);
+
+    let mut report = ImportReport::default();
+    let mut seen = HashSet::new();
+    for (i, record) in reader.records().enumerate() {
+        if i == MAX_IMPORT_ROWS {
+            return Err(format!(
+                "The CSV file has more than {} rows. Please split it into smaller files.",
+                MAX_IMPORT_ROWS
+            ));
+        }
+        let record = match record {
+            Ok(record) => record,
+            Err(e) => {
+                report.rejected.push(SkippedRow {
+                    line: e.position().map(|p| p.line()).unwrap_or_default(),
+                    email: String::new(),
+                    reason: e.to_string(),
+                });
+                continue;
+            }
+        };
+        let line = record.position().map(|p| p.line()).unwrap_or_default();
+        let email = record.get(email_column).unwrap_or_default();
+        let name = record.get(name_column).unwrap_or_default();
+        let subscriber = SubscriberEmail::parse(email)
+            .and_then(|email| Ok(NewSubscriber::new(email, SubscriberName::parse(name)?)));
+        match subscriber {
+            Err(reason) => report.rejected.push(SkippedRow {
+                line,
+                email: email.to_string(),
+                reason,
+            }),
+            Ok(_) if !seen.insert(email.to_lowercase()) => report.duplicates.push(SkippedRow {
+                line,
+                email: email.to_string(),
+                reason: "The address appears earlier in the file.".into(),
+            }),
+            Ok(subscriber) => report.accepted.push(AcceptedRow { line, subscriber }),
+        }
+    }
+    if report.accepted.is_empty() && report.duplicates.is_empty() && report.rejected.is_empty() {
+        return Err("The CSV file does not contain any subscriber.".into());
+    }
+    Ok(report)
+}
+
+// Addresses already on file count as duplicates whatever their status: an import must
+// not sign back up someone who unsubscribed.
+#[tracing::instrument(skip_all)]
+pub async fn dry_run(
+    executor: impl PgExecutor<'_>,
+    csv: &str,
+) -> Result<ImportReport, ImportError> {
+    let mut report = parse_csv(csv).map_err(ImportError::InvalidCsv)?;
+    let emails: Vec<String> = report
+        .accepted
+        .iter()
+        .map(|r| r.subscriber.email.as_ref().to_lowercase())
+        .collect();
+    let existing: HashMap<String, String> = sqlx::query!(
+        r#"
+        SELECT lower(email) AS "email!", status
+        FROM subscriptions
+        WHERE lower(email) = ANY($1)
+        "#,
+        &emails,
+    )
+    .fetch_all(executor)
+    .await?
+    .into_iter()
+    .map(|r| (r.email, r.status))
+    .collect();
+
+    let (accepted, duplicates): (Vec<_>, Vec<_>) = std::mem::take(&mut report.accepted)
+        .into_iter()
+        .partition(|r| !existing.contains_key(&r.subscriber.email.as_ref().to_lowercase()));
+    report.accepted = accepted;
+    for row in duplicates {
+        let email = row.subscriber.email.as_ref().to_string();
+        report.duplicates.push(SkippedRow {
+            line: row.line,
+            reason: format!(
+                "The address is already on file ({}).",
+                existing[&email.to_lowercase()]
+            ),
+            email,
+        });
+    }
+    report.duplicates.sort_by_key(|r| r.line);
+    Ok(report)
+}
+
+// Adds the accepted rows to the list and returns the ids of the new subscribers.
+// Addresses that were taken since the dry run are skipped, whatever their case.
+#[tracing::instrument(skip(transaction, rows, mode))]
+pub async fn import_subscribers(
+    transaction: &mut Transaction<'_, Postgres>,
+    rows: &[AcceptedRow],
+    mode: &ImportMode,
+    list_id: Uuid,
+) -> Result<Vec<Uuid>, sqlx::Error> {
+    let ids: Vec<Uuid> = rows.iter().map(|_| Uuid::new_v4()).collect();
+    let emails: Vec<String> = rows
+        .iter()
+        .map(|r| r.subscriber.email.as_ref().to_string())
+        .collect();
+    let names: Vec<String> = rows
+        .iter()
+        .map(|r| r.subscriber.name.as_ref().to_string())
+        .collect();
+    let (status, consent_source) = match mode {
+        ImportMode::Confirmed { consent_source } => ("confirmed", Some(consent_source.as_str())),
+        ImportMode::Pending => ("pending_confirmation", None),
+    };
+
+    let subscriber_ids: Vec<Uuid> = sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, consent_source)
+        SELECT id, email, name, now(), $4, $5
+        FROM unnest($1::uuid[], $2::text[], $3::text[]) AS t(id, email, name)
+        WHERE NOT EXISTS (SELECT 1 FROM subscriptions s WHERE lower(s.email) = lower(t.email))
+        ON CONFLICT (email) DO NOTHING
+        RETURNING id
+        "#,
+        &ids,
+        &emails,
+        &names,
+        status,
+        consent_source,
+    )
+    .fetch_all(&mut *transaction)
+    .await?
+    .into_iter()
+    .map(|r| r.id)
+    .collect();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        SELECT $1, subscriber_id, $3 FROM unnest($2::uuid[]) AS subscriber_id
+        "#,
+        list_id,
+        &subscriber_ids,
+        status,
+    )
+    .execute(&mut *transaction)
+    .await?;
+    if let ImportMode::Pending = mode {
+        enqueue_confirmation_emails(transaction, &subscriber_ids).await?;
+    }
+    Ok(subscriber_ids)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_csv;
+    use claim::{assert_err, assert_ok};
+
+    #[test]
+    fn rows_are_sorted_into_accepted_duplicate_and_rejected() {
+        let csv = "Name,Email,Status\n\
+                   Ursula,ursula@example.com,confirmed\n\
+                   Octavia,not-an-email,confirmed\n\
+                   ,iain@example.com,confirmed\n\
+                   Ursula again,URSULA@example.com,confirmed\n";
+
+        let report = assert_ok!(parse_csv(csv));
+
+        let lines = |rows: &[super::SkippedRow]| rows.iter().map(|r| r.line).collect::<Vec<_>>();
+        assert_eq!(report.accepted.len(), 1);
+        assert_eq!(report.accepted[0].line, 2);
+        assert_eq!(lines(&report.rejected), vec![3, 4]);
+        assert_eq!(lines(&report.duplicates), vec![5]);
+    }
+
+    #[test]
+    fn missing_columns_are_rejected() {
+        assert_err!(parse_csv("email\nursula@example.com\n"));
+    }
+
+    #[test]
+    fn files_with_too_many_rows_are_rejected() {
+        let mut csv = "email,name\n".to_string();
+        for i in 0..=super::MAX_IMPORT_ROWS {
+            csv.push_str(&format!("reader{}@example.com,Reader\n", i));
+        }
+        assert_err!(parse_csv(&csv));
+    }
+
+    #[test]
+    fn a_file_without_rows_is_rejected() {
+        assert_err!(parse_csv("email,name\n"));
+    }
+}
@@ -0,0 +1,43 @@
+use crate::configuration::Settings;
+use crate::startup::get_connection_pool;
+use anyhow::Context;
+use sqlx::PgPool;
+use std::time::Duration;
+use tracing::{field::display, Span};
+
+// Expired tokens are kept around for a while so that a late click on the
+// confirmation link can still offer to send a new one.
+#[tracing::instrument(
+    skip_all,
+    fields(n_deleted_tokens=tracing::field::Empty),
+    err(Debug)
+)]
+pub async fn sweep_subscription_tokens(pool: &PgPool) -> Result<u64, anyhow::Error> {
+    let n_deleted_tokens = sqlx::query!(
+        r#"
+        DELETE FROM subscription_tokens
+        WHERE
+            used_at IS NOT NULL OR
+            expires_at < now() - interval '7 days'
+        "#,
+    )
+    .execute(pool)
+    .await
+    .context("Failed to delete stale subscription tokens.")?
+    .rows_affected();
+    Span::current().record("n_deleted_tokens", &display(n_deleted_tokens));
+    Ok(n_deleted_tokens)
+}
+
+async fn sweeper_loop(pool: PgPool) -> Result<(), anyhow::Error> {
+    loop {
+        // Failures are already logged, the next sweep will pick up the leftovers.
+        let _ = sweep_subscription_tokens(&pool).await;
+        tokio::time::sleep(Duration::from_secs(60 * 60)).await;
+    }
+}
+
+pub async fn run_sweeper_until_stopped(configuration: Settings) -> Result<(), anyhow::Error> {
+    let connection_pool = get_connection_pool(&configuration.database);
+    sweeper_loop(connection_pool).await
+}
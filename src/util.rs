@@ -1,7 +1,10 @@
+use crate::startup::TrustedProxies;
 use actix_web::http::header::LOCATION;
-use actix_web::HttpResponse;
+use actix_web::{web, HttpRequest, HttpResponse};
 use anyhow::Context;
+use chrono::{DateTime, NaiveDateTime, Utc};
 use sqlx::PgPool;
+use std::net::IpAddr;
 use tokio::task::JoinHandle;
 use uuid::Uuid;
 
@@ -31,6 +34,27 @@ impl AsRef<str> for NonEmptyString {
     }
 }
 
+// Accepts the value of an HTML `datetime-local` input (interpreted as UTC) or an RFC 3339 timestamp.
+pub fn parse_schedule(value: &str) -> Result<DateTime<Utc>, anyhow::Error> {
+    let scheduled_for = DateTime::parse_from_rfc3339(value)
+        .map(|t| t.with_timezone(&Utc))
+        .or_else(|_| {
+            NaiveDateTime::parse_from_str(value, "%Y-%m-%dT%H:%M")
+                .map(|t| DateTime::<Utc>::from_utc(t, Utc))
+        })
+        .map_err(|_| anyhow::anyhow!("{} is not a valid date and time", value))?;
+    if scheduled_for <= Utc::now() {
+        anyhow::bail!("The scheduled time must be in the future");
+    }
+    Ok(scheduled_for)
+}
+
+// Unlike `web::Form`, repeated keys (e.g. a group of checkboxes) are collected into a `Vec`.
+// Errors read the same as the ones `web::Form` reports.
+pub fn parse_form<T: serde::de::DeserializeOwned>(body: &[u8]) -> Result<T, String> {
+    serde_html_form::from_bytes(body).map_err(|e| format!("Parse error: {}.", e))
+}
+
 #[tracing::instrument(name = "Get username", skip(pool))]
 pub async fn get_username(user_id: Uuid, pool: &PgPool) -> Result<String, anyhow::Error> {
     let row = sqlx::query!(
@@ -74,12 +98,52 @@ where
     actix_web::error::ErrorBadRequest(e)
 }
 
+pub fn e403<T>(e: T) -> actix_web::Error
+where
+    T: std::fmt::Debug + std::fmt::Display + 'static,
+{
+    actix_web::error::ErrorForbidden(e)
+}
+
 pub fn see_other(path: &str) -> HttpResponse {
     HttpResponse::SeeOther()
         .insert_header((LOCATION, path))
         .finish()
 }
 
+// The address of the client the request came from. `X-Forwarded-For` is only read when the
+// peer is one of the configured trusted proxies: anyone else can set it, and a new value
+// for each request would escape the per-address limits. The header is read from the right,
+// since proxies append to it, and the first address that is not a trusted proxy is the
+// client.
+pub fn client_ip(request: &HttpRequest) -> Option<String> {
+    let peer = request.peer_addr()?.ip();
+    let trusted_proxies = match request.app_data::<web::Data<TrustedProxies>>() {
+        Some(trusted_proxies) if trusted_proxies.0.contains(&peer) => &trusted_proxies.0,
+        _ => return Some(peer.to_string()),
+    };
+    let forwarded_for = request
+        .headers()
+        .get_all("X-Forwarded-For")
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect::<Vec<_>>();
+    let mut client = peer;
+    for hop in forwarded_for.into_iter().rev() {
+        match hop.parse::<IpAddr>() {
+            Ok(hop) if trusted_proxies.contains(&client) => client = hop,
+            _ => break,
+        }
+    }
+    Some(client.to_string())
+}
+
+// Rounded up, so that a client retrying after the advertised delay is never turned away.
+pub fn retry_after_seconds(retry_after: &std::time::Duration) -> u64 {
+    retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0)
+}
+
 pub fn spawn_blocking_with_tracing<F, R>(f: F) -> JoinHandle<R>
 where
     F: FnOnce() -> R + Send + 'static,
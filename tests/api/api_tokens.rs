@@ -0,0 +1,258 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+    })
+}
+
+// Goes through the admin page, as the token is only ever shown there.
+async fn create_api_token(app: &TestApp) -> String {
+    let html_page = app
+        .post("/admin/api_tokens", &serde_json::json!({ "name": "CMS" }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let start = html_page.find("<code id=\"api-token\">").unwrap() + 21;
+    let end = start + html_page[start..].find("</code>").unwrap();
+    html_page[start..end].to_string()
+}
+
+async fn post_api_newsletter(
+    app: &TestApp,
+    token: Option<&str>,
+    idempotency_key: Option<&str>,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}/api/v1/newsletters", &app.address))
+        .json(body);
+    if let Some(token) = token {
+        request = request.bearer_auth(token);
+    }
+    if let Some(idempotency_key) = idempotency_key {
+        request = request.header("Idempotency-Key", idempotency_key);
+    }
+    request.send().await.expect("Failed to execute request.")
+}
+
+async fn count_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_new_token_is_shown_once_and_stored_hashed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let token = create_api_token(&app).await;
+
+    // Assert
+    assert!(token.starts_with("nl_"));
+    let html_page = app.get("/admin/api_tokens").await.text().await.unwrap();
+    assert!(html_page.contains("CMS"));
+    assert!(html_page.contains(&token[..9]));
+    assert!(!html_page.contains(&token));
+    let stored = sqlx::query!("SELECT token_hash FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_ne!(stored.token_hash, token);
+}
+
+#[tokio::test]
+async fn requests_without_a_valid_token_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    for token in [None, Some("nl_not-a-real-token")] {
+        // Act
+        let response = post_api_newsletter(&app, token, None, &newsletter_body()).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 401);
+        assert_eq!(response.headers()["WWW-Authenticate"], "Bearer");
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["code"], "unauthorized");
+    }
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_valid_token_publishes_a_newsletter_issue() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let token = create_api_token(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_api_newsletter(&app, Some(&token), None, &newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "published");
+    assert!(body["newsletter_issue_id"].as_str().is_some());
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get("/admin/api_tokens").await.text().await.unwrap();
+    assert!(!html_page.contains("Never"));
+}
+
+#[tokio::test]
+async fn an_issue_can_be_scheduled_through_the_api() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let token = create_api_token(&app).await;
+    let mut body = newsletter_body();
+    body["scheduled_for"] = "2999-01-01T10:00:00Z".into();
+
+    // Act
+    let response = post_api_newsletter(&app, Some(&token), None, &body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 202);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "scheduled");
+    assert_eq!(body["scheduled_for"], "2999-01-01T10:00:00+00:00");
+}
+
+#[tokio::test]
+async fn the_idempotency_key_header_prevents_duplicate_issues() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let token = create_api_token(&app).await;
+    let idempotency_key = Uuid::new_v4().to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first = post_api_newsletter(
+        &app,
+        Some(&token),
+        Some(&idempotency_key),
+        &newsletter_body(),
+    )
+    .await;
+    let second = post_api_newsletter(
+        &app,
+        Some(&token),
+        Some(&idempotency_key),
+        &newsletter_body(),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 202);
+    assert_eq!(second.status().as_u16(), 202);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+    assert_eq!(count_issues(&app).await, 1);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn invalid_requests_get_a_json_error_naming_the_field() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let token = create_api_token(&app).await;
+    let mut empty_title = newsletter_body();
+    empty_title["title"] = "".into();
+    let mut past_schedule = newsletter_body();
+    past_schedule["scheduled_for"] = "2001-01-01T10:00:00Z".into();
+    let mut unknown_list = newsletter_body();
+    unknown_list["lists"] = serde_json::json!(["does-not-exist"]);
+    let mut unknown_segment = newsletter_body();
+    unknown_segment["segment"] = "does-not-exist".into();
+    let test_cases = vec![
+        (empty_title, Some("title")),
+        (past_schedule, Some("scheduled_for")),
+        (unknown_list, Some("lists")),
+        (unknown_segment, Some("segment")),
+        (serde_json::json!({ "title": "Missing bodies" }), None),
+    ];
+
+    for (body, field) in test_cases {
+        // Act
+        let response = post_api_newsletter(&app, Some(&token), None, &body).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["error"]["code"], "validation");
+        assert_eq!(error["error"]["field"].as_str(), field);
+    }
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn tokens_only_carry_the_permissions_of_their_owner() {
+    // Arrange
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+    app.login_as(&editor).await;
+    let token = create_api_token(&app).await;
+
+    // Act
+    let response = post_api_newsletter(&app, Some(&token), None, &newsletter_body()).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["code"], "forbidden");
+    assert_eq!(count_issues(&app).await, 0);
+}
+
+#[tokio::test]
+async fn a_revoked_token_stops_working() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let token = create_api_token(&app).await;
+    let api_token_id = sqlx::query!("SELECT api_token_id FROM api_tokens")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .api_token_id;
+
+    // Act
+    let response = app
+        .post(
+            &format!("/admin/api_tokens/{}/revoke", api_token_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/api_tokens");
+    let html_page = app.get("/admin/api_tokens").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The API token has been revoked.</i></p>"));
+    let response = post_api_newsletter(&app, Some(&token), None, &newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 401);
+}
@@ -0,0 +1,221 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use claim::{assert_err, assert_matches, assert_ok};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, Request, ResponseTemplate};
+use zero2prod::issue_delivery_worker::{ExecutionError, ExecutionOutcome};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+async fn subscriber_emails(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT email FROM subscriptions ORDER BY email",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.email)
+        .collect()
+}
+
+async fn delivery_outcomes(app: &TestApp) -> Vec<(String, String)> {
+    sqlx::query!(
+        "SELECT subscriber_email, outcome FROM issue_delivery_outcomes ORDER BY subscriber_email",
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.subscriber_email, r.outcome))
+    .collect()
+}
+
+// Answers a batch request with one result per message, failing the ones sent to `bad_recipient`
+// with the given Postmark error code.
+fn batch_responder(bad_recipient: Option<(String, i64)>) -> impl Fn(&Request) -> ResponseTemplate {
+    move |request: &Request| {
+        let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+        let results = body
+            .iter()
+            .map(|message| match &bad_recipient {
+                Some((email, error_code)) if message["To"].as_str() == Some(email) => {
+                    serde_json::json!({"ErrorCode": error_code, "Message": "Rejected."})
+                }
+                _ => serde_json::json!({"ErrorCode": 0, "Message": "OK"}),
+            })
+            .collect::<Vec<_>>();
+        ResponseTemplate::new(200).set_body_json(results)
+    }
+}
+
+#[tokio::test]
+async fn a_batch_delivers_the_issue_with_a_single_request() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        app.create_confirmed_subscriber().await;
+    }
+    app.login().await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_responder(None))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_ok!(outcome), ExecutionOutcome::TaskCompleted);
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_ok!(outcome), ExecutionOutcome::EmptyQueue);
+
+    let outcomes = delivery_outcomes(&app).await;
+    assert_eq!(outcomes.len(), 3);
+    assert!(outcomes.iter().all(|(_, outcome)| outcome == "delivered"));
+    let request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: Vec<serde_json::Value> = serde_json::from_slice(&request.body).unwrap();
+    assert_eq!(body.len(), 3);
+    assert!(body[0]["Headers"][0]["Value"]
+        .as_str()
+        .unwrap()
+        .contains("/subscriptions/unsubscribe?token="));
+}
+
+#[tokio::test]
+async fn a_batch_takes_at_most_batch_size_deliveries() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        app.create_confirmed_subscriber().await;
+    }
+    app.login().await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_responder(None))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    assert_ok!(app.execute_next_batch(2).await);
+    assert_eq!(delivery_outcomes(&app).await.len(), 2);
+    assert_ok!(app.execute_next_batch(2).await);
+    assert_eq!(delivery_outcomes(&app).await.len(), 3);
+}
+
+#[tokio::test]
+async fn a_rejected_address_does_not_fail_the_rest_of_the_batch() {
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        app.create_confirmed_subscriber().await;
+    }
+    app.login().await;
+    publish_newsletter(&app).await;
+    let emails = subscriber_emails(&app).await;
+    let bad_recipient = emails[1].clone();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        // Postmark asks to retry later when a message exceeds the rate limit.
+        .respond_with(batch_responder(Some((bad_recipient.clone(), 429))))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_ok!(outcome), ExecutionOutcome::TaskCompleted);
+
+    let outcomes = delivery_outcomes(&app).await;
+    assert_eq!(
+        outcomes,
+        vec![
+            (emails[0].clone(), "delivered".to_string()),
+            (emails[2].clone(), "delivered".to_string()),
+        ]
+    );
+    let queued = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_queue",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(queued.subscriber_email, bad_recipient);
+    assert_eq!(queued.n_retries, 1);
+}
+
+#[tokio::test]
+async fn inactive_recipients_are_moved_to_failures_without_retries() {
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        app.create_confirmed_subscriber().await;
+    }
+    app.login().await;
+    publish_newsletter(&app).await;
+    let emails = subscriber_emails(&app).await;
+    let bad_recipient = emails[1].clone();
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(batch_responder(Some((bad_recipient.clone(), 406))))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_ok!(outcome), ExecutionOutcome::TaskCompleted);
+
+    assert_eq!(
+        delivery_outcomes(&app).await,
+        vec![
+            (emails[0].clone(), "delivered".to_string()),
+            (bad_recipient.clone(), "failed".to_string()),
+        ]
+    );
+    let failure = sqlx::query!("SELECT subscriber_email, n_retries FROM issue_delivery_failures",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.subscriber_email, bad_recipient);
+    assert_eq!(failure.n_retries, 0);
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_ok!(outcome), ExecutionOutcome::EmptyQueue);
+}
+
+#[tokio::test]
+async fn a_failed_batch_schedules_a_retry_for_every_delivery() {
+    let app = spawn_app().await;
+    for _ in 0..2 {
+        app.create_confirmed_subscriber().await;
+    }
+    app.login().await;
+    publish_newsletter(&app).await;
+
+    Mock::given(path("/email/batch"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_batch(10).await;
+    assert_matches!(assert_err!(outcome), ExecutionError::Transient(_));
+
+    assert!(delivery_outcomes(&app).await.is_empty());
+    let n_retries = sqlx::query!("SELECT n_retries FROM issue_delivery_queue",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(n_retries.len(), 2);
+    assert!(n_retries.iter().all(|r| r.n_retries == 1));
+}
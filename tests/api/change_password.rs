@@ -115,4 +115,20 @@ async fn redirect_to_login_after_successful_pass_change() {
     // 4 - try to open admin dashboard
     let response = app.get_admin_dashboard().await;
     assert_is_redirect_to(&response, "/login");
+
+    // 5 - the new password is the one that works from now on
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &new_password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
 }
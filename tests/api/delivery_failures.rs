@@ -0,0 +1,198 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use claim::assert_matches;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::{ExecutionError, ExecutionOutcome};
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+async fn exhaust_retry_budget(app: &TestApp) {
+    sqlx::query!(
+        "UPDATE issue_delivery_queue SET n_retries = $1, execute_after = now()",
+        app.delivery_worker.max_retries
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_delivery_failures() {
+    let app = spawn_app().await;
+    let response = app.get("/admin/delivery_failures").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn failed_deliveries_are_retried_later() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    publish_newsletter(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_task().await;
+    assert_matches!(outcome, Err(ExecutionError::Transient(_)));
+
+    // The task is kept in the queue but is not due yet.
+    let task = sqlx::query!("SELECT n_retries, execute_after FROM issue_delivery_queue",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(task.n_retries, 1);
+    assert!(task.execute_after > chrono::Utc::now());
+    assert_matches!(
+        app.execute_next_task().await,
+        Ok(ExecutionOutcome::EmptyQueue)
+    );
+}
+
+#[tokio::test]
+async fn deliveries_exceeding_the_retry_budget_are_moved_to_failures() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    publish_newsletter(&app).await;
+    exhaust_retry_budget(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let outcome = app.execute_next_task().await;
+    assert_matches!(outcome, Err(ExecutionError::Fatal(_)));
+
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+    let failure = sqlx::query!("SELECT n_retries FROM issue_delivery_failures",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(failure.n_retries, app.delivery_worker.max_retries);
+}
+
+#[tokio::test]
+async fn failed_deliveries_can_be_requeued_by_the_admin() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    publish_newsletter(&app).await;
+    exhaust_retry_budget(&app).await;
+    let failing_mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let _ = app.execute_next_task().await;
+    drop(failing_mock_guard);
+
+    // Part 1 - The failure is listed
+    let failure =
+        sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_failures",)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains(&failure.subscriber_email));
+
+    // Part 2 - Re-queue it
+    let response = app
+        .post(
+            "/admin/delivery_failures/requeue",
+            &serde_json::json!({
+                "newsletter_issue_id": failure.newsletter_issue_id,
+                "subscriber_email": failure.subscriber_email,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/delivery_failures");
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("<p><i>1 failed deliveries have been re-queued.</i></p>"));
+
+    // Part 3 - It is delivered with a fresh retry budget
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn deliveries_to_unsubscribed_addresses_are_not_requeued() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    publish_newsletter(&app).await;
+    exhaust_retry_budget(&app).await;
+    let failing_mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let _ = app.execute_next_task().await;
+    drop(failing_mock_guard);
+    let failure =
+        sqlx::query!("SELECT newsletter_issue_id, subscriber_email FROM issue_delivery_failures",)
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+    sqlx::query!("UPDATE subscriptions SET status = 'unsubscribed'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post(
+            "/admin/delivery_failures/requeue",
+            &serde_json::json!({
+                "newsletter_issue_id": failure.newsletter_issue_id,
+                "subscriber_email": failure.subscriber_email,
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/delivery_failures");
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("The delivery was not re-queued"));
+    let response = app
+        .post(
+            "/admin/delivery_failures/requeue_all",
+            &serde_json::json!({}),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/delivery_failures");
+
+    // The failure stays listed and nothing is left in the queue.
+    let html_page = app.get_delivery_failures_html().await;
+    assert!(html_page.contains("0 failed deliveries have been re-queued."));
+    assert!(html_page.contains(&failure.subscriber_email));
+    let n_queued = sqlx::query!("SELECT COUNT(*) AS \"count!\" FROM issue_delivery_queue",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
@@ -0,0 +1,322 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn save_draft(app: &TestApp) -> uuid::Uuid {
+    let draft_request_body = serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    });
+    let response = app.post("/admin/drafts", &draft_request_body).await;
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+    assert_is_redirect_to(&response, &format!("/admin/drafts/{}", newsletter_issue_id));
+    newsletter_issue_id
+}
+
+async fn set_admin_email(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post("/admin/email", &serde_json::json!({ "email": email }))
+        .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_drafts() {
+    let app = spawn_app().await;
+
+    let response = app.get("/admin/drafts").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post(
+            "/admin/drafts",
+            &serde_json::json!({
+                "title": "Draft title",
+                "text_content": "Draft body as plain text",
+                "html_content": "<p>Draft body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn drafts_are_not_delivered_to_subscribers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let issue_id = save_draft(&app).await;
+    app.dispatch_all_pending_emails().await;
+
+    let html_page = app.get("/admin/drafts").await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/drafts/{}">Draft title</a>"#,
+        issue_id
+    )));
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(!html_page.contains("Draft title"));
+}
+
+#[tokio::test]
+async fn drafts_can_be_edited_and_previewed() {
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+
+    let response = app
+        .post(
+            &draft_page,
+            &serde_json::json!({
+                "title": "Updated title",
+                "text_content": "Updated body as plain text",
+                "html_content": "<p>Updated body as HTML</p>",
+            }),
+        )
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app.get(&draft_page).await.text().await.unwrap();
+    assert!(html_page.contains("The draft has been saved."));
+    assert!(html_page.contains(r#"value="Updated title""#));
+
+    let response = app.get(&format!("{}/preview/html", draft_page)).await;
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "text/html; charset=utf-8"
+    );
+    assert_eq!(
+        response.text().await.unwrap(),
+        "<p>Updated body as HTML</p>"
+    );
+
+    let response = app.get(&format!("{}/preview/text", draft_page)).await;
+    assert_eq!(
+        response.headers()["Content-Type"].to_str().unwrap(),
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(response.text().await.unwrap(), "Updated body as plain text");
+}
+
+#[tokio::test]
+async fn previews_fill_in_the_variables_like_a_test_email() {
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+    app.post(
+        &draft_page,
+        &serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Hello {{ name }}, leave at {{ unsubscribe_url }}",
+            "html_content": "<p>Hello {{ name }}</p>",
+        }),
+    )
+    .await;
+
+    let html = app
+        .get(&format!("{}/preview/html", draft_page))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let text = app
+        .get(&format!("{}/preview/text", draft_page))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    assert_eq!(html, format!("<p>Hello {}</p>", app.test_user.username));
+    assert_eq!(
+        text,
+        format!("Hello {}, leave at #", app.test_user.username)
+    );
+}
+
+#[tokio::test]
+async fn html_previews_are_sandboxed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+
+    // Act
+    let response = app
+        .get(&format!("/admin/drafts/{}/preview/html", issue_id))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Security-Policy"]
+            .to_str()
+            .unwrap(),
+        "sandbox; default-src 'none'; img-src * data:; style-src 'unsafe-inline'"
+    );
+    assert_eq!(response.headers()["X-Content-Type-Options"], "nosniff");
+}
+
+#[tokio::test]
+async fn viewers_cannot_preview_drafts() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    app.logout().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let response = app
+        .get(&format!("/admin/drafts/{}/preview/html", issue_id))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn a_test_email_is_sent_only_to_the_logged_in_admin() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+
+    let response = set_admin_email(&app, "admin@example.com").await;
+    assert_is_redirect_to(&response, "/admin/email");
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post(&format!("{}/test", draft_page), &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app.get(&draft_page).await.text().await.unwrap();
+    assert!(html_page.contains("A test email has been sent to admin@example.com."));
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert_eq!(body["To"], "admin@example.com");
+    assert_eq!(body["Subject"], "[TEST] Draft title");
+
+    // Sending a test does not publish the draft.
+    let n_queued = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM issue_delivery_queue"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn a_test_email_requires_an_admin_email_address() {
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post(&format!("{}/test", draft_page), &serde_json::json!({}))
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app.get(&draft_page).await.text().await.unwrap();
+    assert!(html_page.contains("Set your email address before sending a test."));
+}
+
+#[tokio::test]
+async fn invalid_admin_email_addresses_are_rejected() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = set_admin_email(&app, "not-an-email").await;
+    assert_is_redirect_to(&response, "/admin/email");
+    let html_page = app.get("/admin/email").await.text().await.unwrap();
+    assert!(html_page.contains("not-an-email is not a valid subscriber email."));
+}
+
+#[tokio::test]
+async fn published_drafts_are_delivered_to_confirmed_subscribers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let publish_request_body = serde_json::json!({
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app
+        .post(&format!("{}/publish", draft_page), &publish_request_body)
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains("The newsletter issue has been accepted"));
+    app.dispatch_all_pending_emails().await;
+
+    // A published draft is no longer editable.
+    let response = app.get(&draft_page).await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn drafts_with_unknown_variables_cannot_be_published() {
+    let app = spawn_app().await;
+    app.login().await;
+    let issue_id = save_draft(&app).await;
+    let draft_page = format!("/admin/drafts/{}", issue_id);
+    app.post(
+        &draft_page,
+        &serde_json::json!({
+            "title": "Draft title",
+            "text_content": "Hi {{ nickname }}",
+            "html_content": "<p>Draft body as HTML</p>",
+        }),
+    )
+    .await;
+
+    let publish_request_body = serde_json::json!({
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app
+        .post(&format!("{}/publish", draft_page), &publish_request_body)
+        .await;
+    assert_is_redirect_to(&response, &draft_page);
+    let html_page = app.get(&draft_page).await.text().await.unwrap();
+    assert!(html_page.contains("Plain text body: `{{ nickname }}` is not a known variable."));
+}
@@ -6,14 +6,22 @@ use fake::faker::name::en::Name;
 use fake::Fake;
 use once_cell::sync::Lazy;
 use reqwest::{Response, Url};
+use secrecy::Secret;
 use sqlx::{Connection, Executor, PgConnection, PgPool};
+use std::net::{IpAddr, Ipv4Addr};
 use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::MockServer;
 use wiremock::{Mock, ResponseTemplate};
-use zero2prod::configuration::{get_configuration, DatabaseSettings};
+use zero2prod::configuration::{
+    get_configuration, DatabaseSettings, DeliveryWorkerSettings, EmailTransportKind,
+};
+use zero2prod::confirmation_email_worker::try_send_confirmation_email;
 use zero2prod::email_client::EmailClient;
-use zero2prod::issue_delivery_worker::{try_execute_task, ExecutionOutcome};
+use zero2prod::issue_delivery_worker::{
+    try_execute_batch, try_execute_task, ExecutionError, ExecutionOutcome,
+};
+use zero2prod::password_reset_email_worker::try_send_password_reset_email;
 use zero2prod::startup::{get_connection_pool, Application};
 use zero2prod::telemetry::{get_subscriber, init_subscriber};
 
@@ -21,16 +29,21 @@ pub struct TestUser {
     pub user_id: Uuid,
     pub username: String,
     pub password: String,
+    pub role: String,
 }
 impl TestUser {
     pub fn generate() -> Self {
+        Self::with_role("owner")
+    }
+    pub fn with_role(role: &str) -> Self {
         Self {
             user_id: Uuid::new_v4(),
             username: Uuid::new_v4().to_string(),
             password: Uuid::new_v4().to_string(),
+            role: role.into(),
         }
     }
-    async fn store(&self, pool: &PgPool) {
+    pub async fn store(&self, pool: &PgPool) {
         let salt = SaltString::generate(&mut rand::thread_rng());
         let password_hash = Argon2::new(
             Algorithm::Argon2id,
@@ -42,11 +55,12 @@ impl TestUser {
         .to_string();
 
         sqlx::query!(
-            "INSERT INTO users (user_id, username, password_hash)
-            VALUES ($1, $2, $3)",
+            "INSERT INTO users (user_id, username, password_hash, role)
+            VALUES ($1, $2, $3, $4)",
             self.user_id,
             self.username,
             password_hash,
+            self.role,
         )
         .execute(pool)
         .await
@@ -62,6 +76,10 @@ pub struct TestApp {
     pub test_user: TestUser,
     pub api_client: reqwest::Client,
     pub email_client: EmailClient,
+    pub base_url: Url,
+    pub hmac_secret: Secret<String>,
+    pub postmark_webhook_secret: Secret<String>,
+    pub delivery_worker: DeliveryWorkerSettings,
 }
 
 pub struct ConfirmationLinks {
@@ -80,6 +98,15 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_subscriptions_json(&self, body: &serde_json::Value) -> reqwest::Response {
+        self.api_client
+            .post(format!("{}/subscriptions", &self.address))
+            .json(body)
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -103,6 +130,25 @@ impl TestApp {
         ConfirmationLinks { html, plain_text }
     }
 
+    pub fn get_unsubscribe_link(&self, email_request: &wiremock::Request) -> Url {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let header = body["Headers"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|h| h["Name"] == "List-Unsubscribe")
+            .unwrap();
+        let raw_link = header["Value"]
+            .as_str()
+            .unwrap()
+            .trim_start_matches('<')
+            .trim_end_matches('>');
+        let mut unsubscribe_link = Url::parse(raw_link).unwrap();
+        assert_eq!(unsubscribe_link.host_str().unwrap(), "127.0.0.1");
+        unsubscribe_link.set_port(Some(self.port)).unwrap();
+        unsubscribe_link
+    }
+
     pub async fn post_newsletter<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -167,6 +213,21 @@ impl TestApp {
             .expect("Failed to execute request.")
     }
 
+    pub async fn post_postmark_webhook(
+        &self,
+        body: &serde_json::Value,
+        secret: Option<&str>,
+    ) -> reqwest::Response {
+        let mut request = self
+            .api_client
+            .post(format!("{}/webhooks/postmark", &self.address))
+            .json(body);
+        if let Some(secret) = secret {
+            request = request.header("X-Postmark-Webhook-Secret", secret);
+        }
+        request.send().await.expect("Failed to execute request.")
+    }
+
     pub async fn post_login<Body>(&self, body: &Body) -> reqwest::Response
     where
         Body: serde::Serialize,
@@ -181,7 +242,7 @@ impl TestApp {
         self.post("/admin/password", body).await
     }
 
-    async fn get(&self, path: &str) -> Response {
+    pub async fn get(&self, path: &str) -> Response {
         self.api_client
             .get(&format!("{}{}", &self.address, path))
             .send()
@@ -202,9 +263,13 @@ impl TestApp {
     }
 
     pub async fn login(&self) -> Response {
+        self.login_as(&self.test_user).await
+    }
+
+    pub async fn login_as(&self, user: &TestUser) -> Response {
         let login_body = serde_json::json!({
-            "username": &self.test_user.username,
-            "password": &self.test_user.password
+            "username": &user.username,
+            "password": &user.password
         });
         let response = self.post_login(&login_body).await;
         assert_is_redirect_to(&response, "/admin/dashboard");
@@ -231,17 +296,79 @@ impl TestApp {
         self.get_newsletter_form().await.text().await.unwrap()
     }
 
+    pub async fn execute_next_task(&self) -> Result<ExecutionOutcome, ExecutionError> {
+        try_execute_task(
+            &self.db_pool,
+            &self.email_client,
+            &self.base_url,
+            &self.hmac_secret,
+            &self.delivery_worker,
+        )
+        .await
+    }
+
+    pub async fn execute_next_batch(
+        &self,
+        batch_size: u16,
+    ) -> Result<ExecutionOutcome, ExecutionError> {
+        try_execute_batch(
+            &self.db_pool,
+            &self.email_client,
+            &self.base_url,
+            &self.hmac_secret,
+            &self.delivery_worker,
+            batch_size,
+        )
+        .await
+    }
+
     pub async fn dispatch_all_pending_emails(&self) {
         loop {
-            if let ExecutionOutcome::EmptyQueue =
-                try_execute_task(&self.db_pool, &self.email_client)
-                    .await
-                    .unwrap()
-            {
+            if let ExecutionOutcome::EmptyQueue = self.execute_next_task().await.unwrap() {
                 break;
             }
         }
     }
+
+    pub async fn send_queued_confirmation_emails(&self) {
+        loop {
+            let outcome = try_send_confirmation_email(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.delivery_worker,
+            )
+            .await
+            .unwrap();
+            if let ExecutionOutcome::EmptyQueue = outcome {
+                break;
+            }
+        }
+    }
+
+    pub async fn send_queued_password_reset_emails(&self) {
+        loop {
+            let outcome = try_send_password_reset_email(
+                &self.db_pool,
+                &self.email_client,
+                &self.base_url,
+                &self.delivery_worker,
+            )
+            .await
+            .unwrap();
+            if let ExecutionOutcome::EmptyQueue = outcome {
+                break;
+            }
+        }
+    }
+
+    pub async fn get_delivery_failures_html(&self) -> String {
+        self.get("/admin/delivery_failures")
+            .await
+            .text()
+            .await
+            .unwrap()
+    }
 }
 
 static TRACING: Lazy<()> = Lazy::new(|| {
@@ -258,6 +385,15 @@ static TRACING: Lazy<()> = Lazy::new(|| {
 });
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with_trusted_proxies(Vec::new()).await
+}
+
+// The application believes the `X-Forwarded-For` header of requests coming from `proxy`.
+pub async fn spawn_app_behind_proxy(proxy: IpAddr) -> TestApp {
+    spawn_app_with_trusted_proxies(vec![proxy]).await
+}
+
+async fn spawn_app_with_trusted_proxies(trusted_proxies: Vec<IpAddr>) -> TestApp {
     Lazy::force(&TRACING);
 
     let email_server = MockServer::start().await;
@@ -265,7 +401,11 @@ pub async fn spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Failed to read configuration.");
         c.database.database_name = Uuid::new_v4().to_string();
         c.application.port = 0;
+        c.email_client.transport = EmailTransportKind::Postmark;
         c.email_client.base_url = email_server.uri();
+        // Keep the address lockout within reach of a test without hashing fifty passwords.
+        c.login_throttle.max_failures_per_ip = 10;
+        c.application.trusted_proxies = trusted_proxies;
         c
     };
 
@@ -277,11 +417,9 @@ pub async fn spawn_app() -> TestApp {
     let address = format!("http://localhost:{}", application.port());
     let application_port = application.port();
     let _ = tokio::spawn(application.run_until_stopped());
-    let client = reqwest::Client::builder()
-        .redirect(reqwest::redirect::Policy::none())
-        .cookie_store(true)
-        .build()
-        .unwrap();
+    // Login throttling keys on the client address and Redis is shared between tests, so
+    // every test app connects from its own loopback address.
+    let client = client_from(random_loopback_address());
 
     let test_app = TestApp {
         address,
@@ -291,11 +429,30 @@ pub async fn spawn_app() -> TestApp {
         test_user: TestUser::generate(),
         api_client: client,
         email_client: configuration.email_client.client(),
+        base_url: configuration.application.base_url().unwrap(),
+        hmac_secret: configuration.application.hmac_secret,
+        postmark_webhook_secret: configuration.application.postmark_webhook_secret,
+        delivery_worker: configuration.delivery_worker,
     };
     test_app.test_user.store(&test_app.db_pool).await;
     test_app
 }
 
+pub fn random_loopback_address() -> IpAddr {
+    let [a, b, c]: [u8; 3] = rand::random();
+    IpAddr::V4(Ipv4Addr::new(127, a, b, c % 254 + 1))
+}
+
+// A client without redirects that connects from the given local address.
+pub fn client_from(local_address: IpAddr) -> reqwest::Client {
+    reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .cookie_store(true)
+        .local_address(local_address)
+        .build()
+        .unwrap()
+}
+
 async fn configure_database(config: &DatabaseSettings) -> PgPool {
     // Create database
     let mut connection = PgConnection::connect_with(&config.without_db())
@@ -0,0 +1,197 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn invite(app: &TestApp, email: &str, role: &str) -> reqwest::Response {
+    app.post(
+        "/admin/users/invitations",
+        &serde_json::json!({ "email": email, "role": role }),
+    )
+    .await
+}
+
+// Sends an invitation as the test user and returns the path of the link found in the email.
+async fn invitation_link(app: &TestApp, email: &str, role: &str) -> String {
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+    let response = invite(app, email, role).await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request)
+        .html
+        .path()
+        .to_string()
+}
+
+fn new_account(username: &str, password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "username": username,
+        "password": password,
+        "confirm_password": password,
+    })
+}
+
+#[tokio::test]
+async fn only_owners_can_invite_users() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    let publisher = TestUser::with_role("publisher");
+    publisher.store(&app.db_pool).await;
+    app.login_as(&publisher).await;
+
+    let response = invite(&app, "new.user@example.com", "editor").await;
+
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn an_invitee_can_set_up_their_account_with_the_invited_role() {
+    let app = spawn_app().await;
+    let link = invitation_link(&app, "new.user@example.com", "publisher").await;
+
+    let html = app.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains("An invitation has been sent to new.user@example.com."));
+    assert!(html.contains("Pending invitations"));
+    app.logout().await;
+
+    let html = app.get(&link).await.text().await.unwrap();
+    assert!(html.contains("new.user@example.com has been invited to join as publisher."));
+
+    let response = app
+        .post(&link, &new_account("newcomer", "a-long-enough-password"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html = app.get_admin_dashboard_html().await;
+    assert!(html.contains("Welcome newcomer! You are signed in as publisher."));
+
+    // The chosen credentials work for later logins
+    app.logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": "newcomer",
+            "password": "a-long-enough-password",
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let saved = sqlx::query!("SELECT email, role FROM users WHERE username = 'newcomer'")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email.as_deref(), Some("new.user@example.com"));
+    assert_eq!(saved.role, "publisher");
+}
+
+#[tokio::test]
+async fn an_invitation_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let link = invitation_link(&app, "new.user@example.com", "editor").await;
+    app.logout().await;
+
+    let response = app
+        .post(&link, &new_account("newcomer", "a-long-enough-password"))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+
+    let response = app
+        .post(&link, &new_account("someone", "a-long-enough-password"))
+        .await;
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app.get(&link).await;
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn expired_invitations_are_rejected() {
+    let app = spawn_app().await;
+    let link = invitation_link(&app, "new.user@example.com", "editor").await;
+    sqlx::query!("UPDATE user_invitations SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get(&link).await;
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app
+        .post(&link, &new_account("newcomer", "a-long-enough-password"))
+        .await;
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn invalid_account_details_are_reported_back() {
+    let app = spawn_app().await;
+    let link = invitation_link(&app, "new.user@example.com", "editor").await;
+    app.logout().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({
+                "username": "newcomer",
+                "password": "a-long-enough-password",
+                "confirm_password": "another-long-password",
+            }),
+            "New password does not match with confirmation password.",
+        ),
+        (
+            new_account("newcomer", "short"),
+            "New password must at least 12 characters long but shorter than 128 characters.",
+        ),
+        (
+            new_account("  ", "a-long-enough-password"),
+            "Please choose a username.",
+        ),
+        (
+            new_account(&app.test_user.username, "a-long-enough-password"),
+            "That username is already taken.",
+        ),
+    ];
+
+    for (body, message) in test_cases {
+        let response = app.post(&link, &body).await;
+        assert_is_redirect_to(&response, &link);
+        let html = app.get(&link).await.text().await.unwrap();
+        assert!(html.contains(message), "Missing `{}`", message);
+    }
+    let n_users = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM users WHERE email IS NOT NULL"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_users, 0);
+}
+
+#[tokio::test]
+async fn existing_users_cannot_be_invited_again() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.login().await;
+    app.post(
+        "/admin/email",
+        &serde_json::json!({ "email": "admin@example.com" }),
+    )
+    .await;
+
+    let response = invite(&app, "admin@example.com", "viewer").await;
+
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains("admin@example.com already has an account."));
+}
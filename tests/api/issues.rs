@@ -0,0 +1,117 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp, title: &str) -> uuid::Uuid {
+    let newsletter_request_body = serde_json::json!({
+        "title": title,
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    sqlx::query!(
+        "SELECT newsletter_issue_id FROM newsletter_issues WHERE title = $1",
+        title
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .newsletter_issue_id
+}
+
+async fn subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_newsletter_issues() {
+    let app = spawn_app().await;
+    let response = app.get("/admin/issues").await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn published_issues_are_listed_with_delivery_totals() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    let issue_id = publish_newsletter(&app, "Issue #1").await;
+
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        r#"<a href="/admin/issues/{}">Issue #1</a>"#,
+        issue_id
+    )));
+    // queued, delivered, failed, skipped
+    let totals = |counts: [u8; 4]| {
+        counts
+            .iter()
+            .map(|c| format!("<td>{}</td>", c))
+            .collect::<Vec<_>>()
+            .join("\n            ")
+    };
+    assert!(html_page.contains(&totals([1, 0, 0, 0])));
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains(&totals([0, 1, 0, 0])));
+}
+
+#[tokio::test]
+async fn issue_status_page_reports_per_recipient_outcomes() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let email = subscriber_email(&app).await;
+    let issue_id = publish_newsletter(&app, "Issue #1").await;
+    let status_page = format!("/admin/issues/{}", issue_id);
+
+    // Part 1 - Before the worker picks the task up
+    let html_page = app.get(&status_page).await.text().await.unwrap();
+    assert!(html_page.contains(&format!("<td>{}</td>\n            <td>queued</td>", email)));
+
+    // Part 2 - After delivery
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let html_page = app.get(&status_page).await.text().await.unwrap();
+    assert!(html_page.contains(&format!(
+        "<td>{}</td>\n            <td>delivered</td>",
+        email
+    )));
+    let outcome = sqlx::query!("SELECT outcome FROM issue_delivery_outcomes",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "delivered");
+}
+
+#[tokio::test]
+async fn status_page_of_an_unknown_issue_returns_a_404() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let response = app
+        .get(&format!("/admin/issues/{}", uuid::Uuid::new_v4()))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 404);
+}
@@ -0,0 +1,272 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn create_list(app: &TestApp, slug: &str, name: &str) -> reqwest::Response {
+    app.post(
+        "/admin/lists",
+        &serde_json::json!({ "slug": slug, "name": name }),
+    )
+    .await
+}
+
+// The checkboxes of the publish form send one `lists` pair per picked list.
+async fn publish_to_lists(app: &TestApp, lists: &[&str]) -> reqwest::Response {
+    let mut body = serde_urlencoded::to_string(serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": Uuid::new_v4().to_string()
+    }))
+    .unwrap();
+    for list in lists {
+        body.push_str(&format!("&lists={}", list));
+    }
+    app.api_client
+        .post(format!("{}/admin/newsletter", &app.address))
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+// Subscribes and returns the confirmation link that was emailed.
+async fn subscribe_to_list(app: &TestApp, email: &str, list: Option<&str>) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    let mut body = serde_json::json!({ "name": "le guin", "email": email });
+    if let Some(list) = list {
+        body["list"] = serde_json::Value::from(list);
+    }
+    app.post_subscriptions(serde_urlencoded::to_string(body).unwrap())
+        .await
+        .error_for_status()
+        .unwrap();
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_confirmation_links(&email_request).html
+}
+
+async fn queued_addresses(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue ORDER BY subscriber_email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect()
+}
+
+#[tokio::test]
+async fn issues_only_go_to_the_members_of_the_picked_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    create_list(&app, "weekly", "Weekly digest").await;
+    let link = subscribe_to_list(&app, "ursula@example.com", None).await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let link = subscribe_to_list(&app, "octavia@example.com", Some("weekly")).await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    let response = publish_to_lists(&app, &["weekly"]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    assert_eq!(queued_addresses(&app).await, vec!["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn members_of_several_picked_lists_get_a_single_copy() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    create_list(&app, "weekly", "Weekly digest").await;
+    let link = subscribe_to_list(&app, "ursula@example.com", None).await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let link = subscribe_to_list(&app, "ursula@example.com", Some("weekly")).await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act
+    publish_to_lists(&app, &["newsletter", "weekly"]).await;
+
+    // Assert
+    assert_eq!(queued_addresses(&app).await, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn each_list_is_confirmed_on_its_own() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    create_list(&app, "weekly", "Weekly digest").await;
+    let link = subscribe_to_list(&app, "ursula@example.com", None).await;
+    reqwest::get(link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Act - Part 1 - Join a second list without confirming it
+    let weekly_link = subscribe_to_list(&app, "ursula@example.com", Some("weekly")).await;
+    publish_to_lists(&app, &["weekly"]).await;
+
+    // Assert - Part 1 - Still only a member of the first list
+    assert!(queued_addresses(&app).await.is_empty());
+
+    // Act - Part 2 - Confirm the second list
+    reqwest::get(weekly_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    publish_to_lists(&app, &["weekly"]).await;
+
+    // Assert - Part 2
+    assert_eq!(queued_addresses(&app).await, vec!["ursula@example.com"]);
+}
+
+#[tokio::test]
+async fn a_link_only_confirms_the_list_it_was_sent_for() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    create_list(&app, "weekly", "Weekly digest").await;
+    let newsletter_link = subscribe_to_list(&app, "ursula@example.com", None).await;
+    let weekly_link = subscribe_to_list(&app, "ursula@example.com", Some("weekly")).await;
+    assert_ne!(newsletter_link, weekly_link);
+
+    // Act
+    reqwest::get(newsletter_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    publish_to_lists(&app, &["weekly"]).await;
+
+    // Assert
+    assert!(queued_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn subscribing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula@example.com",
+            "list": "does-not-exist"
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["field"], "list");
+    assert_eq!(
+        body["error"]["message"],
+        "There is no list called 'does-not-exist'."
+    );
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_list_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    // Act
+    let response = publish_to_lists(&app, &["does-not-exist"]).await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("There is no list called &#x27;does-not-exist&#x27;."));
+    assert!(queued_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn new_lists_can_be_picked_in_the_publish_form() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let response = create_list(&app, "weekly", "Weekly digest").await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/lists");
+    let html_page = app.get("/admin/lists").await.text().await.unwrap();
+    assert!(html_page.contains("The list &#x27;Weekly digest&#x27; has been created."));
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains(r#"value="weekly""#));
+    assert!(html_page.contains(r#"value="newsletter" checked"#));
+}
+
+#[tokio::test]
+async fn list_slugs_must_be_unique_and_url_friendly() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    for (slug, message) in [
+        (
+            "newsletter",
+            "A list called &#x27;newsletter&#x27; already exists.",
+        ),
+        ("Weekly Digest", "is not a valid list slug"),
+    ] {
+        // Act
+        create_list(&app, slug, "Some list").await;
+
+        // Assert
+        let html_page = app.get("/admin/lists").await.text().await.unwrap();
+        assert!(html_page.contains(message));
+    }
+}
+
+#[tokio::test]
+async fn viewers_cannot_create_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let response = create_list(&app, "weekly", "Weekly digest").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
@@ -1,11 +1,25 @@
-use crate::helper::{assert_is_redirect_to, spawn_app};
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use argon2::password_hash::SaltString;
+use argon2::{Algorithm, Argon2, Params, PasswordHasher, Version};
+use uuid::Uuid;
+
+async fn stored_password_hash(app: &TestApp) -> String {
+    sqlx::query!(
+        "SELECT password_hash FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .password_hash
+}
 
 #[tokio::test]
 async fn an_error_flash_message_is_set_on_failure() {
     let app = spawn_app().await;
 
     let login_body = serde_json::json!({
-        "username": "random-username",
+        "username": Uuid::new_v4().to_string(),
         "password": "random-password"
     });
     // 1 - try login
@@ -38,3 +52,54 @@ async fn redirect_to_admin_dashboard_after_login_success() {
     let html_page = app.get_admin_dashboard_html().await;
     assert!(html_page.contains(&format!("Welcome {}", app.test_user.username)));
 }
+
+#[tokio::test]
+async fn an_outdated_password_hash_is_upgraded_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let salt = SaltString::generate(&mut rand::thread_rng());
+    let outdated_hash = Argon2::new(
+        Algorithm::Argon2id,
+        Version::V0x13,
+        Params::new(4096, 1, 1, None).unwrap(),
+    )
+    .hash_password(app.test_user.password.as_bytes(), &salt)
+    .unwrap()
+    .to_string();
+    sqlx::query!(
+        "UPDATE users SET password_hash = $1 WHERE user_id = $2",
+        outdated_hash,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    app.login().await;
+
+    // Assert
+    let password_hash = stored_password_hash(&app).await;
+    assert!(password_hash.starts_with("$argon2id$v=19$m=15000,t=2,p=1$"));
+    app.logout().await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_up_to_date_password_hash_is_left_alone_on_login() {
+    // Arrange
+    let app = spawn_app().await;
+    let password_hash = stored_password_hash(&app).await;
+
+    // Act
+    app.login().await;
+
+    // Assert
+    assert_eq!(stored_password_hash(&app).await, password_hash);
+}
@@ -0,0 +1,206 @@
+use crate::helper::{
+    assert_is_redirect_to, client_from, random_loopback_address, spawn_app, spawn_app_behind_proxy,
+    TestApp,
+};
+use std::net::IpAddr;
+use std::time::Duration;
+use uuid::Uuid;
+
+async fn fail_login(app: &TestApp, username: &str) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": username,
+            "password": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn repeated_failures_delay_further_attempts_for_that_username() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..3 {
+        fail_login(&app, &app.test_user.username).await;
+        let html_page = app.get_login_html().await;
+        assert!(html_page.contains("<p><i>Authentication failed</i></p>"));
+    }
+    fail_login(&app, &app.test_user.username).await;
+
+    // Act - Part 1 - Even the right password is turned away while the delay runs
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page
+        .contains("<p><i>Too many failed login attempts. Please try again in 1 seconds.</i></p>"));
+
+    // Act - Part 2 - Login works again once the delay has passed
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn a_delayed_username_does_not_block_other_users_on_the_same_address() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_username = Uuid::new_v4().to_string();
+    for _ in 0..4 {
+        fail_login(&app, &other_username).await;
+    }
+
+    // Act
+    app.post_login(&serde_json::json!({
+        "username": &other_username,
+        "password": "whatever"
+    }))
+    .await;
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn an_address_is_locked_out_after_too_many_failures() {
+    // Arrange
+    let app = spawn_app().await;
+    for _ in 0..10 {
+        fail_login(&app, &Uuid::new_v4().to_string()).await;
+    }
+
+    // Act
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts. Please try again in 900 seconds."));
+
+    // Another address is not affected
+    let response = client_from(random_loopback_address())
+        .post(format!("{}/login", &app.address))
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn forwarded_headers_do_not_reset_the_address_counter() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..10 {
+        let response = app
+            .api_client
+            .post(format!("{}/login", &app.address))
+            .header("X-Forwarded-For", format!("192.0.2.{}", i))
+            .form(&serde_json::json!({
+                "username": Uuid::new_v4().to_string(),
+                "password": Uuid::new_v4().to_string()
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/login", &app.address))
+        .header("X-Forwarded-For", "192.0.2.100")
+        .form(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password
+        }))
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    let html_page = app.get_login_html().await;
+    assert!(html_page.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn behind_a_trusted_proxy_each_forwarded_client_has_its_own_counter() {
+    // Arrange
+    let proxy_address = random_loopback_address();
+    let app = spawn_app_behind_proxy(proxy_address).await;
+    let proxy = client_from(proxy_address);
+    let locked_out_client = random_loopback_address();
+    let other_client = random_loopback_address();
+    let login_via_proxy = |client_address: IpAddr, username: String, password: String| {
+        proxy
+            .post(format!("{}/login", &app.address))
+            // Proxies append the address they received the request from.
+            .header(
+                "X-Forwarded-For",
+                format!("203.0.113.1, {}", client_address),
+            )
+            .form(&serde_json::json!({ "username": username, "password": password }))
+            .send()
+    };
+    for _ in 0..10 {
+        let response = login_via_proxy(
+            locked_out_client,
+            Uuid::new_v4().to_string(),
+            Uuid::new_v4().to_string(),
+        )
+        .await
+        .expect("Failed to execute request.");
+        assert_is_redirect_to(&response, "/login");
+    }
+
+    // Act - Part 1 - The client that failed is locked out
+    let response = login_via_proxy(
+        locked_out_client,
+        app.test_user.username.clone(),
+        app.test_user.password.clone(),
+    )
+    .await
+    .expect("Failed to execute request.");
+
+    // Assert - Part 1
+    assert_is_redirect_to(&response, "/login");
+
+    // Act - Part 2 - Another client behind the same proxy is not
+    let response = login_via_proxy(
+        other_client,
+        app.test_user.username.clone(),
+        app.test_user.password.clone(),
+    )
+    .await
+    .expect("Failed to execute request.");
+
+    // Assert - Part 2
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
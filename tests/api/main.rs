@@ -1,9 +1,29 @@
 mod admin_dashboard;
+mod api_tokens;
+mod batch_delivery;
 mod change_password;
+mod delivery_failures;
+mod drafts;
 mod health_check;
 mod helper;
+mod invitations;
+mod issues;
+mod lists;
 mod login;
+mod login_throttle;
 mod logout;
 mod newsletter;
+mod password_reset;
+mod preferences;
+mod resend_confirmation;
+mod roles;
+mod scheduled_newsletter;
+mod segments;
+mod sessions;
+mod subscriber_import;
+mod subscribers;
 mod subscription;
 mod subscription_confirm;
+mod two_factor;
+mod unsubscribe;
+mod webhooks;
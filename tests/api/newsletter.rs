@@ -83,6 +83,53 @@ async fn newsletters_are_delivered_to_confirmed_subscribers() {
     app.dispatch_all_pending_emails().await;
 }
 
+#[tokio::test]
+async fn newsletters_are_personalised_for_each_subscriber() {
+    let app = spawn_app().await;
+    app.login().await;
+    app.create_confirmed_subscriber().await;
+    app.create_confirmed_subscriber().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Hi {{ name }}, this was sent to {{ email }}.",
+        "html_content": "<p>Hi {{name}}</p><a href=\"{{ unsubscribe_url }}\">Leave</a>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    app.dispatch_all_pending_emails().await;
+
+    let subscribers = sqlx::query!("SELECT email, name FROM subscriptions",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    let email_requests = app.email_server.received_requests().await.unwrap();
+    for subscriber in subscribers {
+        let body = email_requests
+            .iter()
+            .map(|r| serde_json::from_slice::<serde_json::Value>(&r.body).unwrap())
+            .find(|body| body["To"] == subscriber.email.as_str() && body["Subject"] != "Welcome!")
+            .unwrap();
+        assert!(body["TextBody"].as_str().unwrap().starts_with(&format!(
+            "Hi {}, this was sent to {}.",
+            subscriber.name, subscriber.email
+        )));
+        let html_body = body["HtmlBody"].as_str().unwrap();
+        // Names may contain apostrophes, which are escaped in HTML.
+        let html_name = subscriber.name.replace('\'', "&#x27;");
+        assert!(html_body.starts_with(&format!("<p>Hi {}</p>", html_name)));
+        assert!(!html_body.contains("{{"));
+        assert!(html_body.contains(r#"<a href="http://127.0.0.1/subscriptions/unsubscribe?token="#));
+    }
+}
+
 #[tokio::test]
 async fn newsletter_redirects_with_flash_message_for_invalid_data() {
     let app = spawn_app().await;
@@ -166,6 +213,24 @@ async fn newsletter_redirects_with_flash_message_for_invalid_data() {
             }),
             "Parse error: The idempotency key must be shorter than 50 characters.",
         ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Hi {{ first_name }}",
+                "html_content": "<p>Newsletter body as HTML</p>",
+                "idempotency_key": uuid::Uuid::new_v4().to_string()
+            }),
+            "Plain text body: `{{ first_name }}` is not a known variable.",
+        ),
+        (
+            serde_json::json!({
+                "title": "Newsletter title",
+                "text_content": "Newsletter body as plain text",
+                "html_content": "<p>Hi {{ name </p>",
+                "idempotency_key": uuid::Uuid::new_v4().to_string()
+            }),
+            "HTML body: A `{{` placeholder is not closed with `}}`.",
+        ),
     ];
     for (invalid_body, flash_message) in test_cases {
         let response = app.post_newsletter(&invalid_body).await;
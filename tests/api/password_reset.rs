@@ -0,0 +1,261 @@
+use crate::helper::{
+    assert_is_redirect_to, client_from, random_loopback_address, spawn_app, TestApp,
+};
+use uuid::Uuid;
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn set_user_email(app: &TestApp, email: &str) {
+    sqlx::query!(
+        "UPDATE users SET email = $1 WHERE user_id = $2",
+        email,
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+}
+
+async fn post_reset_request(app: &TestApp, email: &str) -> reqwest::Response {
+    app.post("/password_reset", &serde_json::json!({ "email": email }))
+        .await
+}
+
+// Addresses have their own cooldown, so every test uses a fresh one.
+fn unique_email() -> String {
+    format!("{}@example.com", Uuid::new_v4())
+}
+
+// Requests a reset for the test user and returns the path of the link found in the email.
+async fn reset_link(app: &TestApp) -> String {
+    let email = unique_email();
+    set_user_email(app, &email).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    let response = post_reset_request(app, &email).await;
+    assert_is_redirect_to(&response, "/login");
+    app.send_queued_password_reset_emails().await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_links(email_request)
+        .html
+        .path()
+        .to_string()
+}
+
+fn new_password_body(password: &str) -> serde_json::Value {
+    serde_json::json!({
+        "new_password": password,
+        "confirm_new_password": password,
+    })
+}
+
+async fn login_with(app: &TestApp, password: &str) -> reqwest::Response {
+    app.post_login(&serde_json::json!({
+        "username": &app.test_user.username,
+        "password": password,
+    }))
+    .await
+}
+
+#[tokio::test]
+async fn the_login_page_links_to_the_password_reset_form() {
+    let app = spawn_app().await;
+
+    let html = app.get_login_html().await;
+
+    assert!(html.contains(r#"<a href="/password_reset">Forgot your password?</a>"#));
+}
+
+#[tokio::test]
+async fn unknown_addresses_get_the_same_answer_but_no_email() {
+    let app = spawn_app().await;
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_reset_request(&app, &unique_email()).await;
+    app.send_queued_password_reset_emails().await;
+
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains(
+        "If an account uses that email address, we have sent it a link to reset the password."
+    ));
+}
+
+#[tokio::test]
+async fn the_reset_email_is_sent_by_the_background_worker() {
+    let app = spawn_app().await;
+    let email = unique_email();
+    set_user_email(&app, &email).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = post_reset_request(&app, &email).await;
+    assert_is_redirect_to(&response, "/login");
+    assert!(app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .is_empty());
+
+    app.send_queued_password_reset_emails().await;
+}
+
+#[tokio::test]
+async fn an_address_gets_a_single_link_during_its_cooldown() {
+    let app = spawn_app().await;
+    let email = unique_email();
+    set_user_email(&app, &email).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Each request comes from another client address, so only the address cooldown applies.
+    for _ in 0..2 {
+        let response = client_from(random_loopback_address())
+            .post(format!("{}/password_reset", &app.address))
+            .form(&serde_json::json!({ "email": &email }))
+            .send()
+            .await
+            .unwrap();
+        assert_is_redirect_to(&response, "/login");
+    }
+    app.send_queued_password_reset_emails().await;
+}
+
+#[tokio::test]
+async fn a_client_address_has_to_wait_between_two_requests() {
+    let app = spawn_app().await;
+
+    let first = post_reset_request(&app, &unique_email()).await;
+    let second = post_reset_request(&app, &unique_email()).await;
+
+    assert_is_redirect_to(&first, "/login");
+    assert_is_redirect_to(&second, "/password_reset");
+    let html = app.get("/password_reset").await.text().await.unwrap();
+    assert!(html.contains("Too many password reset requests. Please try again in"));
+}
+
+#[tokio::test]
+async fn a_reset_link_sets_a_new_password() {
+    let app = spawn_app().await;
+    let link = reset_link(&app).await;
+
+    let response = app.get(&link).await;
+    assert_eq!(response.status().as_u16(), 200);
+    let response = app
+        .post(&link, &new_password_body("a-brand-new-password"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Your password has been reset. Please login to continue."));
+
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/login");
+    let response = login_with(&app, "a-brand-new-password").await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn resetting_the_password_logs_out_every_session() {
+    let app = spawn_app().await;
+    app.login().await;
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The reset happens from another browser
+    let link = reset_link(&app).await;
+    let other_browser = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap();
+    let response = other_browser
+        .post(format!("{}{}", app.address, link))
+        .form(&new_password_body("a-brand-new-password"))
+        .send()
+        .await
+        .unwrap();
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn a_reset_link_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let link = reset_link(&app).await;
+
+    let response = app
+        .post(&link, &new_password_body("a-brand-new-password"))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post(&link, &new_password_body("yet-another-password"))
+        .await;
+    assert_eq!(response.status().as_u16(), 410);
+    let response = app.get(&link).await;
+    assert_eq!(response.status().as_u16(), 410);
+}
+
+#[tokio::test]
+async fn expired_reset_links_are_rejected() {
+    let app = spawn_app().await;
+    let link = reset_link(&app).await;
+    sqlx::query!("UPDATE password_reset_tokens SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .post(&link, &new_password_body("a-brand-new-password"))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 410);
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
+
+#[tokio::test]
+async fn the_new_password_must_follow_the_password_rules() {
+    let app = spawn_app().await;
+    let link = reset_link(&app).await;
+    let test_cases = vec![
+        (
+            new_password_body("short"),
+            "New password must at least 12 characters long but shorter than 128 characters.",
+        ),
+        (
+            serde_json::json!({
+                "new_password": "a-brand-new-password",
+                "confirm_new_password": "another-new-password",
+            }),
+            "New password does not match with confirmation password.",
+        ),
+    ];
+
+    for (body, message) in test_cases {
+        let response = app.post(&link, &body).await;
+        assert_is_redirect_to(&response, &link);
+        let html = app.get(&link).await.text().await.unwrap();
+        assert!(html.contains(message), "Missing `{}`", message);
+    }
+    let response = login_with(&app, &app.test_user.password).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+}
@@ -0,0 +1,327 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use reqwest::Url;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_newsletter(app: &TestApp) {
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+async fn get_preferences_link_from_newsletter(app: &TestApp) -> Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_newsletter(app).await;
+    app.dispatch_all_pending_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    let raw_link = body["TextBody"]
+        .as_str()
+        .unwrap()
+        .lines()
+        .find_map(|line| line.strip_prefix("Manage your preferences: "))
+        .unwrap();
+    let mut preferences_link = Url::parse(raw_link).unwrap();
+    assert_eq!(preferences_link.host_str().unwrap(), "127.0.0.1");
+    preferences_link.set_port(Some(app.port)).unwrap();
+    preferences_link
+}
+
+async fn post_preferences(
+    app: &TestApp,
+    preferences_link: &Url,
+    action: &str,
+    body: &serde_json::Value,
+) -> reqwest::Response {
+    let mut action_link = preferences_link.clone();
+    action_link.set_path(&format!("/preferences/{}", action));
+    app.api_client
+        .post(action_link)
+        .form(body)
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+fn redirect_target(preferences_link: &Url) -> String {
+    format!("/preferences?{}", preferences_link.query().unwrap())
+}
+
+async fn count_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn every_issue_links_to_the_preferences_page() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let subscriber = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+    let response = app.api_client.get(preferences_link).send().await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains(&format!("Preferences for {}", subscriber.email)));
+    assert!(html_page.contains("Pause deliveries"));
+}
+
+#[tokio::test]
+async fn subscribers_can_change_their_name() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &preferences_link,
+        "name",
+        &serde_json::json!({ "name": "Ursula K. Le Guin" }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    let html_page = app
+        .api_client
+        .get(preferences_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>Your name has been updated.</i></p>"));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, "Ursula K. Le Guin");
+}
+
+#[tokio::test]
+async fn an_invalid_name_is_not_saved() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+    let original = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &preferences_link,
+        "name",
+        &serde_json::json!({ "name": "<script>" }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, original.name);
+}
+
+#[tokio::test]
+async fn subscribers_can_pick_their_lists() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    app.post(
+        "/admin/lists",
+        &serde_json::json!({ "slug": "weekly", "name": "Weekly digest" }),
+    )
+    .await;
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &preferences_link,
+        "lists",
+        &serde_json::json!({ "lists": "weekly" }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    let html_page = app
+        .api_client
+        .get(preferences_link)
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>Your lists have been updated.</i></p>"));
+    assert!(html_page.contains(r#"value="weekly" checked"#));
+    let memberships = sqlx::query!(
+        r#"
+        SELECT l.slug, m.status
+        FROM list_memberships m
+        JOIN lists l ON l.list_id = m.list_id
+        ORDER BY l.slug
+        "#
+    )
+    .fetch_all(&app.db_pool)
+    .await
+    .unwrap()
+    .into_iter()
+    .map(|r| (r.slug, r.status))
+    .collect::<Vec<_>>();
+    assert_eq!(
+        memberships,
+        vec![
+            ("newsletter".to_string(), "unsubscribed".to_string()),
+            ("weekly".to_string(), "confirmed".to_string()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn pausing_skips_queued_and_new_issues_until_resumed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+    publish_newsletter(&app).await;
+    assert_eq!(count_queued_deliveries(&app).await, 1);
+
+    // Act - Part 1 - Pause
+    let response = post_preferences(
+        &app,
+        &preferences_link,
+        "pause",
+        &serde_json::json!({ "days": 30 }),
+    )
+    .await;
+    assert_is_redirect_to(&response, &redirect_target(&preferences_link));
+    let html_page = app
+        .api_client
+        .get(preferences_link.clone())
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("<p><i>Deliveries are paused until"));
+    assert!(html_page.contains("Resume deliveries"));
+
+    // Assert - Part 1 - Nothing is queued for the paused subscriber
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+    publish_newsletter(&app).await;
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+
+    // Act - Part 2 - Resume
+    post_preferences(
+        &app,
+        &preferences_link,
+        "pause",
+        &serde_json::json!({ "days": 0 }),
+    )
+    .await;
+
+    // Assert - Part 2 - New issues reach the subscriber again
+    publish_newsletter(&app).await;
+    assert_eq!(count_queued_deliveries(&app).await, 1);
+}
+
+#[tokio::test]
+async fn deliveries_can_only_be_paused_for_the_offered_periods() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let preferences_link = get_preferences_link_from_newsletter(&app).await;
+
+    // Act
+    let response = post_preferences(
+        &app,
+        &preferences_link,
+        "pause",
+        &serde_json::json!({ "days": 3650 }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT paused_until FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.paused_until.is_none());
+}
+
+#[tokio::test]
+async fn preferences_with_a_tampered_token_are_rejected_with_a_401() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let mut preferences_link = get_preferences_link_from_newsletter(&app).await;
+    let token = preferences_link.query_pairs().next().unwrap().1.to_string();
+    let (_, tag) = token.split_once('.').unwrap();
+    preferences_link.set_query(Some(&format!("token={}.{}", Uuid::new_v4(), tag)));
+
+    // Act
+    let page = app
+        .api_client
+        .get(preferences_link.clone())
+        .send()
+        .await
+        .unwrap();
+    let rename = post_preferences(
+        &app,
+        &preferences_link,
+        "name",
+        &serde_json::json!({ "name": "Someone else" }),
+    )
+    .await;
+
+    // Assert
+    assert_eq!(page.status().as_u16(), 401);
+    assert_eq!(rename.status().as_u16(), 401);
+}
@@ -0,0 +1,280 @@
+use crate::helper::{client_from, random_loopback_address, spawn_app, TestApp};
+use std::net::IpAddr;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const NEUTRAL_MESSAGE: &str = "If this address is waiting to be confirmed";
+
+// Cooldowns are kept per client address: each call can come from a new one.
+async fn post_resend(app: &TestApp, email: &str, client_ip: Option<IpAddr>) -> reqwest::Response {
+    let client = match client_ip {
+        Some(client_ip) => client_from(client_ip),
+        None => app.api_client.clone(),
+    };
+    client
+        .post(format!("{}/subscriptions/resend", &app.address))
+        .form(&serde_json::json!({ "email": email }))
+        .send()
+        .await
+        .expect("Failed to execute request.")
+}
+
+async fn pending_subscriber_email(app: &TestApp) -> String {
+    sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .email
+}
+
+async fn count_tokens(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM subscription_tokens"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn a_pending_subscriber_gets_their_still_valid_link_again() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = app.create_unconfirmed_subscriber().await;
+    let email = pending_subscriber_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = post_resend(&app, &email, None).await;
+    app.send_queued_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains(NEUTRAL_MESSAGE));
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(&email_request);
+    assert_eq!(first_links.html, second_links.html);
+    assert_eq!(count_tokens(&app).await, 1);
+}
+
+#[tokio::test]
+async fn an_expired_link_is_replaced_by_a_new_one() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = app.create_unconfirmed_subscriber().await;
+    let email = pending_subscriber_email(&app).await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    post_resend(&app, &email, None).await;
+    app.send_queued_confirmation_emails().await;
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let second_links = app.get_confirmation_links(&email_request);
+    assert_ne!(first_links.html, second_links.html);
+    reqwest::get(second_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn an_address_is_not_emailed_again_during_its_cooldown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let email = pending_subscriber_email(&app).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let first = post_resend(&app, &email, Some(random_loopback_address())).await;
+    let second = post_resend(&app, &email, Some(random_loopback_address())).await;
+    app.send_queued_confirmation_emails().await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 200);
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn unknown_and_confirmed_addresses_get_the_same_response_and_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let confirmed_email = pending_subscriber_email(&app).await;
+    let unknown_email = format!("{}@example.com", Uuid::new_v4());
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    for email in [confirmed_email, unknown_email] {
+        // Act
+        let response = post_resend(&app, &email, Some(random_loopback_address())).await;
+        app.send_queued_confirmation_emails().await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 200);
+        assert!(response.text().await.unwrap().contains(NEUTRAL_MESSAGE));
+    }
+}
+
+#[tokio::test]
+async fn a_client_address_has_to_wait_between_two_requests() {
+    // Arrange
+    let app = spawn_app().await;
+    let client_ip = random_loopback_address();
+    let first_email = format!("{}@example.com", Uuid::new_v4());
+    let second_email = format!("{}@example.com", Uuid::new_v4());
+
+    // Act
+    let first = post_resend(&app, &first_email, Some(client_ip)).await;
+    let second = post_resend(&app, &second_email, Some(client_ip)).await;
+
+    // Assert
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 429);
+    assert!(second.headers().get("Retry-After").is_some());
+    assert!(second
+        .text()
+        .await
+        .unwrap()
+        .contains("Too many requests. Please try again in"));
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_sends_the_same_link() {
+    // Arrange
+    let app = spawn_app().await;
+    let first_links = app.create_unconfirmed_subscriber().await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": subscriber.name,
+        "email": subscriber.email,
+    }))
+    .unwrap();
+    app.post_subscriptions(body)
+        .await
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    assert_eq!(
+        first_links.html,
+        app.get_confirmation_links(&email_request).html
+    );
+    assert_eq!(count_tokens(&app).await, 1);
+}
+
+#[tokio::test]
+async fn the_email_is_sent_by_the_confirmation_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let email = pending_subscriber_email(&app).await;
+
+    // Act - Part 1 - Nothing is sent while handling the request
+    {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        let response = post_resend(&app, &email, None).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    // Act - Part 2 - The worker sends it
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.send_queued_confirmation_emails().await;
+}
+
+#[tokio::test]
+async fn subscribing_again_shares_the_address_cooldown() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_unconfirmed_subscriber().await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    post_resend(&app, &subscriber.email, None).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    app.send_queued_confirmation_emails().await;
+
+    // Act
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": subscriber.name,
+        "email": subscriber.email,
+    }))
+    .unwrap();
+    let response = app.post_subscriptions(body).await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+}
@@ -0,0 +1,164 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+
+async fn login_with_role(app: &TestApp, role: &str) -> TestUser {
+    let user = TestUser::with_role(role);
+    user.store(&app.db_pool).await;
+    app.login_as(&user).await;
+    user
+}
+
+fn newsletter_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    })
+}
+
+fn draft_body() -> serde_json::Value {
+    serde_json::json!({
+        "title": "Draft title",
+        "text_content": "Draft body as plain text",
+        "html_content": "<p>Draft body as HTML</p>",
+    })
+}
+
+async fn n_issues(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n
+}
+
+#[tokio::test]
+async fn viewers_cannot_publish_or_draft() {
+    let app = spawn_app().await;
+    login_with_role(&app, "viewer").await;
+
+    let response = app.post_newsletter(&newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.post("/admin/drafts", &draft_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+    assert_eq!(n_issues(&app).await, 0);
+
+    // Read-only pages are still available
+    let response = app.get("/admin/issues").await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn editors_can_draft_but_not_publish() {
+    let app = spawn_app().await;
+    login_with_role(&app, "editor").await;
+
+    let response = app.post("/admin/drafts", &draft_body()).await;
+    assert_eq!(response.status().as_u16(), 303);
+    let newsletter_issue_id = sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id;
+
+    let response = app
+        .post(
+            &format!("/admin/drafts/{}/publish", newsletter_issue_id),
+            &serde_json::json!({ "idempotency_key": uuid::Uuid::new_v4().to_string() }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app.post_newsletter(&newsletter_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+
+    let status = sqlx::query!("SELECT status FROM newsletter_issues")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "draft");
+}
+
+#[tokio::test]
+async fn publishers_can_publish_but_not_manage_users() {
+    let app = spawn_app().await;
+    login_with_role(&app, "publisher").await;
+
+    let response = app.post_newsletter(&newsletter_body()).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    assert_eq!(n_issues(&app).await, 1);
+
+    let response = app.get("/admin/users").await;
+    assert_eq!(response.status().as_u16(), 403);
+    let response = app
+        .post(
+            &format!("/admin/users/{}/role", app.test_user.user_id),
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn owners_can_change_roles_and_changes_apply_immediately() {
+    let app = spawn_app().await;
+    let editor = TestUser::with_role("editor");
+    editor.store(&app.db_pool).await;
+
+    app.login().await;
+    let html = app.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains(&editor.username));
+
+    let response = app
+        .post(
+            &format!("/admin/users/{}/role", editor.user_id),
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains(&format!("{} is now a viewer.", editor.username)));
+
+    app.logout().await;
+    app.login_as(&editor).await;
+    let response = app.post("/admin/drafts", &draft_body()).await;
+    assert_eq!(response.status().as_u16(), 403);
+}
+
+#[tokio::test]
+async fn the_last_owner_cannot_be_demoted() {
+    let app = spawn_app().await;
+    // Migrations seed a default owner; leave the test user as the only one.
+    sqlx::query!(
+        "DELETE FROM users WHERE user_id <> $1",
+        app.test_user.user_id
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.login().await;
+
+    let response = app
+        .post(
+            &format!("/admin/users/{}/role", app.test_user.user_id),
+            &serde_json::json!({ "role": "viewer" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/users");
+    let html = app.get("/admin/users").await.text().await.unwrap();
+    assert!(html.contains("There must always be at least one owner."));
+}
+
+#[tokio::test]
+async fn removed_users_are_logged_out() {
+    let app = spawn_app().await;
+    let user = login_with_role(&app, "publisher").await;
+
+    sqlx::query!("DELETE FROM users WHERE user_id = $1", user.user_id)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+}
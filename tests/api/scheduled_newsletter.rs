@@ -0,0 +1,193 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use chrono::{Duration, Utc};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+use zero2prod::issue_delivery_worker::fan_out_scheduled_issues;
+
+async fn schedule_newsletter(app: &TestApp, scheduled_for: &str) -> reqwest::Response {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string(),
+        "scheduled_for": scheduled_for,
+    });
+    app.post_newsletter(&newsletter_request_body).await
+}
+
+async fn scheduled_issue_id(app: &TestApp) -> uuid::Uuid {
+    sqlx::query!("SELECT newsletter_issue_id FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .newsletter_issue_id
+}
+
+async fn make_issue_due(app: &TestApp) {
+    sqlx::query!("UPDATE newsletter_issues SET scheduled_for = now() - interval '1 minute'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+}
+
+fn in_one_day() -> String {
+    (Utc::now() + Duration::days(1))
+        .format("%Y-%m-%dT%H:%M")
+        .to_string()
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_not_delivered_before_their_time() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = schedule_newsletter(&app, &in_one_day()).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("The newsletter issue has been scheduled"));
+
+    assert_eq!(fan_out_scheduled_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+    let issue = sqlx::query!("SELECT status, scheduled_for FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "scheduled");
+    assert!(issue.scheduled_for.is_some());
+}
+
+#[tokio::test]
+async fn scheduled_issues_are_delivered_once_their_time_has_come() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    schedule_newsletter(&app, &in_one_day()).await;
+
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    make_issue_due(&app).await;
+    assert_eq!(fan_out_scheduled_issues(&app.db_pool).await.unwrap(), 1);
+    app.dispatch_all_pending_emails().await;
+
+    let issue = sqlx::query!("SELECT status FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(issue.status, "published");
+    // A published issue is never fanned out twice.
+    assert_eq!(fan_out_scheduled_issues(&app.db_pool).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn issues_cannot_be_scheduled_in_the_past() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    let response = schedule_newsletter(&app, "2020-01-01T10:00").await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("The scheduled time must be in the future"));
+
+    let response = schedule_newsletter(&app, "next tuesday").await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("next tuesday is not a valid date and time"));
+
+    let n_issues = sqlx::query!(r#"SELECT COUNT(*) AS "n!" FROM newsletter_issues"#,)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .n;
+    assert_eq!(n_issues, 0);
+}
+
+#[tokio::test]
+async fn cancelled_issues_are_never_delivered() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    schedule_newsletter(&app, &in_one_day()).await;
+    let issue_id = scheduled_issue_id(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post(format!("/admin/issues/{}/cancel", issue_id).as_str(), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains("The scheduled issue has been cancelled."));
+
+    make_issue_due(&app).await;
+    assert_eq!(fan_out_scheduled_issues(&app.db_pool).await.unwrap(), 0);
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn scheduled_issues_can_be_rescheduled() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    schedule_newsletter(&app, &in_one_day()).await;
+    let issue_id = scheduled_issue_id(&app).await;
+
+    let response = app
+        .post(
+            format!("/admin/issues/{}/reschedule", issue_id).as_str(),
+            &serde_json::json!({ "scheduled_for": "2100-01-01T09:30" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains("The issue has been rescheduled"));
+
+    let scheduled_for = sqlx::query!("SELECT scheduled_for FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .scheduled_for
+        .unwrap();
+    assert_eq!(scheduled_for.to_rfc3339(), "2100-01-01T09:30:00+00:00");
+}
+
+#[tokio::test]
+async fn issues_that_started_sending_cannot_be_changed() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    schedule_newsletter(&app, &in_one_day()).await;
+    let issue_id = scheduled_issue_id(&app).await;
+    make_issue_due(&app).await;
+    fan_out_scheduled_issues(&app.db_pool).await.unwrap();
+
+    let response = app
+        .post(format!("/admin/issues/{}/cancel", issue_id).as_str(), &())
+        .await;
+    assert_is_redirect_to(&response, "/admin/issues");
+    let html_page = app.get("/admin/issues").await.text().await.unwrap();
+    assert!(html_page.contains("Only issues that have not started sending can be changed."));
+
+    let status = sqlx::query!("SELECT status FROM newsletter_issues",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .status;
+    assert_eq!(status, "published");
+}
@@ -0,0 +1,246 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use chrono::Utc;
+use uuid::Uuid;
+
+// A confirmed member of the default list.
+async fn insert_confirmed_subscriber(app: &TestApp, email: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, 'le guin', $3, 'confirmed')
+        "#,
+        subscriber_id,
+        email,
+        Utc::now(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        SELECT list_id, $1, 'confirmed' FROM lists WHERE slug = 'newsletter'
+        "#,
+        subscriber_id,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn set_attributes(
+    app: &TestApp,
+    subscriber_id: Uuid,
+    tags: &str,
+    attributes: &str,
+) -> reqwest::Response {
+    app.post(
+        &format!("/admin/subscribers/{}/attributes", subscriber_id),
+        &serde_json::json!({ "tags": tags, "attributes": attributes }),
+    )
+    .await
+}
+
+async fn create_segment(app: &TestApp, name: &str, expression: &str) -> reqwest::Response {
+    app.post(
+        "/admin/segments",
+        &serde_json::json!({ "name": name, "expression": expression }),
+    )
+    .await
+}
+
+async fn queued_addresses(app: &TestApp) -> Vec<String> {
+    sqlx::query!("SELECT subscriber_email FROM issue_delivery_queue ORDER BY subscriber_email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| r.subscriber_email)
+        .collect()
+}
+
+#[tokio::test]
+async fn segments_show_a_live_recipient_count() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let ursula = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+    let octavia = insert_confirmed_subscriber(&app, "octavia@example.com").await;
+    insert_confirmed_subscriber(&app, "iain@example.com").await;
+    set_attributes(&app, ursula, "vip", "country=DE\nplan=pro").await;
+    set_attributes(&app, octavia, "", "country=DE\nplan=free").await;
+
+    // Act - Part 1
+    let response = create_segment(&app, "Paying Germans", "country = DE and plan != free").await;
+
+    // Assert - Part 1
+    assert_is_redirect_to(&response, "/admin/segments");
+    let html_page = app.get("/admin/segments").await.text().await.unwrap();
+    assert!(html_page.contains("The segment &#x27;Paying Germans&#x27; has been created."));
+    assert!(html_page.contains("<td>1</td>"));
+
+    // Act - Part 2
+    set_attributes(&app, octavia, "", "country=DE\nplan=pro").await;
+
+    // Assert - Part 2
+    let html_page = app.get("/admin/segments").await.text().await.unwrap();
+    assert!(html_page.contains("<td>2</td>"));
+}
+
+#[tokio::test]
+async fn segment_counts_leave_out_subscribers_without_a_confirmed_list() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let ursula = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+    let octavia = insert_confirmed_subscriber(&app, "octavia@example.com").await;
+    set_attributes(&app, ursula, "vip", "").await;
+    set_attributes(&app, octavia, "vip", "").await;
+    sqlx::query!(
+        "UPDATE list_memberships SET status = 'unsubscribed' WHERE subscriber_id = $1",
+        octavia,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    create_segment(&app, "VIPs", "tag = vip").await;
+
+    // Assert
+    let html_page = app.get("/admin/segments").await.text().await.unwrap();
+    assert!(html_page.contains("<td>1</td>"));
+}
+
+#[tokio::test]
+async fn issues_aimed_at_a_segment_only_go_to_matching_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let ursula = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+    let octavia = insert_confirmed_subscriber(&app, "octavia@example.com").await;
+    set_attributes(&app, ursula, "vip, beta", "").await;
+    set_attributes(&app, octavia, "beta", "").await;
+    create_segment(&app, "Beta testers", "tag = beta and tag != vip").await;
+
+    // Act
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment": "Beta testers"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    assert_eq!(queued_addresses(&app).await, vec!["octavia@example.com"]);
+}
+
+#[tokio::test]
+async fn publishing_to_an_unknown_segment_is_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    insert_confirmed_subscriber(&app, "ursula@example.com").await;
+
+    // Act
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": "Newsletter title",
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string(),
+            "segment": "does-not-exist"
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/newsletter");
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains("There is no segment called &#x27;does-not-exist&#x27;."));
+    assert!(queued_addresses(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn new_segments_can_be_picked_in_the_publish_form() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    create_segment(&app, "Germans", "country = DE").await;
+
+    // Assert
+    let html_page = app.get_newsletter_form_html().await;
+    assert!(html_page.contains(r#"<option value="Germans">Germans</option>"#));
+}
+
+#[tokio::test]
+async fn invalid_segment_expressions_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    create_segment(&app, "Germans", "country = DE or country = AT").await;
+
+    // Assert
+    let html_page = app.get("/admin/segments").await.text().await.unwrap();
+    assert!(html_page.contains("The segment expression is invalid"));
+    let n_segments = sqlx::query!(r#"SELECT count(*) AS "count!" FROM segments"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_segments, 0);
+}
+
+#[tokio::test]
+async fn tags_and_attributes_are_shown_and_validated() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+
+    // Act - Part 1
+    let response = set_attributes(&app, subscriber_id, "VIP", "signup_source=landing page").await;
+
+    // Assert - Part 1
+    assert_is_redirect_to(&response, &subscriber_page);
+    let html_page = app.get(&subscriber_page).await.text().await.unwrap();
+    assert!(html_page.contains("The tags and attributes have been saved."));
+    assert!(html_page.contains(r#"value="vip""#));
+    assert!(html_page.contains("signup_source=landing page"));
+
+    // Act - Part 2
+    set_attributes(&app, subscriber_id, "early adopter", "").await;
+
+    // Assert - Part 2
+    let html_page = app.get(&subscriber_page).await.text().await.unwrap();
+    assert!(html_page.contains("is not a valid tag"));
+    assert!(html_page.contains(r#"value="vip""#));
+}
+
+#[tokio::test]
+async fn viewers_cannot_manage_segments_or_attributes() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id = insert_confirmed_subscriber(&app, "ursula@example.com").await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let segment_response = create_segment(&app, "Germans", "country = DE").await;
+    let attributes_response = set_attributes(&app, subscriber_id, "vip", "").await;
+
+    // Assert
+    assert_eq!(segment_response.status().as_u16(), 403);
+    assert_eq!(attributes_response.status().as_u16(), 403);
+}
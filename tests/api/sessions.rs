@@ -0,0 +1,203 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use std::net::{IpAddr, Ipv4Addr};
+use uuid::Uuid;
+
+// A separate cookie store, as if the user had signed in on another device.
+struct OtherDevice {
+    client: reqwest::Client,
+    address: String,
+}
+
+impl OtherDevice {
+    async fn login(app: &TestApp, user: &TestUser) -> Self {
+        let client = reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .cookie_store(true)
+            .user_agent("OtherDevice/1.0")
+            .local_address(IpAddr::V4(Ipv4Addr::new(127, 51, 100, 7)))
+            .build()
+            .unwrap();
+        let device = Self {
+            client,
+            address: app.address.clone(),
+        };
+        let response = device
+            .client
+            .post(format!("{}/login", &device.address))
+            .form(&serde_json::json!({
+                "username": &user.username,
+                "password": &user.password
+            }))
+            .send()
+            .await
+            .expect("Failed to execute request.");
+        assert_is_redirect_to(&response, "/admin/dashboard");
+        device
+    }
+
+    async fn get_admin_dashboard(&self) -> reqwest::Response {
+        self.client
+            .get(format!("{}/admin/dashboard", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request.")
+    }
+}
+
+async fn latest_session_id(app: &TestApp, user_id: Uuid) -> Uuid {
+    sqlx::query!(
+        "SELECT session_id FROM user_sessions WHERE user_id = $1 ORDER BY created_at DESC LIMIT 1",
+        user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .session_id
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_your_sessions() {
+    let app = spawn_app().await;
+
+    let response = app.get("/admin/sessions").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_sessions_page_lists_every_active_session_with_its_device() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    OtherDevice::login(&app, &app.test_user).await;
+
+    // Act
+    let html_page = app.get("/admin/sessions").await.text().await.unwrap();
+
+    // Assert
+    assert!(html_page.contains("OtherDevice/1.0"));
+    assert!(html_page.contains("127.51.100.7"));
+    assert!(html_page.contains("This session"));
+    assert_eq!(
+        html_page
+            .matches("<button type=\"submit\">Revoke</button>")
+            .count(),
+        1
+    );
+}
+
+#[tokio::test]
+async fn revoking_a_session_logs_that_device_out() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let other_device = OtherDevice::login(&app, &app.test_user).await;
+    let session_id = latest_session_id(&app, app.test_user.user_id).await;
+
+    // Act
+    let response = app
+        .post(
+            &format!("/admin/sessions/{}/revoke", session_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The session has been revoked.</i></p>"));
+    assert!(!html_page.contains("OtherDevice/1.0"));
+    assert_is_redirect_to(&other_device.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn revoking_all_other_sessions_keeps_the_current_one() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let first_device = OtherDevice::login(&app, &app.test_user).await;
+    let second_device = OtherDevice::login(&app, &app.test_user).await;
+
+    // Act
+    let response = app
+        .post("/admin/sessions/revoke_others", &serde_json::json!({}))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/sessions");
+    let html_page = app.get("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>Revoked 2 other session(s).</i></p>"));
+    assert_eq!(app.get_admin_dashboard().await.status().as_u16(), 200);
+    assert_is_redirect_to(&first_device.get_admin_dashboard().await, "/login");
+    assert_is_redirect_to(&second_device.get_admin_dashboard().await, "/login");
+}
+
+#[tokio::test]
+async fn sessions_of_other_users_cannot_be_revoked() {
+    // Arrange
+    let app = spawn_app().await;
+    let other_user = TestUser::generate();
+    other_user.store(&app.db_pool).await;
+    let other_device = OtherDevice::login(&app, &other_user).await;
+    let session_id = latest_session_id(&app, other_user.user_id).await;
+    app.login().await;
+
+    // Act
+    app.post(
+        &format!("/admin/sessions/{}/revoke", session_id),
+        &serde_json::json!({}),
+    )
+    .await;
+
+    // Assert
+    let html_page = app.get("/admin/sessions").await.text().await.unwrap();
+    assert!(html_page.contains("The session does not exist or has already ended."));
+    assert_eq!(
+        other_device.get_admin_dashboard().await.status().as_u16(),
+        200
+    );
+}
+
+#[tokio::test]
+async fn logging_out_revokes_the_session() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let session_id = latest_session_id(&app, app.test_user.user_id).await;
+
+    // Act
+    app.logout().await;
+
+    // Assert
+    let revoked_at = sqlx::query!(
+        "SELECT revoked_at FROM user_sessions WHERE session_id = $1",
+        session_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .revoked_at;
+    assert!(revoked_at.is_some());
+}
+
+#[tokio::test]
+async fn changing_the_password_revokes_the_other_sessions() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let other_device = OtherDevice::login(&app, &app.test_user).await;
+    let new_password = Uuid::new_v4().to_string();
+
+    // Act
+    let response = app
+        .post_change_pass(&serde_json::json!({
+            "current_password": &app.test_user.password,
+            "new_password": &new_password,
+            "confirm_new_password": &new_password,
+        }))
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/login");
+    assert_is_redirect_to(&other_device.get_admin_dashboard().await, "/login");
+}
@@ -0,0 +1,384 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use chrono::Utc;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const CSV: &str = "email,name\n\
+                   ursula@example.com,Ursula Le Guin\n\
+                   octavia@example.com,Octavia Butler\n\
+                   not-an-email,Iain Banks\n\
+                   Ursula@example.com,Ursula again\n";
+
+// Uploads the file and returns the address of its dry-run report.
+async fn upload_csv(app: &TestApp, csv: &str) -> String {
+    let response = app
+        .post(
+            "/admin/subscribers/import",
+            &serde_json::json!({ "csv": csv }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 303);
+    let report_page = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    assert!(report_page.starts_with("/admin/subscribers/import/"));
+    report_page.to_string()
+}
+
+async fn run_import(
+    app: &TestApp,
+    report_page: &str,
+    body: serde_json::Value,
+) -> reqwest::Response {
+    app.post(report_page, &body).await
+}
+
+async fn subscribers(app: &TestApp) -> Vec<(String, String, Option<String>)> {
+    sqlx::query!("SELECT email, status, consent_source FROM subscriptions ORDER BY email")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(|r| (r.email, r.status, r.consent_source))
+        .collect()
+}
+
+#[tokio::test]
+async fn the_dry_run_reports_accepted_duplicate_and_rejected_rows() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'octavia@example.com', 'Octavia Butler', $2, 'unsubscribed')
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let report_page = upload_csv(&app, CSV).await;
+
+    // Assert
+    let html_page = app.get(&report_page).await.text().await.unwrap();
+    assert!(html_page.contains("Accepted: 1 - Duplicates: 2 - Rejected: 1"));
+    assert!(html_page.contains("The address is already on file (unsubscribed)."));
+    assert!(html_page.contains("The address appears earlier in the file."));
+    assert!(html_page.contains("not-an-email"));
+    assert_eq!(subscribers(&app).await.len(), 1);
+}
+
+#[tokio::test]
+async fn confirmed_imports_record_the_consent_source_and_send_no_email() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let report_page = upload_csv(&app, CSV).await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = run_import(
+        &app,
+        &report_page,
+        serde_json::json!({ "mode": "confirmed", "consent_source": "Signup form of our previous provider" }),
+    )
+    .await;
+    app.send_queued_confirmation_emails().await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get("/admin/subscribers").await.text().await.unwrap();
+    assert!(html_page.contains("2 subscribers have been imported to Newsletter."));
+    let consent = Some("Signup form of our previous provider".to_string());
+    assert_eq!(
+        subscribers(&app).await,
+        vec![
+            (
+                "octavia@example.com".into(),
+                "confirmed".into(),
+                consent.clone()
+            ),
+            ("ursula@example.com".into(), "confirmed".into(), consent),
+        ]
+    );
+    let n_members = sqlx::query!(
+        r#"SELECT count(*) AS "count!" FROM list_memberships WHERE status = 'confirmed'"#
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap()
+    .count;
+    assert_eq!(n_members, 2);
+}
+
+#[tokio::test]
+async fn confirmed_imports_need_a_consent_source() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let report_page = upload_csv(&app, CSV).await;
+
+    // Act
+    let response = run_import(
+        &app,
+        &report_page,
+        serde_json::json!({ "mode": "confirmed", "consent_source": " " }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, &report_page);
+    let html_page = app.get(&report_page).await.text().await.unwrap();
+    assert!(html_page.contains("Please record how these subscribers gave their consent"));
+    assert!(subscribers(&app).await.is_empty());
+}
+
+#[tokio::test]
+async fn pending_imports_queue_confirmation_emails_for_the_background_worker() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let report_page = upload_csv(&app, CSV).await;
+
+    // Act - Part 1 - Import, nothing is sent while handling the request
+    {
+        let _mock_guard = Mock::given(path("/email"))
+            .and(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount_as_scoped(&app.email_server)
+            .await;
+        let response =
+            run_import(&app, &report_page, serde_json::json!({ "mode": "pending" })).await;
+        assert_is_redirect_to(&response, "/admin/subscribers");
+    }
+
+    // Act - Part 2 - The worker sends the confirmation emails
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+    app.send_queued_confirmation_emails().await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let confirmation_link = app.get_confirmation_links(&email_request).html;
+    reqwest::get(confirmation_link)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let statuses: Vec<String> = subscribers(&app)
+        .await
+        .into_iter()
+        .map(|(_, status, _)| status)
+        .collect();
+    assert!(statuses.contains(&"confirmed".to_string()));
+    assert!(statuses.contains(&"pending_confirmation".to_string()));
+}
+
+#[tokio::test]
+async fn addresses_taken_since_the_dry_run_are_skipped_whatever_their_case() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let report_page = upload_csv(&app, CSV).await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, 'OCTAVIA@example.com', 'Octavia Butler', $2, 'unsubscribed')
+        "#,
+        Uuid::new_v4(),
+        Utc::now(),
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    // Act
+    let response = run_import(
+        &app,
+        &report_page,
+        serde_json::json!({ "mode": "confirmed", "consent_source": "Signup form" }),
+    )
+    .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get("/admin/subscribers").await.text().await.unwrap();
+    assert!(html_page.contains("1 subscribers have been imported to Newsletter."));
+    let statuses: Vec<_> = subscribers(&app)
+        .await
+        .into_iter()
+        .map(|(email, status, _)| (email, status))
+        .collect();
+    assert_eq!(
+        statuses,
+        vec![
+            ("OCTAVIA@example.com".into(), "unsubscribed".into()),
+            ("ursula@example.com".into(), "confirmed".into()),
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_file_is_only_imported_once() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let report_page = upload_csv(&app, CSV).await;
+    let body = serde_json::json!({ "mode": "confirmed", "consent_source": "Paper form" });
+    run_import(&app, &report_page, body.clone()).await;
+
+    // Act
+    let response = run_import(&app, &report_page, body).await;
+
+    // Assert
+    assert_is_redirect_to(&response, &report_page);
+    let html_page = app.get(&report_page).await.text().await.unwrap();
+    assert!(html_page.contains("This file has already been imported."));
+    assert_eq!(subscribers(&app).await.len(), 2);
+}
+
+#[tokio::test]
+async fn the_file_can_be_sent_as_text_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .header("Content-Type", "text/csv")
+        .body(CSV)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    let report_page = response
+        .headers()
+        .get("Location")
+        .unwrap()
+        .to_str()
+        .unwrap();
+    let html_page = app.get(report_page).await.text().await.unwrap();
+    assert!(html_page.contains("Accepted: 2 - Duplicates: 1 - Rejected: 1"));
+}
+
+#[tokio::test]
+async fn files_without_the_expected_columns_are_rejected() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let response = app
+        .post(
+            "/admin/subscribers/import",
+            &serde_json::json!({ "csv": "address\nursula@example.com\n" }),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app
+        .get("/admin/subscribers/import")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The CSV file has no `email` column."));
+}
+
+#[tokio::test]
+async fn files_over_the_size_limit_are_rejected_with_a_message() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+    let mut csv = "email,name\n".to_string();
+    while csv.len() <= 5 * 1024 * 1024 {
+        csv.push_str("reader@example.com,Reader\n");
+    }
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/admin/subscribers/import", &app.address))
+        .header("Content-Type", "text/csv")
+        .body(csv)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers/import");
+    let html_page = app
+        .get("/admin/subscribers/import")
+        .await
+        .text()
+        .await
+        .unwrap();
+    assert!(html_page.contains("The CSV file is larger than 5 MB."));
+}
+
+#[tokio::test]
+async fn other_admin_forms_keep_the_default_size_limit() {
+    // Arrange
+    let app = spawn_app().await;
+    app.login().await;
+
+    // Act
+    let response = app
+        .post(
+            "/admin/newsletter",
+            &serde_json::json!({ "text_content": "a".repeat(1024 * 1024) }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 413);
+}
+
+#[tokio::test]
+async fn viewers_cannot_import_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let response = app
+        .post(
+            "/admin/subscribers/import",
+            &serde_json::json!({ "csv": CSV }),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 403);
+}
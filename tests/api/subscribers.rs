@@ -0,0 +1,310 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp, TestUser};
+use chrono::Utc;
+use uuid::Uuid;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn insert_subscriber(app: &TestApp, email: &str, name: &str, status: &str) -> Uuid {
+    let subscriber_id = Uuid::new_v4();
+    sqlx::query!(
+        r#"
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
+        VALUES ($1, $2, $3, $4, $5)
+        "#,
+        subscriber_id,
+        email,
+        name,
+        Utc::now(),
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query!(
+        r#"
+        INSERT INTO list_memberships (list_id, subscriber_id, status)
+        SELECT list_id, $1, $2 FROM lists WHERE slug = 'newsletter'
+        "#,
+        subscriber_id,
+        status,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    subscriber_id
+}
+
+async fn publish_newsletter(app: &TestApp, title: &str) {
+    let response = app
+        .post_newsletter(&serde_json::json!({
+            "title": title,
+            "text_content": "Newsletter body as plain text",
+            "html_content": "<p>Newsletter body as HTML</p>",
+            "idempotency_key": Uuid::new_v4().to_string()
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+async fn count_queued_deliveries(app: &TestApp) -> i64 {
+    sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_see_subscribers() {
+    let app = spawn_app().await;
+
+    let response = app.get("/admin/subscribers").await;
+
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn subscribers_can_be_searched_and_filtered_by_status() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", "confirmed").await;
+    insert_subscriber(&app, "octavia@example.com", "Octavia Butler", "confirmed").await;
+    insert_subscriber(&app, "frank@example.com", "Frank Herbert", "unsubscribed").await;
+    app.login().await;
+
+    // Act
+    let by_name = app
+        .get("/admin/subscribers?search=le%20GUIN")
+        .await
+        .text()
+        .await
+        .unwrap();
+    let by_status = app
+        .get("/admin/subscribers?status=confirmed")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(by_name.contains("ursula@example.com"));
+    assert!(!by_name.contains("octavia@example.com"));
+    assert!(by_status.contains("ursula@example.com"));
+    assert!(by_status.contains("octavia@example.com"));
+    assert!(!by_status.contains("frank@example.com"));
+    assert!(by_status.contains("2 subscriber(s) match."));
+}
+
+#[tokio::test]
+async fn the_subscriber_list_is_paginated() {
+    // Arrange
+    let app = spawn_app().await;
+    for i in 0..51 {
+        let email = format!("reader{}@example.com", i);
+        insert_subscriber(&app, &email, "Reader", "confirmed").await;
+    }
+    app.login().await;
+
+    // Act
+    let first_page = app.get("/admin/subscribers").await.text().await.unwrap();
+    let second_page = app
+        .get("/admin/subscribers?page=2")
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(first_page.contains("Page 1 of 2"));
+    assert!(first_page
+        .contains(r#"<a href="/admin/subscribers?search=&amp;status=&amp;page=2">Next</a>"#));
+    assert_eq!(first_page.matches("@example.com</a>").count(), 50);
+    assert!(second_page.contains("Page 2 of 2"));
+    assert_eq!(second_page.matches("@example.com</a>").count(), 1);
+}
+
+#[tokio::test]
+async fn pages_past_the_end_show_the_last_page() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Ursula", "confirmed").await;
+    app.login().await;
+
+    // Act
+    let response = app.get("/admin/subscribers?page=9223372036854775807").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let html_page = response.text().await.unwrap();
+    assert!(html_page.contains("Page 1 of 1"));
+    assert!(html_page.contains("ursula@example.com</a>"));
+}
+
+#[tokio::test]
+async fn the_subscriber_page_shows_the_delivery_history() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", "confirmed").await;
+    app.login().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    publish_newsletter(&app, "Issue #1").await;
+    app.dispatch_all_pending_emails().await;
+
+    // Act
+    let html_page = app
+        .get(&format!("/admin/subscribers/{}", subscriber_id))
+        .await
+        .text()
+        .await
+        .unwrap();
+
+    // Assert
+    assert!(html_page.contains("Ursula Le Guin"));
+    assert!(html_page.contains("Issue #1"));
+    assert!(html_page.contains("<td>delivered</td>"));
+}
+
+#[tokio::test]
+async fn unsubscribing_a_subscriber_skips_their_queued_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", "confirmed").await;
+    app.login().await;
+    publish_newsletter(&app, "Issue #1").await;
+
+    // Act
+    let response = app
+        .post(
+            &format!("/admin/subscribers/{}/unsubscribe", subscriber_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    let subscriber_page = format!("/admin/subscribers/{}", subscriber_id);
+    assert_is_redirect_to(&response, &subscriber_page);
+    let html_page = app.get(&subscriber_page).await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The subscriber has been unsubscribed.</i></p>"));
+    assert!(html_page.contains("<td>unsubscribed</td>"));
+    assert!(html_page.contains("<td>skipped</td>"));
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+}
+
+#[tokio::test]
+async fn deleting_a_subscriber_removes_their_pending_deliveries() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber_id = sqlx::query!("SELECT id FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .id;
+    app.login().await;
+    publish_newsletter(&app, "Issue #1").await;
+
+    // Act
+    let response = app
+        .post(
+            &format!("/admin/subscribers/{}/delete", subscriber_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_is_redirect_to(&response, "/admin/subscribers");
+    let html_page = app.get("/admin/subscribers").await.text().await.unwrap();
+    assert!(html_page.contains("<p><i>The subscriber has been deleted.</i></p>"));
+    assert!(html_page.contains("0 subscriber(s) match."));
+    assert_eq!(count_queued_deliveries(&app).await, 0);
+    let response = app
+        .get(&format!("/admin/subscribers/{}", subscriber_id))
+        .await;
+    assert_eq!(response.status().as_u16(), 404);
+}
+
+#[tokio::test]
+async fn the_export_contains_the_filtered_subscribers_as_csv() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(&app, "ursula@example.com", "Le Guin, Ursula", "confirmed").await;
+    insert_subscriber(&app, "frank@example.com", "Frank Herbert", "unsubscribed").await;
+    app.login().await;
+
+    // Act
+    let response = app.get("/admin/subscribers/export?status=confirmed").await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    assert_eq!(
+        response.headers()["Content-Type"],
+        "text/csv; charset=utf-8"
+    );
+    let csv = response.text().await.unwrap();
+    let mut lines = csv.lines();
+    assert_eq!(lines.next(), Some("email,name,status,subscribed_at"));
+    assert!(lines
+        .next()
+        .unwrap()
+        .starts_with("ursula@example.com,\"Le Guin, Ursula\",confirmed,"));
+    assert_eq!(lines.next(), None);
+}
+
+#[tokio::test]
+async fn the_export_neutralises_cells_that_look_like_formulas() {
+    // Arrange
+    let app = spawn_app().await;
+    insert_subscriber(
+        &app,
+        "ursula@example.com",
+        "=HYPERLINK(\"http://example.com\")",
+        "confirmed",
+    )
+    .await;
+    app.login().await;
+
+    // Act
+    let response = app.get("/admin/subscribers/export").await;
+
+    // Assert
+    let csv = response.text().await.unwrap();
+    assert!(csv
+        .lines()
+        .nth(1)
+        .unwrap()
+        .starts_with(r#"ursula@example.com,"'=HYPERLINK(""http://example.com"")",confirmed,"#));
+}
+
+#[tokio::test]
+async fn viewers_cannot_export_or_delete_subscribers() {
+    // Arrange
+    let app = spawn_app().await;
+    let subscriber_id =
+        insert_subscriber(&app, "ursula@example.com", "Ursula Le Guin", "confirmed").await;
+    let viewer = TestUser::with_role("viewer");
+    viewer.store(&app.db_pool).await;
+    app.login_as(&viewer).await;
+
+    // Act
+    let export = app.get("/admin/subscribers/export").await;
+    let delete = app
+        .post(
+            &format!("/admin/subscribers/{}/delete", subscriber_id),
+            &serde_json::json!({}),
+        )
+        .await;
+
+    // Assert
+    assert_eq!(export.status().as_u16(), 403);
+    assert_eq!(delete.status().as_u16(), 403);
+    let html_page = app.get("/admin/subscribers").await.text().await.unwrap();
+    assert!(html_page.contains("ursula@example.com"));
+    assert!(!html_page.contains("Export as CSV"));
+}
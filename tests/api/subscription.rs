@@ -1,4 +1,5 @@
 use crate::helper::spawn_app;
+use uuid::Uuid;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -181,12 +182,13 @@ async fn new_confirmation_email_is_sent_when_user_subscribes_again() {
         .mount(&app.email_server)
         .await;
 
-    let body = "name=sathwik%20matsa&email=sathwikmatsa%40gmail.com";
-    let _response = app.post_subscriptions(body.into()).await;
+    // Subscribing again is subject to a cooldown per address, which outlives the test.
+    let body = format!("name=sathwik%20matsa&email={}%40gmail.com", Uuid::new_v4());
+    let _response = app.post_subscriptions(body.clone()).await;
     let _first_email_request = &app.email_server.received_requests().await.unwrap()[0];
 
     // user tries to subscribe again, ignoring previous confirmation email
-    let _response = app.post_subscriptions(body.into()).await;
+    let _response = app.post_subscriptions(body).await;
     let _second_email_request = &app.email_server.received_requests().await.unwrap()[1];
 
     // Assert new email is sent to the user.
@@ -207,3 +209,146 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_accepts_a_json_body_and_answers_with_json() {
+    // Arrange
+    let app = spawn_app().await;
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": "le guin",
+            "email": "ursula_le_guin@gmail.com",
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 200);
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "pending_confirmation");
+    let saved = sqlx::query!("SELECT email, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "ursula_le_guin@gmail.com");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn invalid_json_subscriptions_get_an_error_naming_the_field() {
+    // Arrange
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            serde_json::json!({ "name": "le guin", "email": "abc.com" }),
+            "email",
+            "abc.com is not a valid subscriber email.",
+        ),
+        (
+            serde_json::json!({ "name": "", "email": "ursula_le_guin@gmail.com" }),
+            "name",
+            " is not a valid subscriber name.",
+        ),
+        (
+            serde_json::json!({ "email": "ursula_le_guin@gmail.com" }),
+            "name",
+            "The name is missing.",
+        ),
+        (
+            serde_json::json!({ "name": "le guin" }),
+            "email",
+            "The email address is missing.",
+        ),
+    ];
+
+    for (body, field, message) in test_cases {
+        // Act
+        let response = app.post_subscriptions_json(&body).await;
+
+        // Assert
+        assert_eq!(response.status().as_u16(), 400);
+        let error: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(error["error"]["code"], "validation");
+        assert_eq!(error["error"]["field"], field);
+        assert_eq!(error["error"]["message"], message);
+    }
+}
+
+#[tokio::test]
+async fn a_malformed_json_body_is_rejected_with_a_json_error() {
+    // Arrange
+    let app = spawn_app().await;
+
+    // Act
+    let response = app
+        .api_client
+        .post(format!("{}/subscriptions", &app.address))
+        .header("Content-Type", "application/json")
+        .body("{\"name\": ")
+        .send()
+        .await
+        .expect("Failed to execute request.");
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "invalid_body");
+    assert!(error["error"].get("field").is_none());
+}
+
+#[tokio::test]
+async fn json_clients_are_told_when_the_subscriber_is_already_confirmed() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = app
+        .post_subscriptions_json(&serde_json::json!({
+            "name": subscriber.name,
+            "email": subscriber.email,
+        }))
+        .await;
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let error: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["code"], "already_confirmed");
+}
+
+#[tokio::test]
+async fn signing_up_again_does_not_rename_a_confirmed_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let subscriber = sqlx::query!("SELECT name, email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let body = serde_urlencoded::to_string(serde_json::json!({
+        "name": "Someone Else",
+        "email": subscriber.email,
+    }))
+    .unwrap();
+    app.post_subscriptions(body).await;
+
+    // Assert
+    let saved = sqlx::query!("SELECT name FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.name, subscriber.name);
+}
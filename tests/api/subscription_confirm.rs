@@ -1,6 +1,7 @@
 use crate::helper::spawn_app;
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
+use zero2prod::subscription_token_sweeper::sweep_subscription_tokens;
 
 #[tokio::test]
 async fn confirmations_without_token_are_rejected_with_a_400() {
@@ -102,6 +103,31 @@ async fn clicking_on_the_confirmation_link_confirms_a_subscriber() {
     assert_eq!(saved.status, "confirmed");
 }
 
+#[tokio::test]
+async fn an_old_link_does_not_bring_back_a_bounced_subscriber() {
+    // Arrange
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "bounced");
+}
+
 #[tokio::test]
 async fn confirmed_subscribers_on_retrying_subscription_are_rejected_with_400() {
     // Arrange
@@ -126,3 +152,154 @@ async fn confirmed_subscribers_on_retrying_subscription_are_rejected_with_400()
 
     assert_eq!(response.status().as_u16(), 400);
 }
+
+#[tokio::test]
+async fn confirmation_links_can_only_be_used_once() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+
+    let first_response = reqwest::get(confirmation_links.html.clone()).await.unwrap();
+    let second_response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(first_response.status().as_u16(), 200);
+    assert_eq!(second_response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn expired_confirmation_links_are_rejected_with_a_410() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 410);
+    assert!(response
+        .text()
+        .await
+        .unwrap()
+        .contains("action=\"/subscriptions/confirm/resend\""));
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn a_new_confirmation_link_can_be_requested_for_an_expired_link() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let expired_token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post(
+            "/subscriptions/confirm/resend",
+            &serde_json::json!({ "subscription_token": expired_token }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let new_links = app.get_confirmation_links(&email_request);
+    assert_ne!(new_links.html, confirmation_links.html);
+    reqwest::get(new_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn new_links_for_an_expired_link_share_the_address_cooldown() {
+    let app = spawn_app().await;
+    let confirmation_links = app.create_unconfirmed_subscriber().await;
+    sqlx::query!("UPDATE subscription_tokens SET expires_at = now() - interval '1 minute'",)
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    let expired_token = confirmation_links
+        .html
+        .query_pairs()
+        .find(|(k, _)| k == "subscription_token")
+        .unwrap()
+        .1
+        .to_string();
+    Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    for _ in 0..2 {
+        let response = app
+            .post(
+                "/subscriptions/confirm/resend",
+                &serde_json::json!({ "subscription_token": &expired_token }),
+            )
+            .await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+}
+
+#[tokio::test]
+async fn sweeper_deletes_used_and_long_expired_tokens() {
+    let app = spawn_app().await;
+    let used_link = app.create_unconfirmed_subscriber().await;
+    reqwest::get(used_link.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    app.create_unconfirmed_subscriber().await;
+    sqlx::query!(
+        "UPDATE subscription_tokens
+        SET expires_at = now() - interval '8 days'
+        WHERE used_at IS NULL",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    app.create_unconfirmed_subscriber().await;
+
+    let n_deleted_tokens = sweep_subscription_tokens(&app.db_pool).await.unwrap();
+
+    assert_eq!(n_deleted_tokens, 2);
+    let remaining = sqlx::query!("SELECT used_at, expires_at FROM subscription_tokens",)
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+    assert!(remaining[0].used_at.is_none());
+}
@@ -0,0 +1,258 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use totp_rs::{Algorithm, Secret, TOTP};
+
+fn code_at(secret: &str, time: u64) -> String {
+    let secret = Secret::Encoded(secret.to_string()).to_bytes().unwrap();
+    TOTP::new(Algorithm::SHA1, 6, 0, 30, secret, None, "".into())
+        .unwrap()
+        .generate(time)
+}
+
+fn now() -> u64 {
+    chrono::Utc::now().timestamp() as u64
+}
+
+fn extract_between<'a>(html: &'a str, start: &str, end: &str) -> Vec<&'a str> {
+    html.split(start)
+        .skip(1)
+        .map(|s| s.split(end).next().unwrap())
+        .collect()
+}
+
+// Enrolls the logged in test user and returns the TOTP secret along with the recovery codes.
+async fn enable_two_factor(app: &TestApp) -> (String, Vec<String>) {
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    let secret = extract_between(&html, r#"<code id="totp-secret">"#, "</code>")[0].to_string();
+
+    let response = app
+        .post(
+            "/admin/two_factor",
+            &serde_json::json!({ "code": code_at(&secret, now()) }),
+        )
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+    let html = response.text().await.unwrap();
+    let recovery_codes = extract_between(&html, "<li><code>", "</code></li>")
+        .into_iter()
+        .map(String::from)
+        .collect();
+    (secret, recovery_codes)
+}
+
+async fn login_with_password(app: &TestApp) {
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+async fn post_code(app: &TestApp, code: &str) -> reqwest::Response {
+    app.post("/login/two_factor", &serde_json::json!({ "code": code }))
+        .await
+}
+
+#[tokio::test]
+async fn you_must_be_logged_in_to_manage_two_factor_authentication() {
+    let app = spawn_app().await;
+
+    let response = app.get("/admin/two_factor").await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = app
+        .post(
+            "/admin/two_factor",
+            &serde_json::json!({ "code": "123456" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn the_set_up_page_shows_a_qr_code_for_the_provisioning_uri() {
+    let app = spawn_app().await;
+    app.login().await;
+
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+
+    assert!(html.contains("<svg"));
+    let secret = extract_between(&html, r#"<code id="totp-secret">"#, "</code>")[0];
+    assert!(html.contains(&format!(
+        "otpauth://totp/Newsletter:{}?secret={}",
+        app.test_user.username, secret
+    )));
+    // The same secret is offered until the set up is completed
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    assert!(html.contains(secret));
+}
+
+#[tokio::test]
+async fn a_wrong_code_does_not_enable_two_factor_authentication() {
+    let app = spawn_app().await;
+    app.login().await;
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    let secret = extract_between(&html, r#"<code id="totp-secret">"#, "</code>")[0].to_string();
+    let wrong_code = code_at(&secret, now() - 300);
+
+    let response = app
+        .post(
+            "/admin/two_factor",
+            &serde_json::json!({ "code": wrong_code }),
+        )
+        .await;
+
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    assert!(html.contains("The code is invalid. Check the clock of your device and try again."));
+    let saved = sqlx::query!(
+        "SELECT totp_secret FROM users WHERE user_id = $1",
+        app.test_user.user_id
+    )
+    .fetch_one(&app.db_pool)
+    .await
+    .unwrap();
+    assert!(saved.totp_secret.is_none());
+}
+
+#[tokio::test]
+async fn login_asks_for_a_code_once_two_factor_authentication_is_enabled() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, recovery_codes) = enable_two_factor(&app).await;
+    assert_eq!(recovery_codes.len(), 10);
+    app.logout().await;
+
+    login_with_password(&app).await;
+    // The password alone does not give access to the admin area
+    let response = app.get_admin_dashboard().await;
+    assert_is_redirect_to(&response, "/login");
+
+    let response = post_code(&app, &code_at(&secret, now() + 30)).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let response = app.get_admin_dashboard().await;
+    assert_eq!(response.status().as_u16(), 200);
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.logout().await;
+    let code = code_at(&secret, now() + 30);
+
+    login_with_password(&app).await;
+    let response = post_code(&app, &code).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    app.logout().await;
+
+    login_with_password(&app).await;
+    let response = post_code(&app, &code).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+    let html = app.get("/login/two_factor").await.text().await.unwrap();
+    assert!(html.contains("The authentication code is invalid."));
+}
+
+#[tokio::test]
+async fn each_recovery_code_can_be_used_once_to_login() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (_, recovery_codes) = enable_two_factor(&app).await;
+    app.logout().await;
+
+    login_with_password(&app).await;
+    let response = post_code(&app, &recovery_codes[0].to_uppercase()).await;
+    assert_is_redirect_to(&response, "/admin/dashboard");
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    assert!(html.contains("You have 9 unused recovery codes left."));
+    app.logout().await;
+
+    login_with_password(&app).await;
+    let response = post_code(&app, &recovery_codes[0]).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+}
+
+#[tokio::test]
+async fn too_many_wrong_codes_restart_the_login() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.logout().await;
+    let wrong_code = code_at(&secret, now() - 300);
+
+    login_with_password(&app).await;
+    for _ in 0..4 {
+        let response = post_code(&app, &wrong_code).await;
+        assert_is_redirect_to(&response, "/login/two_factor");
+    }
+    let response = post_code(&app, &wrong_code).await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Too many invalid codes. Please login again."));
+
+    // Even a valid code is refused until the password has been entered again
+    let response = post_code(&app, &code_at(&secret, now() + 30)).await;
+    assert_is_redirect_to(&response, "/login");
+}
+
+#[tokio::test]
+async fn wrong_codes_count_towards_the_login_throttle() {
+    let app = spawn_app().await;
+    app.login().await;
+    let (secret, _) = enable_two_factor(&app).await;
+    app.logout().await;
+    for _ in 0..3 {
+        app.post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": "wrong password",
+        }))
+        .await;
+    }
+
+    // The right password does not forgive the earlier failures on its own
+    login_with_password(&app).await;
+    let response = post_code(&app, &code_at(&secret, now() - 300)).await;
+    assert_is_redirect_to(&response, "/login/two_factor");
+
+    let response = app
+        .post_login(&serde_json::json!({
+            "username": &app.test_user.username,
+            "password": &app.test_user.password,
+        }))
+        .await;
+    assert_is_redirect_to(&response, "/login");
+    let html = app.get_login_html().await;
+    assert!(html.contains("Too many failed login attempts."));
+}
+
+#[tokio::test]
+async fn two_factor_authentication_can_be_turned_off_with_the_password() {
+    let app = spawn_app().await;
+    app.login().await;
+    enable_two_factor(&app).await;
+
+    let response = app
+        .post(
+            "/admin/two_factor/disable",
+            &serde_json::json!({ "current_password": "wrong-password" }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    assert!(html.contains("Current password is incorrect."));
+
+    let response = app
+        .post(
+            "/admin/two_factor/disable",
+            &serde_json::json!({ "current_password": &app.test_user.password }),
+        )
+        .await;
+    assert_is_redirect_to(&response, "/admin/two_factor");
+    let html = app.get("/admin/two_factor").await.text().await.unwrap();
+    assert!(html.contains("Two-factor authentication has been turned off."));
+
+    app.logout().await;
+    app.login().await;
+}
@@ -0,0 +1,222 @@
+use crate::helper::{spawn_app, TestApp};
+use wiremock::matchers::{any, method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn publish_and_deliver_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    app.post_newsletter(&newsletter_request_body).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+async fn get_unsubscribe_link_from_newsletter(app: &TestApp) -> reqwest::Url {
+    let _mock_guard = Mock::given(path("/email"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount_as_scoped(&app.email_server)
+        .await;
+    publish_and_deliver_newsletter(app).await;
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    app.get_unsubscribe_link(&email_request)
+}
+
+#[tokio::test]
+async fn newsletter_issues_carry_list_unsubscribe_headers() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+
+    let email_request = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .pop()
+        .unwrap();
+    let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+    assert!(body["Headers"]
+        .as_array()
+        .unwrap()
+        .contains(&serde_json::json!({
+            "Name": "List-Unsubscribe-Post",
+            "Value": "List-Unsubscribe=One-Click"
+        })));
+    assert_eq!(unsubscribe_link.path(), "/subscriptions/unsubscribe");
+    assert!(body["TextBody"].as_str().unwrap().contains("Unsubscribe"));
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_marks_the_subscriber_as_unsubscribed() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+
+    let response = app
+        .api_client
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "unsubscribed");
+}
+
+#[tokio::test]
+async fn one_click_unsubscribe_keeps_a_bounced_status() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+    sqlx::query!("UPDATE subscriptions SET status = 'bounced'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app
+        .api_client
+        .post(unsubscribe_link)
+        .header("Content-Type", "application/x-www-form-urlencoded")
+        .body("List-Unsubscribe=One-Click")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "bounced");
+}
+
+#[tokio::test]
+async fn visiting_the_unsubscribe_link_does_not_unsubscribe() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+
+    let response = reqwest::get(unsubscribe_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    assert!(response.text().await.unwrap().contains("<form"));
+    let saved = sqlx::query!("SELECT status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn queued_deliveries_are_dropped_when_the_subscriber_unsubscribes() {
+    // Arrange
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+    app.post_newsletter(&serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    }))
+    .await;
+
+    // Act
+    app.api_client
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    // Assert
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    app.dispatch_all_pending_emails().await;
+    let n_queued = sqlx::query!(r#"SELECT count(*) AS "count!" FROM issue_delivery_queue"#)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .count;
+    assert_eq!(n_queued, 0);
+}
+
+#[tokio::test]
+async fn unsubscribed_subscribers_do_not_receive_newsletters() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+    app.api_client
+        .post(unsubscribe_link)
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+    publish_and_deliver_newsletter(&app).await;
+}
+
+#[tokio::test]
+async fn unsubscribe_with_a_tampered_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let mut unsubscribe_link = get_unsubscribe_link_from_newsletter(&app).await;
+    let token = unsubscribe_link.query_pairs().next().unwrap().1.to_string();
+    let (_, tag) = token.split_once('.').unwrap();
+    unsubscribe_link.set_query(Some(&format!("token={}.{}", uuid::Uuid::new_v4(), tag)));
+
+    let response = app.api_client.post(unsubscribe_link).send().await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn unsubscribe_with_an_ill_formatted_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = app
+        .api_client
+        .post(format!(
+            "{}/subscriptions/unsubscribe?token=not-a-token",
+            app.address
+        ))
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status().as_u16(), 400);
+}
@@ -0,0 +1,169 @@
+use crate::helper::{assert_is_redirect_to, spawn_app, TestApp};
+use secrecy::ExposeSecret;
+use wiremock::matchers::any;
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscriber(app: &TestApp) -> (String, String) {
+    let row = sqlx::query!("SELECT email, status FROM subscriptions",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    (row.email, row.status)
+}
+
+fn hard_bounce(email: &str) -> serde_json::Value {
+    serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "HardBounce",
+        "TypeCode": 1,
+        "Email": email,
+        "Description": "The server was unable to deliver your message (ex: unknown user, mailbox not found).",
+    })
+}
+
+async fn publish_newsletter(app: &TestApp) {
+    let newsletter_request_body = serde_json::json!({
+        "title": "Newsletter title",
+        "text_content": "Newsletter body as plain text",
+        "html_content": "<p>Newsletter body as HTML</p>",
+        "idempotency_key": uuid::Uuid::new_v4().to_string()
+    });
+    let response = app.post_newsletter(&newsletter_request_body).await;
+    assert_is_redirect_to(&response, "/admin/newsletter");
+}
+
+#[tokio::test]
+async fn webhooks_without_the_shared_secret_are_rejected() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (email, _) = subscriber(&app).await;
+
+    for secret in [None, Some("wrong-secret")] {
+        let response = app
+            .post_postmark_webhook(&hard_bounce(&email), secret)
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    let (_, status) = subscriber(&app).await;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_hard_bounce_suppresses_the_subscriber() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (email, _) = subscriber(&app).await;
+    let secret = app.postmark_webhook_secret.expose_secret();
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce(&email), Some(secret))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (_, status) = subscriber(&app).await;
+    assert_eq!(status, "bounced");
+    let event = sqlx::query!("SELECT email, record_type, event_type FROM email_feedback_events",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(event.email, email);
+    assert_eq!(event.record_type, "Bounce");
+    assert_eq!(event.event_type, "HardBounce");
+}
+
+#[tokio::test]
+async fn a_spam_complaint_suppresses_the_subscriber() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (email, _) = subscriber(&app).await;
+    let body = serde_json::json!({
+        "RecordType": "SpamComplaint",
+        "Type": "SpamComplaint",
+        "TypeCode": 512,
+        "Email": email,
+    });
+
+    let response = app
+        .post_postmark_webhook(&body, Some(app.postmark_webhook_secret.expose_secret()))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (_, status) = subscriber(&app).await;
+    assert_eq!(status, "complained");
+}
+
+#[tokio::test]
+async fn soft_bounces_and_other_events_are_ignored() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (email, _) = subscriber(&app).await;
+    let secret = app.postmark_webhook_secret.expose_secret();
+
+    let soft_bounce = serde_json::json!({
+        "RecordType": "Bounce",
+        "Type": "SoftBounce",
+        "TypeCode": 4096,
+        "Email": email,
+    });
+    let delivery = serde_json::json!({
+        "RecordType": "Delivery",
+        "Recipient": email,
+    });
+    for body in [soft_bounce, delivery] {
+        let response = app.post_postmark_webhook(&body, Some(secret)).await;
+        assert_eq!(response.status().as_u16(), 200);
+    }
+
+    let (_, status) = subscriber(&app).await;
+    assert_eq!(status, "confirmed");
+}
+
+#[tokio::test]
+async fn suppressed_subscribers_do_not_receive_issues() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    app.login().await;
+    let (email, _) = subscriber(&app).await;
+
+    Mock::given(any())
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    // A delivery already queued when the bounce comes in is dropped...
+    publish_newsletter(&app).await;
+    app.post_postmark_webhook(
+        &hard_bounce(&email),
+        Some(app.postmark_webhook_secret.expose_secret()),
+    )
+    .await;
+    app.dispatch_all_pending_emails().await;
+    let outcome = sqlx::query!("SELECT outcome FROM issue_delivery_outcomes",)
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap()
+        .outcome;
+    assert_eq!(outcome, "skipped");
+
+    // ...and later issues are not enqueued at all.
+    publish_newsletter(&app).await;
+    app.dispatch_all_pending_emails().await;
+}
+
+#[tokio::test]
+async fn bounces_are_matched_to_the_subscriber_ignoring_case() {
+    let app = spawn_app().await;
+    app.create_confirmed_subscriber().await;
+    let (email, _) = subscriber(&app).await;
+    let secret = app.postmark_webhook_secret.expose_secret();
+
+    let response = app
+        .post_postmark_webhook(&hard_bounce(&email.to_uppercase()), Some(secret))
+        .await;
+    assert_eq!(response.status().as_u16(), 200);
+
+    let (_, status) = subscriber(&app).await;
+    assert_eq!(status, "bounced");
+}